@@ -0,0 +1,450 @@
+// Copyright (C) 2026 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+use std::io::Error as IoError;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tempfile::NamedTempFile;
+
+use tokio::fs::create_dir_all;
+use tokio::fs::metadata;
+use tokio::fs::remove_file;
+use tokio::fs::File;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt as _;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt as _;
+use tokio::task::spawn_blocking;
+use tokio::time::sleep;
+
+use crate::caching_client::cache_dir_from_env;
+use crate::caching_client::cache_error;
+use crate::caching_client::cache_state;
+use crate::caching_client::entry_path;
+use crate::caching_client::ignore_already_exists;
+use crate::caching_client::ignore_not_found;
+use crate::caching_client::persist;
+use crate::caching_client::CacheConfig;
+use crate::caching_client::CacheState;
+use crate::caching_client::COPY_BUFFER_SIZE;
+use crate::client::Artifact;
+use crate::error::Result;
+use crate::eviction::Eviction;
+use crate::lock::lock_path;
+use crate::lock::refresh_lock;
+use crate::lock::LockFile;
+use crate::lock::LOCK_POLL_INTERVAL;
+use crate::log::debug;
+use crate::log::warn;
+use crate::AsyncClient;
+use crate::BuildId;
+use crate::Error;
+
+
+/// Copy all data from `reader`, the body of a response from `url`, to
+/// `writer`, which is destined to end up at `path` in the cache. `lock`
+/// is refreshed as the copy progresses.
+async fn copy<R, W>(
+  reader: &mut R,
+  writer: &mut W,
+  url: &str,
+  path: &Path,
+  lock: &mut LockFile,
+) -> Result<()>
+where
+  R: AsyncRead + Unpin,
+  W: AsyncWrite + Unpin,
+{
+  let mut buffer = vec![0; COPY_BUFFER_SIZE];
+  loop {
+    if let Some(lock_path) = lock.refresh_due() {
+      let lock_path = lock_path.to_path_buf();
+      let () = spawn_blocking(move || refresh_lock(&lock_path))
+        .await
+        .map_err(|err| cache_error(path, IoError::from(err)))??;
+    }
+
+    let count = match reader.read(&mut buffer).await {
+      Ok(0) => break,
+      Ok(count) => count,
+      Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+      Err(err) => return Err(Error::from_read(err, url)),
+    };
+    let () = writer
+      .write_all(&buffer[..count])
+      .await
+      .map_err(|err| cache_error(path, err))?;
+  }
+
+  writer.flush().await.map_err(|err| cache_error(path, err))
+}
+
+
+/// Record in the cache that the data at `path` is not available.
+async fn record_miss(path: &Path) -> Result<()> {
+  // SANITY: Our path is guaranteed to always have a parent.
+  let dir = path.parent().unwrap();
+  let () = create_dir_all(dir)
+    .await
+    .map_err(|err| cache_error(dir, err))?;
+
+  let () = OpenOptions::new()
+    .write(true)
+    .create_new(true)
+    .open(path)
+    .await
+    .map(|_file| ())
+    .or_else(ignore_already_exists)
+    .map_err(|err| cache_error(path, err))?;
+  Ok(())
+}
+
+
+/// An asynchronous debuginfod client that caches data using the file
+/// system.
+///
+/// This type is the asynchronous counterpart to
+/// [`CachingClient`][crate::CachingClient] and shares its cache layout.
+#[derive(Debug)]
+pub struct AsyncCachingClient {
+  /// The debuginfod client we use for satisfying requests.
+  client: AsyncClient,
+  /// The root directory of the cache.
+  cache_dir: PathBuf,
+  /// The configuration of the cache.
+  config: CacheConfig,
+}
+
+impl AsyncCachingClient {
+  /// Create a new [`AsyncCachingClient`] using `cache_dir` as the
+  /// directory at which fetched debug info files are cached on the file
+  /// system.
+  ///
+  /// See [`CachingClient::new`][crate::CachingClient::new].
+  pub async fn new<P>(client: AsyncClient, cache_dir: P) -> Result<Self>
+  where
+    P: AsRef<Path>,
+  {
+    let cache_dir = cache_dir.as_ref();
+    let () = create_dir_all(cache_dir)
+      .await
+      .map_err(|err| cache_error(cache_dir, err))?;
+
+    let dir = cache_dir.to_path_buf();
+    let config = spawn_blocking(move || CacheConfig::read(&dir))
+      .await
+      .map_err(|err| cache_error(cache_dir, IoError::from(err)))??;
+
+    let slf = Self {
+      client,
+      cache_dir: cache_dir.to_path_buf(),
+      config,
+    };
+    Ok(slf)
+  }
+
+  /// Create a new [`AsyncCachingClient`] using the cache directory as
+  /// configured in the environment.
+  ///
+  /// See [`CachingClient::from_env`][crate::CachingClient::from_env].
+  pub async fn from_env(client: AsyncClient) -> Result<Self> {
+    Self::new(client, cache_dir_from_env()?).await
+  }
+
+  /// Set the time for which the absence of data on all servers is
+  /// remembered.
+  ///
+  /// See
+  /// [`CachingClient::with_cache_miss_ttl`][crate::CachingClient::with_cache_miss_ttl].
+  pub fn with_cache_miss_ttl(mut self, ttl: Duration) -> Self {
+    self.config.cache_miss = ttl;
+    self
+  }
+
+  /// Set the interval at which the cache is cleaned up.
+  ///
+  /// See
+  /// [`CachingClient::with_clean_interval`][crate::CachingClient::with_clean_interval].
+  pub fn with_clean_interval(mut self, interval: Duration) -> Self {
+    self.config.eviction.interval = interval;
+    self
+  }
+
+  /// Set the time after which cache entries that have not been used
+  /// are removed during a cleanup.
+  ///
+  /// See
+  /// [`CachingClient::with_max_unused_age`][crate::CachingClient::with_max_unused_age].
+  pub fn with_max_unused_age(mut self, age: Option<Duration>) -> Self {
+    self.config.eviction.max_unused_age = age;
+    self
+  }
+
+  /// Set the maximum total size of all cache entries, in bytes.
+  ///
+  /// See [`CachingClient::with_max_size`][crate::CachingClient::with_max_size].
+  pub fn with_max_size(mut self, size: Option<u64>) -> Self {
+    self.config.eviction.max_size = size;
+    self
+  }
+
+  /// Run `f` for cleaning up the cache on the blocking thread pool.
+  async fn run_eviction<F>(&self, f: F) -> Result<()>
+  where
+    F: FnOnce(&Eviction, &Path) -> Result<()> + Send + 'static,
+  {
+    let eviction = self.config.eviction;
+    let cache_dir = self.cache_dir.clone();
+    spawn_blocking(move || f(&eviction, &cache_dir))
+      .await
+      .map_err(|err| cache_error(&self.cache_dir, IoError::from(err)))?
+  }
+
+  /// Clean up the cache right away, irrespective of the time passed
+  /// since the last cleanup.
+  ///
+  /// See [`CachingClient::clean`][crate::CachingClient::clean].
+  pub async fn clean(&self) -> Result<()> {
+    self.run_eviction(Eviction::clean).await
+  }
+
+  /// Fetch the debug info for the given build ID.
+  pub async fn fetch_debug_info(&self, build_id: &BuildId<'_>) -> Result<Option<PathBuf>> {
+    self.fetch(build_id, Artifact::DebugInfo).await
+  }
+
+  /// Fetch the executable for the given build ID.
+  pub async fn fetch_executable(&self, build_id: &BuildId<'_>) -> Result<Option<PathBuf>> {
+    self.fetch(build_id, Artifact::Executable).await
+  }
+
+  /// Fetch the source file at `path` for the given build ID.
+  ///
+  /// See [`Client::fetch_source`][crate::Client::fetch_source].
+  pub async fn fetch_source(&self, build_id: &BuildId<'_>, path: &str) -> Result<Option<PathBuf>> {
+    self.fetch(build_id, Artifact::Source(path)).await
+  }
+
+  /// Fetch the ELF section with the given name for the given build ID.
+  ///
+  /// See [`Client::fetch_section`][crate::Client::fetch_section].
+  pub async fn fetch_section(
+    &self,
+    build_id: &BuildId<'_>,
+    section: &str,
+  ) -> Result<Option<PathBuf>> {
+    self.fetch(build_id, Artifact::Section(section)).await
+  }
+
+  async fn fetch(&self, build_id: &BuildId<'_>, artifact: Artifact<'_>) -> Result<Option<PathBuf>> {
+    if let Err(err) = self.run_eviction(Eviction::clean_if_due).await {
+      warn!("failed to clean up cache: {err}");
+    }
+
+    let path = entry_path(&self.cache_dir, build_id, &artifact)?;
+    let lock_path = lock_path(&path);
+    // Only a single process fetches the data for a build ID at any
+    // time, while others wait for the result to show up in the cache.
+    let mut lock = None;
+    loop {
+      match cache_state(&path, metadata(&path).await, self.config.cache_miss)? {
+        CacheState::Present => {
+          debug!("cache hit on `{}`", path.display());
+          return Ok(Some(path))
+        },
+        CacheState::Missing => {
+          debug!("cached miss on `{}`", path.display());
+          return Ok(None)
+        },
+        CacheState::Expired if lock.is_some() => {
+          let () = remove_file(&path)
+            .await
+            .or_else(ignore_not_found)
+            .map_err(|err| cache_error(&path, err))?;
+          break
+        },
+        CacheState::Absent if lock.is_some() => break,
+        CacheState::Expired | CacheState::Absent => (),
+      }
+
+      let src = lock_path.clone();
+      lock = spawn_blocking(move || LockFile::try_acquire(&src))
+        .await
+        .map_err(|err| cache_error(&lock_path, IoError::from(err)))??;
+      if lock.is_none() {
+        debug!("waiting for concurrent fetch of `{}`", path.display());
+        let () = self.client.check_cancelled()?;
+        let () = sleep(LOCK_POLL_INTERVAL).await;
+      }
+    }
+
+    let mut response = if let Some(response) = self.client.fetch(build_id, artifact).await? {
+      response
+    } else {
+      if !self.config.cache_miss.is_zero() {
+        let () = record_miss(&path).await?;
+      }
+      return Ok(None)
+    };
+
+    // It's important that our temporary file is located inside `cache_dir`
+    // already, or it may end up on a different device, in which case the
+    // `persist` below won't work and we cannot guarantee atomicity.
+    let cache_dir = self.cache_dir.clone();
+    let (tempfile, file) = spawn_blocking(move || {
+      let tempfile =
+        NamedTempFile::new_in(&cache_dir).map_err(|err| cache_error(&cache_dir, err))?;
+      let file = tempfile
+        .as_file()
+        .try_clone()
+        .map_err(|err| cache_error(tempfile.path(), err))?;
+      Result::<_>::Ok((tempfile, file))
+    })
+    .await
+    .map_err(|err| cache_error(&self.cache_dir, IoError::from(err)))??;
+    let mut file = File::from_std(file);
+    // SANITY: We only get here once we hold the lock.
+    let lock = lock.as_mut().unwrap();
+    let () = copy(
+      &mut response.data,
+      &mut file,
+      response.server_url,
+      &path,
+      lock,
+    )
+    .await?;
+
+    // SANITY: Our path is guaranteed to always have a parent.
+    let dir = path.parent().unwrap();
+    let () = create_dir_all(dir)
+      .await
+      .map_err(|err| cache_error(dir, err))?;
+
+    let dst = path.clone();
+    let () = spawn_blocking(move || persist(tempfile, &dst))
+      .await
+      .map_err(|err| cache_error(&path, IoError::from(err)))??;
+    Ok(Some(path))
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::borrow::Cow;
+  use std::fs::read;
+  use std::fs::read_dir;
+
+  use http::Response as HttpResponse;
+  use http::StatusCode;
+
+  use tempfile::tempdir;
+
+  use crate::eviction::CLEAN_INTERVAL_FILE;
+  use crate::AsyncHttpClient;
+  use crate::AsyncReadable;
+  use crate::BoxFuture;
+  use crate::ClientBuilder;
+  use crate::HttpClientError;
+  use crate::SizeLimitExceeded;
+
+
+  /// An `AsyncHttpClient` serving a canned response.
+  #[derive(Debug)]
+  struct DummyHttpClient(Option<&'static [u8]>);
+
+  impl AsyncHttpClient for DummyHttpClient {
+    fn get<'slf>(
+      &'slf self,
+      _url: &'slf str,
+    ) -> BoxFuture<'slf, Result<HttpResponse<Box<dyn AsyncReadable>>, HttpClientError>> {
+      Box::pin(async move {
+        if let Some(data) = self.0 {
+          Ok(HttpResponse::new(Box::new(data) as Box<dyn AsyncReadable>))
+        } else {
+          Err(HttpClientError::StatusCode(StatusCode::NOT_FOUND))
+        }
+      })
+    }
+  }
+
+
+  /// Check that we can fetch and cache debug information.
+  #[tokio::test]
+  async fn fetch_debug_info() {
+    let cache_dir = tempdir().unwrap();
+    let urls = ["https://debuginfod.fedoraproject.org/"];
+    let client = ClientBuilder::default()
+      .async_http_client(DummyHttpClient(Some(b"Debug info!")))
+      .build_async(urls)
+      .unwrap()
+      .unwrap();
+    let client = AsyncCachingClient::new(client, cache_dir.path())
+      .await
+      .unwrap();
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let path = client.fetch_debug_info(&build_id).await.unwrap().unwrap();
+    assert_eq!(path, cache_dir.path().join("00").join("debuginfo"));
+    assert_eq!(read(&path).unwrap(), b"Debug info!");
+
+    // A second fetch should be served from the cache.
+    let client = ClientBuilder::default()
+      .async_http_client(DummyHttpClient(None))
+      .build_async(urls)
+      .unwrap()
+      .unwrap();
+    let client = AsyncCachingClient::new(client, cache_dir.path())
+      .await
+      .unwrap();
+    let cached = client.fetch_debug_info(&build_id).await.unwrap().unwrap();
+    assert_eq!(cached, path);
+
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x01]));
+    let result = client.fetch_debug_info(&build_id).await.unwrap();
+    assert_eq!(result, None);
+    // The miss should have been recorded.
+    let path = cache_dir.path().join("01").join("debuginfo");
+    assert_eq!(path.metadata().unwrap().len(), 0);
+  }
+
+  /// Make sure that we clean up after ourselves when a response turns
+  /// out to be too large.
+  #[tokio::test]
+  async fn fetch_debug_info_too_large() {
+    let cache_dir = tempdir().unwrap();
+    let urls = ["https://debuginfod.fedoraproject.org/"];
+    let client = ClientBuilder::default()
+      .async_http_client(DummyHttpClient(Some(b"Debug info!")))
+      .max_size(Some(5))
+      .build_async(urls)
+      .unwrap()
+      .unwrap();
+    let client = AsyncCachingClient::new(client, cache_dir.path())
+      .await
+      .unwrap();
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let err = client.fetch_debug_info(&build_id).await.unwrap_err();
+    assert!(
+      matches!(
+        err,
+        Error::TooLarge(SizeLimitExceeded {
+          limit: 5,
+          size: None
+        })
+      ),
+      "{err:?}"
+    );
+    // Only the record of the last cleanup should be present.
+    let files = read_dir(cache_dir.path())
+      .unwrap()
+      .map(|entry| entry.unwrap().file_name())
+      .collect::<Vec<_>>();
+    assert_eq!(files, [CLEAN_INTERVAL_FILE]);
+  }
+}
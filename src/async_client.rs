@@ -0,0 +1,605 @@
+// Copyright (C) 2026 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+use std::io::Cursor;
+use std::io::ErrorKind;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use futures_util::stream::FuturesUnordered;
+use futures_util::StreamExt as _;
+
+use http::HeaderMap;
+use http::Response as HttpResponse;
+
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt as _;
+use tokio::time::timeout_at;
+use tokio::time::Instant as TokioInstant;
+
+use url::Url;
+
+use crate::client::check_size;
+use crate::client::fetch_outcome;
+use crate::client::make_request;
+use crate::client::parse_base_urls;
+use crate::client::progress_reporter;
+use crate::client::Artifact;
+use crate::client::MetadataQuery;
+use crate::client::Strategy;
+use crate::elf::check_prefix;
+use crate::elf::BuildIdMismatch;
+use crate::elf::Verdict;
+use crate::elf::MAX_VERIFY_PREFIX;
+use crate::error::Result;
+use crate::log::debug;
+use crate::progress::ProgressFn;
+use crate::retry::send_with_retry_async;
+use crate::retry::RetryPolicy;
+use crate::transfer::AsyncTransfer;
+use crate::transfer::Timeouts;
+use crate::util::split_env_var_contents;
+use crate::AsyncHttpClient;
+use crate::AsyncReadable;
+use crate::BuildId;
+use crate::CancellationToken;
+use crate::Cancelled;
+use crate::ClientBuilder;
+use crate::Error;
+use crate::HttpClientError;
+use crate::Metadata;
+use crate::Response;
+
+
+/// An asynchronous client for interacting with (one or more)
+/// `debuginfod` servers.
+///
+/// This type is the asynchronous counterpart to [`Client`][crate::Client]
+/// and is created via [`ClientBuilder::build_async`]. It honors the same
+/// configuration. Operations can be aborted by dropping the returned
+/// futures (or readers); a [`CancellationToken`] is only checked in
+/// between requests and reads.
+#[derive(Debug)]
+pub struct AsyncClient {
+  /// A list of base URLs of services speaking the debuginfod
+  /// protocol, in decreasing order of importance.
+  base_urls: Vec<Url>,
+  /// The HTTP client we use for satisfying requests.
+  client: Arc<dyn AsyncHttpClient>,
+  /// Headers to send along with each request.
+  headers: HeaderMap,
+  /// The strategy used for querying the servers at `base_urls`.
+  strategy: Strategy,
+  /// The policy for retrying failed requests.
+  retry: RetryPolicy,
+  /// Timeouts applying to requests and data transfers.
+  timeouts: Timeouts,
+  /// The maximum size of a response we accept, in bytes.
+  max_size: Option<u64>,
+  /// The callback informed about the progress of downloads.
+  progress: Option<ProgressFn>,
+  /// The token used for cancelling in-flight operations.
+  cancel: Option<CancellationToken>,
+  /// Whether to verify the build ID of downloaded ELF files.
+  verify_build_id: bool,
+}
+
+impl AsyncClient {
+  /// Perform a GET request to the provided URL.
+  async fn get(
+    &self,
+    url: &Url,
+    deadline: Option<Instant>,
+  ) -> Result<HttpResponse<Box<dyn AsyncReadable>>, HttpClientError> {
+    let request = make_request(url, &self.headers)?;
+    send_with_retry_async(
+      &*self.client,
+      &request,
+      &self.retry,
+      self.timeouts.response,
+      deadline,
+      self.cancel.as_ref(),
+    )
+    .await
+  }
+
+  /// Fail with [`Cancelled`] if our cancellation token got cancelled.
+  pub(crate) fn check_cancelled(&self) -> Result<(), Cancelled> {
+    self
+      .cancel
+      .as_ref()
+      .map_or(Ok(()), CancellationToken::check)
+  }
+
+  /// Fetch the debug info for the given build ID.
+  ///
+  /// See [`Client::fetch_debug_info`][crate::Client::fetch_debug_info].
+  pub async fn fetch_debug_info(
+    &self,
+    build_id: &BuildId<'_>,
+  ) -> Result<Option<Response<'_, impl AsyncReadable>>> {
+    self.fetch(build_id, Artifact::DebugInfo).await
+  }
+
+  /// Fetch the executable for the given build ID.
+  ///
+  /// See [`Client::fetch_executable`][crate::Client::fetch_executable].
+  pub async fn fetch_executable(
+    &self,
+    build_id: &BuildId<'_>,
+  ) -> Result<Option<Response<'_, impl AsyncReadable>>> {
+    self.fetch(build_id, Artifact::Executable).await
+  }
+
+  /// Fetch the source file at `path` for the given build ID.
+  ///
+  /// See [`Client::fetch_source`][crate::Client::fetch_source].
+  pub async fn fetch_source(
+    &self,
+    build_id: &BuildId<'_>,
+    path: &str,
+  ) -> Result<Option<Response<'_, impl AsyncReadable>>> {
+    self.fetch(build_id, Artifact::Source(path)).await
+  }
+
+  /// Fetch the ELF section with the given name for the given build ID.
+  ///
+  /// See [`Client::fetch_section`][crate::Client::fetch_section].
+  pub async fn fetch_section(
+    &self,
+    build_id: &BuildId<'_>,
+    section: &str,
+  ) -> Result<Option<Response<'_, impl AsyncReadable>>> {
+    self.fetch(build_id, Artifact::Section(section)).await
+  }
+
+  /// Query the metadata of files known to the debuginfod servers.
+  ///
+  /// See [`Client::query_metadata`][crate::Client::query_metadata].
+  pub async fn query_metadata(&self, key: &str, value: &str) -> Result<Metadata> {
+    let mut query = MetadataQuery::new(key, value);
+
+    for base_url in &self.base_urls {
+      let () = self.check_cancelled()?;
+
+      let url = query.url(base_url);
+      debug!("making GET request to {url}");
+
+      let deadline = self.timeouts.deadline();
+      let result = match self.get(&url, deadline).await {
+        Ok(response) => {
+          let mut data = Vec::new();
+          AsyncTransfer::new(response.into_body(), &self.timeouts, deadline)
+            .cancel(self.cancel.clone())
+            .read_to_end(&mut data)
+            .await
+            .map(|_count| data)
+            .map_err(|err| HttpClientError::Other(Box::new(err)))
+        },
+        Err(err) => Err(err),
+      };
+      let () = query.add(&url, result);
+    }
+
+    query.finish()
+  }
+
+  /// Request the provided URLs in the manner dictated by the
+  /// configured strategy, until the first one succeeds.
+  ///
+  /// Returned are the index of the URL that could be retrieved
+  /// successfully along with its response, if any, as well as all errors
+  /// encountered, ordered by URL index. Each is accompanied by the time
+  /// the respective request took.
+  async fn request_any(
+    &self,
+    urls: &[Url],
+    deadline: Option<Instant>,
+  ) -> (
+    Option<(usize, HttpResponse<Box<dyn AsyncReadable>>, Duration)>,
+    Vec<(usize, HttpClientError, Duration)>,
+  ) {
+    // The delay after which we start the next request, even if
+    // requests are still in flight. `None` means that we only ever
+    // have a single request in flight.
+    let delay = match self.strategy {
+      Strategy::Sequential => None,
+      Strategy::Concurrent => Some(Default::default()),
+      Strategy::Hedged(delay) => Some(delay),
+    };
+    let request = |idx: usize| {
+      let url = &urls[idx];
+      debug!("making GET request to {url}");
+      async move {
+        let start = Instant::now();
+        let result = self.get(url, deadline).await;
+        (idx, result, start.elapsed())
+      }
+    };
+
+    let mut pending = FuturesUnordered::new();
+    let mut errors = Vec::new();
+    let mut response = None;
+    // The index of the next URL to request.
+    let mut next = 0;
+    // The point in time at which we start the next request.
+    let mut hedge_time = Instant::now();
+
+    loop {
+      if next < urls.len() && self.check_cancelled().is_err() {
+        let () = errors.push((
+          next,
+          HttpClientError::Other(Box::new(Cancelled)),
+          Duration::ZERO,
+        ));
+        next = urls.len();
+      }
+
+      if next < urls.len() && pending.is_empty() {
+        let () = pending.push(request(next));
+        next += 1;
+        hedge_time = Instant::now() + delay.unwrap_or_default();
+        continue
+      }
+
+      let result = match delay {
+        Some(delay) if next < urls.len() => {
+          match timeout_at(TokioInstant::from_std(hedge_time), pending.next()).await {
+            Ok(result) => result,
+            Err(_elapsed) => {
+              let () = pending.push(request(next));
+              next += 1;
+              hedge_time = Instant::now() + delay;
+              continue
+            },
+          }
+        },
+        _ => pending.next().await,
+      };
+
+      match result {
+        Some((idx, Ok(resp), duration)) => {
+          response = Some((idx, resp, duration));
+          break
+        },
+        Some((idx, Err(err), duration)) => errors.push((idx, err, duration)),
+        None => break,
+      }
+    }
+
+    let () = errors.sort_by_key(|(idx, _err, _duration)| *idx);
+    (response, errors)
+  }
+
+  pub(crate) async fn fetch(
+    &self,
+    build_id: &BuildId<'_>,
+    artifact: Artifact<'_>,
+  ) -> Result<Option<Response<'_, impl AsyncReadable>>> {
+    let build_id = build_id.normalize()?;
+    let urls = artifact.urls(&self.base_urls, &build_id)?;
+    let deadline = self.timeouts.deadline();
+    let verify = self.verify_build_id && artifact.is_elf();
+    let mut errors = Vec::new();
+    // The indices of the servers that have not been asked yet.
+    let mut candidates = (0..urls.len()).collect::<Vec<_>>();
+
+    let found = loop {
+      let round_urls = candidates
+        .iter()
+        .map(|idx| urls[*idx].clone())
+        .collect::<Vec<_>>();
+      let (response, round_errors) = self.request_any(&round_urls, deadline).await;
+      let () = self.check_cancelled()?;
+
+      let () = errors.extend(
+        round_errors
+          .into_iter()
+          .map(|(idx, err, duration)| (candidates[idx], err, duration)),
+      );
+      let (idx, response, duration) = match response {
+        Some((idx, response, duration)) => (candidates[idx], response, duration),
+        None => break None,
+      };
+
+      let (parts, data) = response.into_parts();
+      let data = AsyncTransfer::new(data, &self.timeouts, deadline)
+        .max_size(self.max_size)
+        .cancel(self.cancel.clone());
+      let mut response = Response::new(data, self.base_urls[idx].as_str(), &parts.headers);
+      let progress = progress_reporter(self.progress.as_ref(), &response, &build_id);
+      response.data = response.data.progress(progress);
+      let () = check_size(&response, self.max_size)?;
+
+      let prefix = if verify {
+        if let Some(prefix) =
+          verify_build_id(&mut response.data, &build_id, response.server_url).await?
+        {
+          prefix
+        } else {
+          let () = errors.push((
+            idx,
+            HttpClientError::Other(Box::new(BuildIdMismatch)),
+            duration,
+          ));
+          // Fall through to the servers not asked so far.
+          let () = candidates.retain(|idx| errors.iter().all(|(asked, ..)| asked != idx));
+          continue
+        }
+      } else {
+        Vec::new()
+      };
+
+      // Data we read for verification is handed out before the rest.
+      let response = response.map_data(|data| Cursor::new(prefix).chain(data));
+      break Some((idx, duration, response))
+    };
+
+    let success = found
+      .as_ref()
+      .map(|(idx, duration, _response)| (*idx, *duration));
+    let attempts = fetch_outcome(&artifact, &self.base_urls, &urls, success, errors)?;
+    let response = found.map(|(_idx, _duration, mut response)| {
+      response.attempts = attempts;
+      response
+    });
+    Ok(response)
+  }
+}
+
+
+/// Read the beginning of `reader`, the data of a response from `url`,
+/// and check whether it is an ELF file with build ID `build_id`.
+///
+/// This function is the asynchronous counterpart to the one used by
+/// [`Client`][crate::Client].
+async fn verify_build_id<R>(reader: &mut R, build_id: &str, url: &str) -> Result<Option<Vec<u8>>>
+where
+  R: AsyncRead + Unpin,
+{
+  let mut prefix = Vec::new();
+  let mut eof = false;
+
+  loop {
+    match check_prefix(&prefix, eof, build_id) {
+      Verdict::Match => break Ok(Some(prefix)),
+      Verdict::Mismatch => break Ok(None),
+      Verdict::Unknown => {
+        debug!("failed to locate build ID in data from `{url}`; skipping verification");
+        break Ok(Some(prefix))
+      },
+      Verdict::NeedMore => {
+        let len = prefix.len();
+        let () = prefix.resize((len * 2).clamp(4096, MAX_VERIFY_PREFIX), 0);
+        let count = loop {
+          match reader.read(&mut prefix[len..]).await {
+            Ok(count) => break count,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(Error::from_read(err, url)),
+          }
+        };
+        let () = prefix.truncate(len + count);
+        eof = count == 0;
+      },
+    }
+  }
+}
+
+
+impl ClientBuilder<()> {
+  /// Set the asynchronous HTTP client to use for requests.
+  pub fn async_http_client<C>(self, client: C) -> ClientBuilder<C>
+  where
+    C: AsyncHttpClient + 'static,
+  {
+    self.with_client(client)
+  }
+}
+
+impl<C> ClientBuilder<C>
+where
+  C: AsyncHttpClient + 'static,
+{
+  /// Build a new `AsyncClient` able to speak the debuginfod protocol.
+  ///
+  /// See [`ClientBuilder::build`] for details.
+  pub fn build_async<'url, U>(self, base_urls: U) -> Result<Option<AsyncClient>>
+  where
+    U: IntoIterator<Item = &'url str>,
+  {
+    let base_urls = if let Some(base_urls) = parse_base_urls(base_urls)? {
+      base_urls
+    } else {
+      return Ok(None)
+    };
+
+    let slf = AsyncClient {
+      base_urls,
+      client: Arc::new(self.client),
+      headers: self.headers,
+      strategy: self.strategy,
+      retry: self.retry,
+      timeouts: self.timeouts,
+      max_size: self.max_size,
+      progress: self.progress,
+      cancel: self.cancel,
+      verify_build_id: self.verify_build_id,
+    };
+    Ok(Some(slf))
+  }
+
+  /// Build a new `AsyncClient` object with URLs parsed from the
+  /// `DEBUGINFOD_URLS` environment variable.
+  ///
+  /// See [`ClientBuilder::build_from_env`] for details.
+  pub fn build_async_from_env(mut self) -> Result<Option<AsyncClient>> {
+    let urls = if let Some(urls) = self.apply_env()? {
+      urls
+    } else {
+      return Ok(None)
+    };
+    self.build_async(split_env_var_contents(&urls))
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::borrow::Cow;
+  use std::time::Duration;
+
+  use http::StatusCode;
+
+  use tokio::io::copy;
+  use tokio::io::sink;
+  use tokio::time::sleep;
+
+  use crate::BoxFuture;
+  use crate::SizeLimitExceeded;
+
+
+  /// An `AsyncHttpClient` serving data only for certain URLs.
+  #[derive(Debug)]
+  struct DummyHttpClient {
+    /// URLs containing this string are served.
+    serve: &'static str,
+    /// URLs containing this string never respond.
+    hang: Option<&'static str>,
+  }
+
+  impl AsyncHttpClient for DummyHttpClient {
+    fn get<'slf>(
+      &'slf self,
+      url: &'slf str,
+    ) -> BoxFuture<'slf, Result<HttpResponse<Box<dyn AsyncReadable>>, HttpClientError>> {
+      Box::pin(async move {
+        if matches!(self.hang, Some(hang) if url.contains(hang)) {
+          let () = sleep(Duration::from_secs(30)).await;
+        }
+
+        if url.contains(self.serve) {
+          let data: &[u8] = b"Debug info!";
+          Ok(HttpResponse::new(Box::new(data) as Box<dyn AsyncReadable>))
+        } else {
+          Err(HttpClientError::StatusCode(StatusCode::NOT_FOUND))
+        }
+      })
+    }
+  }
+
+
+  /// Check that we fall back to other servers and report data of the
+  /// first one serving the artifact.
+  #[tokio::test]
+  async fn fetch_debug_info() {
+    let urls = [
+      "https://debuginfod.fedoraproject.org/",
+      "https://debuginfod.archlinux.org/",
+    ];
+    let client = ClientBuilder::default()
+      .async_http_client(DummyHttpClient {
+        serve: "archlinux",
+        hang: None,
+      })
+      .build_async(urls)
+      .unwrap()
+      .unwrap();
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let mut info = client.fetch_debug_info(&build_id).await.unwrap().unwrap();
+    assert_eq!(info.server_url, "https://debuginfod.archlinux.org/");
+
+    let mut data = Vec::new();
+    let _count = info.data.read_to_end(&mut data).await.unwrap();
+    assert_eq!(data, b"Debug info!");
+
+    let info = client.fetch_executable(&build_id).await.unwrap();
+    assert!(info.is_some());
+
+    // No server serving data means no data.
+    let client = ClientBuilder::default()
+      .async_http_client(DummyHttpClient {
+        serve: "nowhere",
+        hang: None,
+      })
+      .build_async(urls)
+      .unwrap()
+      .unwrap();
+    let info = client.fetch_debug_info(&build_id).await.unwrap();
+    assert!(info.is_none());
+  }
+
+  /// Check that hedging lets us use a server responding quicker than
+  /// the preferred one.
+  #[tokio::test]
+  async fn fetch_hedged() {
+    let urls = [
+      "https://debuginfod.fedoraproject.org/",
+      "https://debuginfod.archlinux.org/",
+    ];
+    let client = ClientBuilder::default()
+      .async_http_client(DummyHttpClient {
+        serve: "debuginfod",
+        hang: Some("fedoraproject"),
+      })
+      .hedge_delay(Duration::from_millis(10))
+      .build_async(urls)
+      .unwrap()
+      .unwrap();
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let now = Instant::now();
+    let info = client.fetch_debug_info(&build_id).await.unwrap().unwrap();
+    assert!(now.elapsed() < Duration::from_secs(15));
+    assert_eq!(info.server_url, "https://debuginfod.archlinux.org/");
+  }
+
+  /// Check that we move on to the next server if one does not respond
+  /// in time.
+  #[tokio::test]
+  async fn fetch_connect_timeout() {
+    let urls = [
+      "https://debuginfod.fedoraproject.org/",
+      "https://debuginfod.archlinux.org/",
+    ];
+    let client = ClientBuilder::default()
+      .async_http_client(DummyHttpClient {
+        serve: "debuginfod",
+        hang: Some("fedoraproject"),
+      })
+      .connect_timeout(Some(Duration::from_millis(50)))
+      .build_async(urls)
+      .unwrap()
+      .unwrap();
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let now = Instant::now();
+    let info = client.fetch_debug_info(&build_id).await.unwrap().unwrap();
+    assert!(now.elapsed() < Duration::from_secs(15));
+    assert_eq!(info.server_url, "https://debuginfod.archlinux.org/");
+  }
+
+  /// Check that we reject responses exceeding the maximum size.
+  #[tokio::test]
+  async fn fetch_max_size() {
+    let urls = ["https://debuginfod.fedoraproject.org/"];
+    let client = ClientBuilder::default()
+      .async_http_client(DummyHttpClient {
+        serve: "debuginfod",
+        hang: None,
+      })
+      .max_size(Some(5))
+      .build_async(urls)
+      .unwrap()
+      .unwrap();
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let mut info = client.fetch_debug_info(&build_id).await.unwrap().unwrap();
+    let err = copy(&mut info.data, &mut sink()).await.unwrap_err();
+    let err = err
+      .get_ref()
+      .unwrap()
+      .downcast_ref::<SizeLimitExceeded>()
+      .unwrap();
+    assert_eq!(err.limit, 5);
+  }
+}
@@ -0,0 +1,76 @@
+// Copyright (C) 2026 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+use std::fmt::Debug;
+use std::future::Future;
+use std::io::Error as IoError;
+use std::io::ErrorKind;
+use std::pin::Pin;
+
+use http::Method;
+use http::Request;
+use http::Response;
+
+use tokio::io::AsyncRead;
+
+use crate::HttpClientError;
+
+
+/// A boxed future, as returned by [`AsyncHttpClient`] methods.
+pub type BoxFuture<'fut, T> = Pin<Box<dyn Future<Output = T> + Send + 'fut>>;
+
+
+/// A trait representing an entity that can be read from
+/// asynchronously.
+pub trait AsyncReadable: AsyncRead + Debug + Send + Unpin {}
+
+impl<R> AsyncReadable for R where R: AsyncRead + Debug + Send + Unpin {}
+
+
+/// A trait representing HTTP client capable of performing asynchronous
+/// GET requests, used to download debug information from `debuginfod`
+/// servers.
+///
+/// This trait is the asynchronous counterpart to
+/// [`HttpClient`][crate::HttpClient] and its methods follow the same
+/// contract.
+pub trait AsyncHttpClient: Debug + Send + Sync {
+  /// Perform an HTTP GET request to the specified URL.
+  ///
+  /// On success, the returned response provides access to the response
+  /// headers as well as a reader for the body. Responses with a
+  /// non-success status code should be reported as
+  /// [`HttpClientError::StatusCode`].
+  fn get<'slf>(
+    &'slf self,
+    url: &'slf str,
+  ) -> BoxFuture<'slf, Result<Response<Box<dyn AsyncReadable>>, HttpClientError>>;
+
+  /// Perform an HTTP request as described by `request`.
+  ///
+  /// In contrast to [`AsyncHttpClient::get`], this method allows for
+  /// the provision of request headers, which implementations are
+  /// expected to send along. Responses with a non-success status code
+  /// may be returned as-is.
+  ///
+  /// The default implementation forwards `GET` requests to
+  /// [`AsyncHttpClient::get`], dropping all request headers, and fails
+  /// all other requests. Implementations are encouraged to override it.
+  fn send(
+    &self,
+    request: Request<()>,
+  ) -> BoxFuture<'_, Result<Response<Box<dyn AsyncReadable>>, HttpClientError>> {
+    Box::pin(async move {
+      if request.method() != Method::GET {
+        let err = IoError::new(
+          ErrorKind::Unsupported,
+          format!("HTTP method {} is not supported", request.method()),
+        );
+        return Err(HttpClientError::Other(Box::new(err)))
+      }
+
+      let url = request.uri().to_string();
+      self.get(&url).await
+    })
+  }
+}
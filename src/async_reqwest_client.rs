@@ -0,0 +1,170 @@
+// Copyright (C) 2026 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::io::Error as IoError;
+use std::io::ErrorKind;
+use std::io::Result as IoResult;
+use std::pin::Pin;
+use std::task::Context;
+use std::task::Poll;
+
+use futures_util::TryStreamExt as _;
+
+use http::Request;
+use http::Response;
+
+use reqwest::Client;
+use reqwest::Request as AsyncRequest;
+
+use tokio::io::AsyncRead;
+use tokio::io::ReadBuf;
+
+use tokio_util::io::StreamReader;
+
+use crate::AsyncHttpClient;
+use crate::AsyncReadable;
+use crate::BoxFuture;
+use crate::HttpClientError;
+
+
+/// A reader for the body of a response.
+struct BodyReader(Pin<Box<dyn AsyncRead + Send>>);
+
+impl Debug for BodyReader {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    f.debug_tuple("BodyReader").finish()
+  }
+}
+
+impl AsyncRead for BodyReader {
+  fn poll_read(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<IoResult<()>> {
+    self.0.as_mut().poll_read(cx, buf)
+  }
+}
+
+
+/// Implements the `AsyncHttpClient` trait for the `reqwest` crate.
+impl AsyncHttpClient for Client {
+  /// Perform an HTTP GET request to the specified URL.
+  fn get<'slf>(
+    &'slf self,
+    url: &'slf str,
+  ) -> BoxFuture<'slf, Result<Response<Box<dyn AsyncReadable>>, HttpClientError>> {
+    Box::pin(async move {
+      let request = Request::get(url)
+        .body(())
+        .map_err(|err| HttpClientError::InvalidUrl(Box::new(err)))?;
+      let response = AsyncHttpClient::send(self, request).await?;
+
+      let status = response.status();
+      if !status.is_success() {
+        return Err(HttpClientError::StatusCode(status));
+      }
+      Ok(response)
+    })
+  }
+
+  /// Perform an HTTP request, including all provided headers.
+  ///
+  /// Responses are reported irrespective of their status code.
+  fn send(
+    &self,
+    request: Request<()>,
+  ) -> BoxFuture<'_, Result<Response<Box<dyn AsyncReadable>>, HttpClientError>> {
+    Box::pin(async move {
+      let (parts, ()) = request.into_parts();
+      let url = parts
+        .uri
+        .to_string()
+        .as_str()
+        .try_into()
+        .map_err(|err| HttpClientError::InvalidUrl(Box::new(err)))?;
+      let mut request = AsyncRequest::new(parts.method, url);
+      *request.headers_mut() = parts.headers;
+
+      let resp = self
+        .execute(request)
+        .await
+        .map_err(|err| HttpClientError::Other(Box::new(err)))?;
+
+      let status = resp.status();
+      let headers = resp.headers().clone();
+      let stream = resp
+        .bytes_stream()
+        .map_err(|err| IoError::new(ErrorKind::Other, err));
+      let reader = BodyReader(Box::pin(StreamReader::new(stream)));
+      let mut response = Response::new(Box::new(reader) as Box<dyn AsyncReadable>);
+      *response.status_mut() = status;
+      *response.headers_mut() = headers;
+      Ok(response)
+    })
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use http::header::USER_AGENT;
+
+  use tokio::io::AsyncBufReadExt as _;
+  use tokio::io::AsyncReadExt as _;
+  use tokio::io::AsyncWriteExt as _;
+  use tokio::io::BufReader;
+  use tokio::net::TcpListener;
+
+
+  /// Check that we send along request headers and report response
+  /// headers as well as the body.
+  #[tokio::test]
+  async fn request_headers() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+      let (stream, _addr) = listener.accept().await.unwrap();
+      let mut reader = BufReader::new(stream);
+      let mut headers = Vec::new();
+      loop {
+        let mut line = String::new();
+        let _count = reader.read_line(&mut line).await.unwrap();
+        if line.trim().is_empty() {
+          break
+        }
+        let () = headers.push(line.trim().to_ascii_lowercase());
+      }
+
+      let response =
+        "HTTP/1.1 200 OK\r\nX-DEBUGINFOD-SIZE: 11\r\nContent-Length: 11\r\n\r\nDebug info!";
+      let () = reader
+        .get_mut()
+        .write_all(response.as_bytes())
+        .await
+        .unwrap();
+      headers
+    });
+
+    let client = Client::new();
+    let request = Request::get(format!("http://{addr}/buildid/00/debuginfo"))
+      .header(USER_AGENT, "debuginfod-test/1.0")
+      .body(())
+      .unwrap();
+    let response = AsyncHttpClient::send(&client, request).await.unwrap();
+    assert!(response.status().is_success());
+    assert_eq!(response.headers().get("x-debuginfod-size").unwrap(), "11");
+
+    let mut data = Vec::new();
+    let _count = response.into_body().read_to_end(&mut data).await.unwrap();
+    assert_eq!(data, b"Debug info!");
+
+    let headers = server.await.unwrap();
+    assert!(headers.contains(&"user-agent: debuginfod-test/1.0".to_string()));
+  }
+}
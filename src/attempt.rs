@@ -0,0 +1,92 @@
+// Copyright (C) 2026 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+use std::time::Duration;
+
+use http::StatusCode;
+
+use crate::elf::BuildIdMismatch;
+use crate::HttpClientError;
+
+
+/// The outcome of a request to a single server.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AttemptOutcome {
+  /// The server provided the requested data.
+  Success,
+  /// The server does not know about the requested data.
+  NotFound,
+  /// The server responded with a non-success HTTP status other than
+  /// `404 Not Found`.
+  Status(StatusCode),
+  /// The server provided data that does not belong to the requested
+  /// build ID.
+  BuildIdMismatch,
+  /// The request could not be issued or failed in transit, e.g.,
+  /// because the server could not be reached or timed out. Contained
+  /// is a description of the error.
+  Transport(String),
+}
+
+impl AttemptOutcome {
+  /// Classify an error reported by an `HttpClient`.
+  pub(crate) fn from_error(err: &HttpClientError) -> Self {
+    match err {
+      HttpClientError::StatusCode(StatusCode::NOT_FOUND) => Self::NotFound,
+      HttpClientError::StatusCode(status) => Self::Status(*status),
+      HttpClientError::Other(err) if err.is::<BuildIdMismatch>() => Self::BuildIdMismatch,
+      err => Self::Transport(err.to_string()),
+    }
+  }
+}
+
+
+/// A request to a single server made as part of a fetch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attempt {
+  /// The URL of the server that was contacted.
+  pub server_url: String,
+  /// The outcome of the request.
+  pub outcome: AttemptOutcome,
+  /// The time it took for the request to conclude, including any
+  /// retries. For successful requests that is the time until the
+  /// response headers were received.
+  pub duration: Duration,
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::io::Error as IoError;
+  use std::io::ErrorKind;
+
+
+  /// Check that we classify errors reported by HTTP clients correctly.
+  #[test]
+  fn outcome_classification() {
+    let err = HttpClientError::StatusCode(StatusCode::NOT_FOUND);
+    assert_eq!(AttemptOutcome::from_error(&err), AttemptOutcome::NotFound);
+
+    let err = HttpClientError::StatusCode(StatusCode::BAD_GATEWAY);
+    assert_eq!(
+      AttemptOutcome::from_error(&err),
+      AttemptOutcome::Status(StatusCode::BAD_GATEWAY)
+    );
+
+    let err = HttpClientError::Other(Box::new(BuildIdMismatch));
+    assert_eq!(
+      AttemptOutcome::from_error(&err),
+      AttemptOutcome::BuildIdMismatch
+    );
+
+    let err = IoError::new(ErrorKind::ConnectionRefused, "connection refused");
+    let err = HttpClientError::Other(Box::new(err));
+    assert!(matches!(
+      AttemptOutcome::from_error(&err),
+      AttemptOutcome::Transport(msg) if msg.contains("connection refused")
+    ));
+  }
+}
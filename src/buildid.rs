@@ -2,9 +2,60 @@
 // SPDX-License-Identifier: (Apache-2.0 OR MIT)
 
 use std::borrow::Cow;
+use std::error::Error as StdError;
 use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::io::Seek;
+use std::path::Path;
 
+use crate::elf::read_build_id;
+use crate::elf::ReadAt;
+use crate::elf::SeekReader;
+use crate::error::Result;
 use crate::util::format_build_id;
+use crate::Error;
+
+
+/// The maximum length of a build ID, in bytes.
+///
+/// Common build IDs are 16 (MD5, UUID) or 20 (SHA-1) bytes long, but
+/// linkers accept arbitrary ones specified in hex.
+const MAX_BUILD_ID_LEN: usize = 64;
+
+
+/// An error indicating that a [`BuildId`] is malformed.
+///
+/// Build IDs have to consist of between one and 64 bytes. Formatted
+/// ones have to be made up of an even number of hexadecimal digits.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidBuildId {
+  /// The rejected build ID, in printable form.
+  build_id: String,
+  /// The reason the build ID got rejected.
+  reason: &'static str,
+}
+
+impl InvalidBuildId {
+  fn new(build_id: &str, reason: &'static str) -> Self {
+    Self {
+      build_id: build_id.escape_debug().to_string(),
+      reason,
+    }
+  }
+}
+
+impl fmt::Display for InvalidBuildId {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "build ID `{}` is invalid: {}",
+      self.build_id, self.reason
+    )
+  }
+}
+
+impl StdError for InvalidBuildId {}
 
 
 /// The (GNU) build id is a randomly generated string added by most compilers to
@@ -58,6 +109,121 @@ impl<'id> BuildId<'id> {
       Self::Formatted(string) => Cow::Borrowed(string),
     }
   }
+
+  /// Check that the build ID is well-formed.
+  ///
+  /// ```
+  /// # use debuginfod::BuildId;
+  /// assert!(BuildId::formatted("aeb9a983ace1fb04").validate().is_ok());
+  /// assert!(BuildId::formatted("../../.ssh").validate().is_err());
+  /// ```
+  pub fn validate(&self) -> Result<(), InvalidBuildId> {
+    self.normalize().map(|_build_id| ())
+  }
+
+  /// Validate the build ID and return its hex representation, using
+  /// lowercase digits only.
+  ///
+  /// This representation is safe for use in URLs and file system paths.
+  pub(crate) fn normalize(&self) -> Result<Cow<'_, str>, InvalidBuildId> {
+    match self {
+      Self::RawBytes(bytes) => {
+        if bytes.is_empty() {
+          return Err(InvalidBuildId::new("", "build ID is empty"))
+        }
+        if bytes.len() > MAX_BUILD_ID_LEN {
+          return Err(InvalidBuildId::new(
+            &format_build_id(bytes),
+            "build ID is too long",
+          ))
+        }
+        Ok(Cow::Owned(format_build_id(bytes)))
+      },
+      Self::Formatted(string) => {
+        if string.is_empty() {
+          return Err(InvalidBuildId::new(string, "build ID is empty"))
+        }
+        if !string.bytes().all(|b| b.is_ascii_hexdigit()) {
+          return Err(InvalidBuildId::new(
+            string,
+            "build ID contains non-hexadecimal characters",
+          ))
+        }
+        if string.len() % 2 != 0 {
+          return Err(InvalidBuildId::new(
+            string,
+            "build ID has an odd number of digits",
+          ))
+        }
+        if string.len() > MAX_BUILD_ID_LEN * 2 {
+          return Err(InvalidBuildId::new(string, "build ID is too long"))
+        }
+
+        if string.bytes().any(|b| b.is_ascii_uppercase()) {
+          Ok(Cow::Owned(string.to_ascii_lowercase()))
+        } else {
+          Ok(Cow::Borrowed(string))
+        }
+      },
+    }
+  }
+}
+
+impl BuildId<'static> {
+  /// Read the build ID of the ELF file at `path`.
+  ///
+  /// The GNU build ID note is searched for via both the program headers
+  /// and the section headers of the file, which may be 32 or 64 bit and
+  /// of either byte order. `None` is returned if the file does not
+  /// carry a build ID.
+  ///
+  /// ```no_run
+  /// # use debuginfod::BuildId;
+  /// let build_id = BuildId::from_elf_path("/usr/bin/sleep").unwrap();
+  /// ```
+  pub fn from_elf_path<P>(path: P) -> Result<Option<Self>>
+  where
+    P: AsRef<Path>,
+  {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|source| Error::Io {
+      path: path.to_path_buf(),
+      source,
+    })?;
+    Self::read(SeekReader(file)).map_err(|err| match err {
+      Error::Elf { source, .. } => Error::Elf {
+        path: Some(path.to_path_buf()),
+        source,
+      },
+      err => err,
+    })
+  }
+
+  /// Read the build ID of the ELF file contained in `data`.
+  ///
+  /// See [`BuildId::from_elf_path`] for details.
+  pub fn from_elf_bytes(data: &[u8]) -> Result<Option<Self>> {
+    Self::read(data)
+  }
+
+  /// Read the build ID of the ELF file provided by `reader`.
+  ///
+  /// See [`BuildId::from_elf_path`] for details.
+  pub fn from_reader<R>(reader: R) -> Result<Option<Self>>
+  where
+    R: Read + Seek,
+  {
+    Self::read(SeekReader(reader))
+  }
+
+  fn read<S>(mut source: S) -> Result<Option<Self>>
+  where
+    S: ReadAt,
+  {
+    let build_id =
+      read_build_id(&mut source).map_err(|source| Error::Elf { path: None, source })?;
+    Ok(build_id.map(Self::raw))
+  }
 }
 
 impl fmt::Display for BuildId<'_> {
@@ -71,6 +237,13 @@ impl fmt::Display for BuildId<'_> {
 mod tests {
   use super::*;
 
+  use std::io::Cursor;
+  use std::io::Write as _;
+
+  use tempfile::NamedTempFile;
+
+  use crate::elf::tests::make_elf;
+
 
   /// Check that we can construct `BuildId` objects as expected.
   #[test]
@@ -88,6 +261,80 @@ mod tests {
     assert!(matches!(build_id, BuildId::Formatted(Cow::Owned(..))));
   }
 
+  /// Check that we validate and normalize build IDs correctly.
+  #[test]
+  fn build_id_validation() {
+    let build_id = BuildId::formatted("AEB9a983");
+    assert_eq!(build_id.normalize().unwrap(), "aeb9a983");
+
+    let build_id = BuildId::raw(&[0xae, 0xb9]);
+    assert_eq!(build_id.normalize().unwrap(), "aeb9");
+
+    let invalid = [
+      BuildId::formatted(""),
+      BuildId::formatted("../../.ssh"),
+      BuildId::formatted("aeb9/a983"),
+      BuildId::formatted("aeb"),
+      BuildId::formatted("00".repeat(MAX_BUILD_ID_LEN + 1)),
+      BuildId::raw(&[]),
+      BuildId::raw(vec![0x00; MAX_BUILD_ID_LEN + 1]),
+    ];
+    for build_id in invalid {
+      let _err = build_id.validate().unwrap_err();
+    }
+
+    let err = BuildId::formatted("../x").validate().unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "build ID `../x` is invalid: build ID contains non-hexadecimal characters"
+    );
+  }
+
+  /// Check that we can read build IDs from ELF files.
+  #[test]
+  fn build_id_from_elf() {
+    let build_id = [0xae, 0xb9, 0xa9, 0x83];
+
+    for is_64 in [false, true] {
+      for big_endian in [false, true] {
+        for phdr in [false, true] {
+          let elf = make_elf(is_64, big_endian, phdr, &build_id);
+          let found = BuildId::from_elf_bytes(&elf).unwrap().unwrap();
+          assert_eq!(found.format(), "aeb9a983");
+
+          let found = BuildId::from_reader(Cursor::new(&elf)).unwrap().unwrap();
+          assert_eq!(found.format(), "aeb9a983");
+        }
+      }
+    }
+
+    let mut file = NamedTempFile::new().unwrap();
+    let () = file
+      .write_all(&make_elf(true, false, false, &build_id))
+      .unwrap();
+    let found = BuildId::from_elf_path(file.path()).unwrap().unwrap();
+    assert_eq!(found.format(), "aeb9a983");
+  }
+
+  /// Make sure that we report errors when reading build IDs from data
+  /// that is not an ELF file.
+  #[test]
+  fn build_id_from_invalid_elf() {
+    let err = BuildId::from_elf_bytes(b"<html></html>").unwrap_err();
+    assert!(matches!(err, Error::Elf { path: None, .. }), "{err:?}");
+
+    let mut file = NamedTempFile::new().unwrap();
+    let () = file.write_all(b"\x7fEL").unwrap();
+    let err = BuildId::from_elf_path(file.path()).unwrap_err();
+    assert!(
+      matches!(&err, Error::Elf { path: Some(path), .. } if path == file.path()),
+      "{err:?}"
+    );
+
+    let err = BuildId::from_elf_path("/does/not/exist").unwrap_err();
+    assert!(matches!(err, Error::Io { .. }), "{err:?}");
+  }
+
   /// Test the `Display` implementation of the `BuildId` type.
   #[test]
   fn build_id_display() {
@@ -1,34 +1,141 @@
 // Copyright (C) 2024-2025 Daniel Mueller <deso@posteo.net>
 // SPDX-License-Identifier: (Apache-2.0 OR MIT)
 
+use std::borrow::Cow;
 use std::env;
 use std::fs::create_dir_all;
-use std::fs::File;
-use std::io::copy;
+use std::fs::read_to_string;
+use std::fs::remove_file;
+use std::fs::Metadata;
+use std::fs::OpenOptions;
+use std::io::Error as IoError;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Result as IoResult;
+use std::io::Write;
 use std::ops::Deref;
 use std::path::Path;
 use std::path::PathBuf;
-
-use anyhow::Context as _;
-use anyhow::Result;
+use std::str::FromStr as _;
+use std::thread::sleep;
+use std::time::Duration;
+use std::time::SystemTime;
 
 use dirs::cache_dir;
 use dirs::home_dir;
 
 use tempfile::NamedTempFile;
 
+use crate::client::source_path_components;
+use crate::client::Artifact;
+use crate::error::Result;
+use crate::eviction::Eviction;
+use crate::eviction::CLEAN_INTERVAL_FILE;
+use crate::eviction::DEFAULT_CLEAN_INTERVAL_S;
+use crate::eviction::DEFAULT_MAX_UNUSED_AGE_S;
+use crate::eviction::MAX_UNUSED_AGE_FILE;
+use crate::lock::lock_path;
+use crate::lock::refresh_lock;
+use crate::lock::LockFile;
+use crate::lock::LOCK_POLL_INTERVAL;
 use crate::log::debug;
+use crate::log::warn;
+use crate::single_flight::SingleFlight;
 use crate::BuildId;
+use crate::CancellationToken;
 use crate::Client;
+use crate::Error;
+
+
+/// The size of the buffer used for copying data into the cache.
+pub(crate) const COPY_BUFFER_SIZE: usize = 64 * 1024;
+/// The name of the file in the cache root containing the number of
+/// seconds for which misses are cached.
+const CACHE_MISS_FILE: &str = "cache_miss_s";
+/// The time for which misses are cached by default, in seconds.
+const DEFAULT_CACHE_MISS_S: u64 = 600;
+/// The size of the buffer for an escaped path used in the file name of
+/// a cache entry, including the NUL terminator elfutils accounts for.
+/// Just like elfutils, we stay well below `NAME_MAX`, as some file
+/// systems reject even shorter names.
+const MAX_ESCAPED_LEN: usize = 255 / 2;
+
+
+/// The state of an entry in the cache.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum CacheState {
+  /// The requested data is cached.
+  Present,
+  /// The requested data is known to be unavailable.
+  Missing,
+  /// The requested data was unavailable when last checked, but that
+  /// information has expired.
+  Expired,
+  /// Nothing is known about the requested data.
+  Absent,
+}
+
+
+/// The configuration of a cache, as stored in its root directory.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct CacheConfig {
+  /// The time for which misses are cached.
+  pub(crate) cache_miss: Duration,
+  /// The policy for evicting entries.
+  pub(crate) eviction: Eviction,
+}
+
+impl CacheConfig {
+  /// Read the configuration of the cache at `cache_dir`.
+  ///
+  /// Settings that are not present or malformed assume their default
+  /// values.
+  pub(crate) fn read(cache_dir: &Path) -> Result<Self> {
+    fn read_setting(cache_dir: &Path, name: &str, default: u64) -> Result<Duration> {
+      let path = cache_dir.join(name);
+      let secs = match read_to_string(&path) {
+        Ok(contents) => u64::from_str(contents.trim()).unwrap_or_else(|_err| {
+          warn!("ignoring malformed contents of `{}`", path.display());
+          default
+        }),
+        Err(err) if err.kind() == ErrorKind::NotFound => default,
+        Err(err) => return Err(cache_error(&path, err)),
+      };
+      Ok(Duration::from_secs(secs))
+    }
+
+    let slf = Self {
+      cache_miss: read_setting(cache_dir, CACHE_MISS_FILE, DEFAULT_CACHE_MISS_S)?,
+      eviction: Eviction {
+        interval: read_setting(cache_dir, CLEAN_INTERVAL_FILE, DEFAULT_CLEAN_INTERVAL_S)?,
+        max_unused_age: Some(read_setting(
+          cache_dir,
+          MAX_UNUSED_AGE_FILE,
+          DEFAULT_MAX_UNUSED_AGE_S,
+        )?),
+        max_size: None,
+      },
+    };
+    Ok(slf)
+  }
+}
 
 
 /// A debuginfod client that caches data using the file system.
+///
+/// The cache may be shared between multiple threads and processes. Only
+/// one of them fetches the data for a given build ID at any time, while
+/// the others wait for it to arrive in the cache.
 #[derive(Debug)]
 pub struct CachingClient {
   /// The debuginfod client we use for satisfying requests.
   client: Client,
   /// The root directory of the cache.
   cache_dir: PathBuf,
+  /// The configuration of the cache.
+  config: CacheConfig,
+  /// Fetches in progress, keyed by the path of the cache entry.
+  flights: SingleFlight<PathBuf, Option<PathBuf>, Error>,
 }
 
 impl CachingClient {
@@ -40,17 +147,29 @@ impl CachingClient {
   /// system's cache directory to share data with other debuginfod aware
   /// programs. Hence, consider using the [`CachingClient::from_env`]
   /// constructor instead.
+  ///
+  /// Build IDs that no server knows about are recorded in the cache as
+  /// well and not looked up again for some time. That time is read
+  /// from the `cache_miss_s` file in `cache_dir`, if present, and
+  /// defaults to ten minutes otherwise. See
+  /// [`CachingClient::with_cache_miss_ttl`] for overriding it.
+  ///
+  /// The cache is cleaned up periodically, at the interval contained in
+  /// the `cache_clean_interval_s` file (one day by default), removing
+  /// entries that have not been used for the time contained in the
+  /// `max_unused_age_s` file (one week by default).
   pub fn new<P>(client: Client, cache_dir: P) -> Result<Self>
   where
     P: AsRef<Path>,
   {
     let cache_dir = cache_dir.as_ref();
-    let () = create_dir_all(cache_dir)
-      .with_context(|| format!("failed to create cache directory `{}`", cache_dir.display()))?;
+    let () = create_dir_all(cache_dir).map_err(|err| cache_error(cache_dir, err))?;
 
     let slf = Self {
       client,
       cache_dir: cache_dir.to_path_buf(),
+      config: CacheConfig::read(cache_dir)?,
+      flights: SingleFlight::default(),
     };
     Ok(slf)
   }
@@ -59,39 +178,207 @@ impl CachingClient {
   /// `DEBUGINFOD_CACHE_PATH` environment variable as the directory at
   /// which fetched debug info files are cached on the file system.
   ///
-  /// If `DEBUGINFOD_CACHE_PATH` is not present, then if
-  /// `XDG_CACHE_HOME` is set `$XDG_CACHE_HOME/debuginfod_client` is
-  /// used and if that is unset as well then
-  /// `$HOME/.cache/debuginfod_client` will be used.
+  /// If `DEBUGINFOD_CACHE_PATH` is not present, then the legacy
+  /// `$HOME/.debuginfod_client_cache` directory is used if it exists.
+  /// Otherwise, if `XDG_CACHE_HOME` is set
+  /// `$XDG_CACHE_HOME/debuginfod_client` is used and if that is unset
+  /// as well then `$HOME/.cache/debuginfod_client` will be used.
   pub fn from_env(client: Client) -> Result<Self> {
-    let cache_path = env::var_os("DEBUGINFOD_CACHE_PATH")
-      .map(PathBuf::from)
-      .or_else(|| cache_dir().map(|dir| dir.join("debuginfod_client")))
-      .or_else(|| home_dir().map(|dir| dir.join(".cache").join("debuginfod_client")))
-      .context("DEBUGINFOD_CACHE_PATH environment variable not found")?;
+    Self::new(client, cache_dir_from_env()?)
+  }
+
+  /// Set the time for which the absence of data on all servers is
+  /// remembered, during which lookups of the data are answered from
+  /// the cache.
+  ///
+  /// A duration of zero disables the caching of misses.
+  pub fn with_cache_miss_ttl(mut self, ttl: Duration) -> Self {
+    self.config.cache_miss = ttl;
+    self
+  }
+
+  /// Set the interval at which the cache is cleaned up.
+  ///
+  /// The time of the last cleanup is shared with other debuginfod
+  /// clients using the same cache directory, but the interval set here
+  /// only applies to this client.
+  pub fn with_clean_interval(mut self, interval: Duration) -> Self {
+    self.config.eviction.interval = interval;
+    self
+  }
 
-    Self::new(client, cache_path)
+  /// Set the time after which cache entries that have not been used
+  /// are removed during a cleanup.
+  ///
+  /// `None` disables the removal of entries based on their age.
+  pub fn with_max_unused_age(mut self, age: Option<Duration>) -> Self {
+    self.config.eviction.max_unused_age = age;
+    self
   }
 
-  #[inline]
-  fn debuginfo_path(&self, build_id: &BuildId) -> PathBuf {
+  /// Set the maximum total size of all cache entries, in bytes.
+  ///
+  /// During a cleanup, the least recently used entries are removed
+  /// until the cache fits within this size. `None`, the default,
+  /// disables the limit.
+  pub fn with_max_size(mut self, size: Option<u64>) -> Self {
+    self.config.eviction.max_size = size;
     self
-      .cache_dir
-      .join(build_id.format().deref())
-      .join("debuginfo")
+  }
+
+  /// Clean up the cache right away, irrespective of the time passed
+  /// since the last cleanup.
+  ///
+  /// Entries are considered used when they were last accessed or
+  /// modified, whichever is more recent. Note that some file systems
+  /// do not maintain, or only lazily update, access times.
+  pub fn clean(&self) -> Result<()> {
+    self.config.eviction.clean(&self.cache_dir)
   }
 
   /// Fetch the debug info for the given build ID.
   pub fn fetch_debug_info(&self, build_id: &BuildId) -> Result<Option<PathBuf>> {
-    let path = self.debuginfo_path(build_id);
-    if path.try_exists()? {
-      debug!("cache hit on `{}`", path.display());
-      return Ok(Some(path))
+    self.fetch(build_id, Artifact::DebugInfo, None)
+  }
+
+  /// Fetch the debug info for the given build ID, aborting once
+  /// `cancel` got cancelled.
+  ///
+  /// See [`Client::fetch_debug_info_with`].
+  pub fn fetch_debug_info_with(
+    &self,
+    build_id: &BuildId,
+    cancel: &CancellationToken,
+  ) -> Result<Option<PathBuf>> {
+    self.fetch(build_id, Artifact::DebugInfo, Some(cancel))
+  }
+
+  /// Fetch the executable for the given build ID.
+  pub fn fetch_executable(&self, build_id: &BuildId) -> Result<Option<PathBuf>> {
+    self.fetch(build_id, Artifact::Executable, None)
+  }
+
+  /// Fetch the executable for the given build ID, aborting once
+  /// `cancel` got cancelled.
+  ///
+  /// See [`Client::fetch_debug_info_with`].
+  pub fn fetch_executable_with(
+    &self,
+    build_id: &BuildId,
+    cancel: &CancellationToken,
+  ) -> Result<Option<PathBuf>> {
+    self.fetch(build_id, Artifact::Executable, Some(cancel))
+  }
+
+  /// Fetch the source file at `path` for the given build ID.
+  ///
+  /// See [`Client::fetch_source`].
+  pub fn fetch_source(&self, build_id: &BuildId, path: &str) -> Result<Option<PathBuf>> {
+    self.fetch(build_id, Artifact::Source(path), None)
+  }
+
+  /// Fetch the source file at `path` for the given build ID, aborting
+  /// once `cancel` got cancelled.
+  ///
+  /// See [`Client::fetch_source`] and [`Client::fetch_debug_info_with`].
+  pub fn fetch_source_with(
+    &self,
+    build_id: &BuildId,
+    path: &str,
+    cancel: &CancellationToken,
+  ) -> Result<Option<PathBuf>> {
+    self.fetch(build_id, Artifact::Source(path), Some(cancel))
+  }
+
+  /// Fetch the ELF section with the given name for the given build ID.
+  ///
+  /// See [`Client::fetch_section`].
+  pub fn fetch_section(&self, build_id: &BuildId, section: &str) -> Result<Option<PathBuf>> {
+    self.fetch(build_id, Artifact::Section(section), None)
+  }
+
+  /// Fetch the ELF section with the given name for the given build ID,
+  /// aborting once `cancel` got cancelled.
+  ///
+  /// See [`Client::fetch_section`] and [`Client::fetch_debug_info_with`].
+  pub fn fetch_section_with(
+    &self,
+    build_id: &BuildId,
+    section: &str,
+    cancel: &CancellationToken,
+  ) -> Result<Option<PathBuf>> {
+    self.fetch(build_id, Artifact::Section(section), Some(cancel))
+  }
+
+  fn fetch(
+    &self,
+    build_id: &BuildId,
+    artifact: Artifact<'_>,
+    cancel: Option<&CancellationToken>,
+  ) -> Result<Option<PathBuf>> {
+    if let Err(err) = self.config.eviction.clean_if_due(&self.cache_dir) {
+      warn!("failed to clean up cache: {err}");
+    }
+
+    let path = entry_path(&self.cache_dir, build_id, &artifact)?;
+    let cancel = self.client.cancel_token(cancel);
+    let cancel = cancel.as_ref();
+    // Concurrent fetches of the same entry within this process share a
+    // single download, including its failure. Should it get cancelled,
+    // a waiter takes over.
+    self.flights.run(path.clone(), cancel, || {
+      self.fetch_entry(build_id, artifact, path, cancel)
+    })
+  }
+
+  fn fetch_entry(
+    &self,
+    build_id: &BuildId,
+    artifact: Artifact<'_>,
+    path: PathBuf,
+    cancel: Option<&CancellationToken>,
+  ) -> Result<Option<PathBuf>> {
+    let lock_path = lock_path(&path);
+    // Only a single process fetches the data for a build ID at any
+    // time, while others wait for the result to show up in the cache.
+    let mut lock = None;
+    loop {
+      match cache_state(&path, path.metadata(), self.config.cache_miss)? {
+        CacheState::Present => {
+          debug!("cache hit on `{}`", path.display());
+          return Ok(Some(path))
+        },
+        CacheState::Missing => {
+          debug!("cached miss on `{}`", path.display());
+          return Ok(None)
+        },
+        CacheState::Expired if lock.is_some() => {
+          let () = remove_file(&path)
+            .or_else(ignore_not_found)
+            .map_err(|err| cache_error(&path, err))?;
+          break
+        },
+        CacheState::Absent if lock.is_some() => break,
+        CacheState::Expired | CacheState::Absent => (),
+      }
+
+      lock = LockFile::try_acquire(&lock_path)?;
+      if lock.is_none() {
+        debug!("waiting for concurrent fetch of `{}`", path.display());
+        if let Some(cancel) = cancel {
+          let () = cancel.sleep(LOCK_POLL_INTERVAL)?;
+        } else {
+          let () = sleep(LOCK_POLL_INTERVAL);
+        }
+      }
     }
 
-    let mut response = if let Some(debug_info) = self.client.fetch_debug_info(build_id)? {
-      debug_info
+    let mut response = if let Some(response) = self.client.fetch(build_id, artifact, cancel)? {
+      response
     } else {
+      if !self.config.cache_miss.is_zero() {
+        let () = record_miss(&path)?;
+      }
       return Ok(None)
     };
 
@@ -99,51 +386,267 @@ impl CachingClient {
     // already, or it may end up on a different device, in which case the
     // `persist` below won't work and we cannot guarantee atomicity.
     let mut tempfile =
-      NamedTempFile::new_in(&self.cache_dir).context("failed to create temporary file")?;
-    let _count = copy(&mut response.data, &mut tempfile)
-      .context("failed to write debug info to file system")?;
+      NamedTempFile::new_in(&self.cache_dir).map_err(|err| cache_error(&self.cache_dir, err))?;
+    // SANITY: We only get here once we hold the lock.
+    let lock = lock.as_mut().unwrap();
+    let () = copy(
+      &mut response.data,
+      tempfile.as_file_mut(),
+      response.server_url,
+      &path,
+      lock,
+    )?;
 
     // SANITY: Our path is guaranteed to always have a parent.
     let dir = path.parent().unwrap();
-    let () = create_dir_all(dir)
-      .with_context(|| format!("failed to create directory `{}`", dir.display()))?;
-
-    let _file = tempfile.persist_noclobber(&path).map_err(|err| {
-      let src_path = err.file.path().to_path_buf();
-      Result::<File, _>::Err(err)
-        .with_context(|| {
-          format!(
-            "failed to move temporary file `{}` to `{}`",
-            src_path.display(),
-            path.display()
-          )
-        })
-        .unwrap_err()
-    })?;
+    let () = create_dir_all(dir).map_err(|err| cache_error(dir, err))?;
 
+    let () = persist(tempfile, &path)?;
     Ok(Some(path))
   }
 }
 
 
+/// Determine the cache directory to use, based on the environment.
+pub(crate) fn cache_dir_from_env() -> Result<PathBuf> {
+  env::var_os("DEBUGINFOD_CACHE_PATH")
+    .map(PathBuf::from)
+    .or_else(|| {
+      home_dir()
+        .map(|dir| dir.join(".debuginfod_client_cache"))
+        .filter(|dir| dir.is_dir())
+    })
+    .or_else(|| cache_dir().map(|dir| dir.join("debuginfod_client")))
+    .or_else(|| home_dir().map(|dir| dir.join(".cache").join("debuginfod_client")))
+    .ok_or_else(|| {
+      Error::invalid_env(
+        "DEBUGINFOD_CACHE_PATH",
+        Some("variable is not set and no default cache directory is known"),
+      )
+    })
+}
+
+
+/// Determine the state of the cache entry at `path`, given the result
+/// of retrieving its metadata.
+///
+/// Misses are represented by empty files, which are considered valid
+/// for `cache_miss`.
+pub(crate) fn cache_state(
+  path: &Path,
+  result: IoResult<Metadata>,
+  cache_miss: Duration,
+) -> Result<CacheState> {
+  let metadata = match result {
+    Ok(metadata) => metadata,
+    Err(err) if err.kind() == ErrorKind::NotFound => return Ok(CacheState::Absent),
+    Err(err) => return Err(cache_error(path, err)),
+  };
+
+  if metadata.len() > 0 {
+    return Ok(CacheState::Present)
+  }
+
+  let modified = metadata.modified().map_err(|err| cache_error(path, err))?;
+  // A modification time in the future is treated as just now.
+  let age = SystemTime::now()
+    .duration_since(modified)
+    .unwrap_or_default();
+  if age < cache_miss {
+    Ok(CacheState::Missing)
+  } else {
+    Ok(CacheState::Expired)
+  }
+}
+
+
+/// Record in the cache that the data at `path` is not available.
+fn record_miss(path: &Path) -> Result<()> {
+  // SANITY: Our path is guaranteed to always have a parent.
+  let dir = path.parent().unwrap();
+  let () = create_dir_all(dir).map_err(|err| cache_error(dir, err))?;
+
+  let () = OpenOptions::new()
+    .write(true)
+    .create_new(true)
+    .open(path)
+    .map(|_file| ())
+    .or_else(ignore_already_exists)
+    .map_err(|err| cache_error(path, err))?;
+  Ok(())
+}
+
+
+/// Ignore an error indicating that a file does not exist.
+pub(crate) fn ignore_not_found(err: IoError) -> IoResult<()> {
+  if err.kind() == ErrorKind::NotFound {
+    Ok(())
+  } else {
+    Err(err)
+  }
+}
+
+
+/// Ignore an error indicating that a file exists already.
+pub(crate) fn ignore_already_exists(err: IoError) -> IoResult<()> {
+  if err.kind() == ErrorKind::AlreadyExists {
+    Ok(())
+  } else {
+    Err(err)
+  }
+}
+
+
+/// Create an [`Error::Cache`] for a failure to access `path`.
+pub(crate) fn cache_error(path: &Path, err: IoError) -> Error {
+  Error::Cache {
+    path: path.to_path_buf(),
+    source: err,
+  }
+}
+
+
+/// Copy all data from `reader`, the body of a response from `url`, to
+/// `writer`, which is destined to end up at `path` in the cache.
+///
+/// In contrast to [`std::io::copy`], this function tells apart errors
+/// reading data from errors writing it. `lock` is refreshed as the copy
+/// progresses.
+fn copy<R, W>(
+  reader: &mut R,
+  writer: &mut W,
+  url: &str,
+  path: &Path,
+  lock: &mut LockFile,
+) -> Result<()>
+where
+  R: Read,
+  W: Write,
+{
+  let mut buffer = vec![0; COPY_BUFFER_SIZE];
+  loop {
+    if let Some(lock_path) = lock.refresh_due() {
+      let () = refresh_lock(lock_path)?;
+    }
+
+    let count = match reader.read(&mut buffer) {
+      Ok(0) => break Ok(()),
+      Ok(count) => count,
+      Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+      Err(err) => break Err(Error::from_read(err, url)),
+    };
+    let () = writer
+      .write_all(&buffer[..count])
+      .map_err(|err| cache_error(path, err))?;
+  }
+}
+
+
+/// Escape `path` for use in the file name of a cache entry, the same
+/// way elfutils (0.192 and later) does in `path_escape`.
+///
+/// The result is the djb2 hash of the full path, followed by as much of
+/// its end as fits, with every byte other than an ASCII alphanumeric,
+/// `.`, `-`, or `_` replaced by `#`.
+fn escape_path(path: &str) -> String {
+  // elfutils hashes an `unsigned long` over (signed) `char`s.
+  let hash = path.bytes().fold(5381u64, |hash, b| {
+    hash
+      .wrapping_shl(5)
+      .wrapping_add(hash)
+      .wrapping_add(b as i8 as u64)
+  });
+  let mut escaped = format!("{hash:08x}-");
+  // The C string is limited to `MAX_ESCAPED_LEN` bytes including the
+  // terminating NUL, and to ten bytes more than the path.
+  let max_len = MAX_ESCAPED_LEN.min(path.len() + 10) - 1;
+  let room = max_len.saturating_sub(escaped.len());
+  let tail = &path.as_bytes()[path.len().saturating_sub(room)..];
+  let () = escaped.extend(tail.iter().map(|&b| {
+    if b.is_ascii_alphanumeric() || matches!(b, b'.' | b'-' | b'_') {
+      char::from(b)
+    } else {
+      '#'
+    }
+  }));
+  escaped
+}
+
+
+/// Retrieve the path at which `artifact` for `build_id` is cached.
+///
+/// The build ID is validated, so that it cannot be used to escape the
+/// cache directory.
+pub(crate) fn entry_path(
+  cache_dir: &Path,
+  build_id: &BuildId,
+  artifact: &Artifact<'_>,
+) -> Result<PathBuf> {
+  let name = match artifact {
+    Artifact::DebugInfo => Cow::Borrowed("debuginfo"),
+    Artifact::Executable => Cow::Borrowed("executable"),
+    Artifact::Source(path) => {
+      // Entries are keyed by the path actually requested, with `.` and
+      // `..` components resolved.
+      let path = format!("/{}", source_path_components(path)?.join("/"));
+      Cow::Owned(format!("source-{}", escape_path(&path)))
+    },
+    Artifact::Section(name) => Cow::Owned(format!("section-{}", escape_path(name))),
+  };
+  let build_id = build_id.normalize()?;
+  Ok(cache_dir.join(build_id.deref()).join(name.deref()))
+}
+
+
+/// Atomically move `tempfile` to `path`, unless a file exists there
+/// already.
+///
+/// An existing file was persisted by a concurrent fetch that broke our
+/// lock, and is just as good as ours.
+pub(crate) fn persist(tempfile: NamedTempFile, path: &Path) -> Result<()> {
+  match tempfile.persist_noclobber(path) {
+    Ok(_file) => Ok(()),
+    Err(err) if err.error.kind() == ErrorKind::AlreadyExists => {
+      debug!("`{}` got persisted concurrently", path.display());
+      Ok(())
+    },
+    Err(err) => Err(cache_error(path, err.error)),
+  }
+}
+
+
 #[cfg(test)]
 mod tests {
   use super::*;
 
-  use std::borrow::Cow;
   use std::ffi::OsStr;
+  use std::fs::read_dir;
+  use std::fs::write;
+  use std::sync::atomic::AtomicUsize;
+  use std::sync::atomic::Ordering;
+  use std::sync::Arc;
+  use std::thread;
 
   use blazesym::symbolize::source::Elf;
   use blazesym::symbolize::source::Source;
   use blazesym::symbolize::Input;
   use blazesym::symbolize::Symbolizer;
 
+  use http::Response as HttpResponse;
+  use http::StatusCode;
+
   use reqwest::blocking::Client as ReqwestBlockingClient;
 
   use tempfile::tempdir;
 
   use test_fork::fork;
 
+  use crate::CancellationToken;
+  use crate::HttpClient;
+  use crate::HttpClientError;
+  use crate::Readable;
+  use crate::SizeLimitExceeded;
+
 
   /// Check that the creation of a `Client` object from information
   /// provided in the environment works as it should.
@@ -181,6 +684,210 @@ mod tests {
     assert_eq!(client.cache_dir, cache_dir.path());
   }
 
+  /// Check that we honor the legacy cache directory when it exists.
+  #[fork]
+  #[test]
+  fn from_env_legacy_dir() {
+    let home = tempdir().unwrap();
+    // SAFETY: `test-fork` ensures that we are in a single-threaded
+    //         context.
+    let () = unsafe { env::remove_var("DEBUGINFOD_CACHE_PATH") };
+    // SAFETY: `test-fork` ensures that we are in a single-threaded
+    //         context.
+    let () = unsafe { env::set_var("HOME", home.path()) };
+    // SAFETY: `test-fork` ensures that we are in a single-threaded
+    //         context.
+    let () = unsafe { env::remove_var("XDG_CACHE_HOME") };
+
+    let path = cache_dir_from_env().unwrap();
+    assert_eq!(path, home.path().join(".cache").join("debuginfod_client"));
+
+    let legacy = home.path().join(".debuginfod_client_cache");
+    let () = create_dir_all(&legacy).unwrap();
+    let path = cache_dir_from_env().unwrap();
+    assert_eq!(path, legacy);
+  }
+
+  /// Check that we lay out cache entries the way elfutils does.
+  #[test]
+  fn entry_paths() {
+    let cache_dir = Path::new("/cache");
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0xab, 0xcd]));
+    let entries = [
+      (Artifact::DebugInfo, "debuginfo"),
+      (Artifact::Executable, "executable"),
+      (
+        Artifact::Source("/usr/src/debug/foo#1/bar.c"),
+        "source-b171d824c6cac657-#debug#foo#1#bar.c",
+      ),
+      (
+        Artifact::Source("/usr/src/./debug/../bar.c"),
+        "source-6fa2f6a714f0361a-#bar.c",
+      ),
+      // Non-ASCII bytes are hashed as signed `char`s.
+      (Artifact::Source("/src/ä.c"), "source-3778219a8153aa3-.c"),
+      (
+        Artifact::Section(".debug_line"),
+        "section-bf58746f02145901-ine",
+      ),
+    ];
+
+    for (artifact, name) in entries {
+      let path = entry_path(cache_dir, &build_id, &artifact).unwrap();
+      assert_eq!(path, cache_dir.join("abcd").join(name));
+    }
+
+    // Relative source paths are rejected, just like elfutils does.
+    let err = entry_path(cache_dir, &build_id, &Artifact::Source("usr/src/bar.c")).unwrap_err();
+    assert!(matches!(err, Error::InvalidPath { .. }), "{err:?}");
+
+    // Long paths are truncated to their end, to stay clear of file name
+    // length limits.
+    let long = format!("/usr/src/debug/{}bär#baz.c", "dir/".repeat(100));
+    let path = entry_path(cache_dir, &build_id, &Artifact::Source(&long)).unwrap();
+    let name = path.file_name().unwrap().to_str().unwrap();
+    let expected = format!("source-147ea4e71f0810ae-ir{}#b##r#baz.c", "#dir".repeat(24));
+    assert_eq!(name, expected);
+    assert_eq!(name.len(), "source-".len() + MAX_ESCAPED_LEN - 1);
+
+    let path = entry_path(
+      cache_dir,
+      &build_id,
+      &Artifact::Source(&format!("/x{long}")),
+    )
+    .unwrap();
+    let name = path.file_name().unwrap().to_str().unwrap();
+    let expected = format!("source-4ad05340ffd5c855-ir{}#b##r#baz.c", "#dir".repeat(24));
+    assert_eq!(name, expected);
+
+    // Formatted build IDs are normalized to lowercase.
+    let build_id = BuildId::formatted("ABCD");
+    let path = entry_path(cache_dir, &build_id, &Artifact::DebugInfo).unwrap();
+    assert_eq!(path, cache_dir.join("abcd").join("debuginfo"));
+  }
+
+  /// Make sure that malformed build IDs cannot be used to access files
+  /// outside of the cache directory.
+  #[test]
+  fn fetch_debug_info_invalid_build_id() {
+    #[derive(Debug)]
+    struct DummyHttpClient;
+
+    impl HttpClient for DummyHttpClient {
+      fn get(&self, url: &str) -> Result<HttpResponse<Box<dyn Readable>>, HttpClientError> {
+        panic!("unexpected request to `{url}`")
+      }
+    }
+
+    let cache_dir = tempdir().unwrap();
+    let urls = ["https://debug.infod/"];
+    let client = Client::builder()
+      .http_client(DummyHttpClient)
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let client = CachingClient::new(client, cache_dir.path()).unwrap();
+
+    let build_id = BuildId::formatted("../../../.ssh");
+    let err = client.fetch_debug_info(&build_id).unwrap_err();
+    assert!(matches!(err, Error::InvalidBuildId(..)), "{err:?}");
+  }
+
+  /// Check that we wait for a concurrent fetch of the same build ID
+  /// instead of issuing requests ourselves.
+  #[test]
+  fn fetch_debug_info_locked() {
+    #[derive(Debug)]
+    struct DummyHttpClient;
+
+    impl HttpClient for DummyHttpClient {
+      fn get(&self, url: &str) -> Result<HttpResponse<Box<dyn Readable>>, HttpClientError> {
+        panic!("unexpected request to `{url}`")
+      }
+    }
+
+    let cache_dir = tempdir().unwrap();
+    let urls = ["https://debug.infod/"];
+    let client = Client::builder()
+      .http_client(DummyHttpClient)
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let client = CachingClient::new(client, cache_dir.path()).unwrap();
+
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let path = entry_path(cache_dir.path(), &build_id, &Artifact::DebugInfo).unwrap();
+    let lock = LockFile::try_acquire(&lock_path(&path)).unwrap().unwrap();
+
+    let handle = thread::spawn({
+      let path = path.clone();
+      move || {
+        let () = sleep(Duration::from_millis(200));
+        let () = write(&path, b"debug info").unwrap();
+        let () = drop(lock);
+      }
+    });
+
+    let fetched = client.fetch_debug_info(&build_id).unwrap().unwrap();
+    assert_eq!(fetched, path);
+    assert_eq!(read_to_string(&fetched).unwrap(), "debug info");
+    let () = handle.join().unwrap();
+    assert!(!lock_path(&path).exists());
+  }
+
+  /// Make sure that an entry persisted concurrently, e.g., by a process
+  /// that broke our lock, is treated as a hit.
+  #[test]
+  fn persist_existing() {
+    let cache_dir = tempdir().unwrap();
+    let path = cache_dir.path().join("debuginfo");
+    let () = write(&path, b"theirs").unwrap();
+
+    let mut tempfile = NamedTempFile::new_in(cache_dir.path()).unwrap();
+    let () = tempfile.write_all(b"ours").unwrap();
+    let tmp_path = tempfile.path().to_path_buf();
+    let () = persist(tempfile, &path).unwrap();
+    assert_eq!(read_to_string(&path).unwrap(), "theirs");
+    assert!(!tmp_path.exists());
+  }
+
+  /// Check that we can fetch and cache source files.
+  #[test]
+  fn fetch_source() {
+    #[derive(Debug)]
+    struct DummyHttpClient;
+
+    impl HttpClient for DummyHttpClient {
+      fn get(&self, url: &str) -> Result<HttpResponse<Box<dyn Readable>>, HttpClientError> {
+        assert!(url.ends_with("/buildid/00/source/usr/src/foo.c"), "{url}");
+        let data: &[u8] = b"int main() {}";
+        Ok(HttpResponse::new(Box::new(data)))
+      }
+    }
+
+    let cache_dir = tempdir().unwrap();
+    let urls = ["https://debuginfod.fedoraproject.org/"];
+    let client = Client::builder()
+      .http_client(DummyHttpClient)
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let client = CachingClient::new(client, cache_dir.path()).unwrap();
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let path = client
+      .fetch_source(&build_id, "/usr/src/foo.c")
+      .unwrap()
+      .unwrap();
+    assert_eq!(
+      path,
+      cache_dir
+        .path()
+        .join("00")
+        .join("source-6fa2f6a7154038a9-#foo.c")
+    );
+    assert_eq!(read_to_string(&path).unwrap(), "int main() {}");
+  }
+
   /// Check that we can successfully fetch debug information.
   #[test]
   fn fetch_debug_info() {
@@ -225,4 +932,175 @@ mod tests {
     let info = client.fetch_debug_info(&build_id).unwrap();
     assert!(info.is_none());
   }
+
+  /// Check that we remember build IDs no server knows about.
+  #[test]
+  fn fetch_debug_info_cached_miss() {
+    #[derive(Debug, Default)]
+    struct DummyHttpClient(Arc<AtomicUsize>);
+
+    impl HttpClient for DummyHttpClient {
+      fn get(&self, _url: &str) -> Result<HttpResponse<Box<dyn Readable>>, HttpClientError> {
+        let _count = self.0.fetch_add(1, Ordering::Relaxed);
+        Err(HttpClientError::StatusCode(StatusCode::NOT_FOUND))
+      }
+    }
+
+    let cache_dir = tempdir().unwrap();
+    let () = write(cache_dir.path().join("cache_miss_s"), "1234\n").unwrap();
+    let urls = ["https://debuginfod.fedoraproject.org/"];
+    let http_client = DummyHttpClient::default();
+    let requests = Arc::clone(&http_client.0);
+    let client = Client::builder()
+      .http_client(http_client)
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let client = CachingClient::new(client, cache_dir.path()).unwrap();
+    assert_eq!(client.config.cache_miss, Duration::from_secs(1234));
+
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let info = client.fetch_debug_info(&build_id).unwrap();
+    assert_eq!(info, None);
+    assert_eq!(requests.load(Ordering::Relaxed), 1);
+    let path = cache_dir.path().join("00").join("debuginfo");
+    assert_eq!(path.metadata().unwrap().len(), 0);
+
+    // The miss should be served from the cache.
+    let info = client.fetch_debug_info(&build_id).unwrap();
+    assert_eq!(info, None);
+    assert_eq!(requests.load(Ordering::Relaxed), 1);
+
+    // Once expired, we should ask the servers again. With caching of
+    // misses disabled, we should not record the miss again.
+    let client = client.with_cache_miss_ttl(Duration::ZERO);
+    let info = client.fetch_debug_info(&build_id).unwrap();
+    assert_eq!(info, None);
+    assert_eq!(requests.load(Ordering::Relaxed), 2);
+    assert!(!path.exists());
+  }
+
+  /// Make sure that we do not persist partial files when a transfer
+  /// exceeds the configured maximum size.
+  #[test]
+  fn fetch_debug_info_too_large() {
+    #[derive(Debug)]
+    struct DummyHttpClient;
+
+    impl HttpClient for DummyHttpClient {
+      fn get(&self, _url: &str) -> Result<HttpResponse<Box<dyn Readable>>, HttpClientError> {
+        let data: &[u8] = b"Debug info!";
+        Ok(HttpResponse::new(Box::new(data)))
+      }
+    }
+
+    let cache_dir = tempdir().unwrap();
+    let urls = ["https://debuginfod.fedoraproject.org/"];
+    let client = Client::builder()
+      .http_client(DummyHttpClient)
+      .max_size(Some(5))
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let client = CachingClient::new(client, cache_dir.path()).unwrap();
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let err = client.fetch_debug_info(&build_id).unwrap_err();
+    assert!(
+      matches!(
+        err,
+        Error::TooLarge(SizeLimitExceeded {
+          limit: 5,
+          size: None
+        })
+      ),
+      "{err:?}"
+    );
+    // Only the record of the last cleanup should be present.
+    let files = read_dir(cache_dir.path())
+      .unwrap()
+      .map(|entry| entry.unwrap().file_name())
+      .collect::<Vec<_>>();
+    assert_eq!(files, [CLEAN_INTERVAL_FILE]);
+  }
+
+  /// Make sure that we do not cache data not matching the requested
+  /// build ID.
+  #[test]
+  fn fetch_debug_info_mismatch() {
+    #[derive(Debug)]
+    struct DummyHttpClient;
+
+    impl HttpClient for DummyHttpClient {
+      fn get(&self, _url: &str) -> Result<HttpResponse<Box<dyn Readable>>, HttpClientError> {
+        let data: &[u8] = b"<html>Debug info!</html>";
+        Ok(HttpResponse::new(Box::new(data)))
+      }
+    }
+
+    let cache_dir = tempdir().unwrap();
+    let urls = ["https://debuginfod.fedoraproject.org/"];
+    let client = Client::builder()
+      .http_client(DummyHttpClient)
+      .verify_build_id(true)
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let client = CachingClient::new(client, cache_dir.path()).unwrap();
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let err = client.fetch_debug_info(&build_id).unwrap_err();
+    assert!(matches!(err, Error::BuildIdMismatch { .. }), "{err:?}");
+    // Neither data nor a miss should have been recorded.
+    let files = read_dir(cache_dir.path())
+      .unwrap()
+      .map(|entry| entry.unwrap().file_name())
+      .collect::<Vec<_>>();
+    assert_eq!(files, [CLEAN_INTERVAL_FILE]);
+  }
+
+  /// Make sure that we clean up after ourselves when a fetch is
+  /// cancelled while data is being transferred.
+  #[test]
+  fn fetch_debug_info_cancelled() {
+    /// A reader cancelling the operation after producing some data.
+    #[derive(Debug)]
+    struct CancellingReader(CancellationToken);
+
+    impl Read for CancellingReader {
+      fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let () = self.0.cancel();
+        buf[0] = 42;
+        Ok(1)
+      }
+    }
+
+    #[derive(Debug)]
+    struct DummyHttpClient(CancellationToken);
+
+    impl HttpClient for DummyHttpClient {
+      fn get(&self, _url: &str) -> Result<HttpResponse<Box<dyn Readable>>, HttpClientError> {
+        let reader = CancellingReader(self.0.clone());
+        Ok(HttpResponse::new(Box::new(reader)))
+      }
+    }
+
+    let cache_dir = tempdir().unwrap();
+    let urls = ["https://debuginfod.fedoraproject.org/"];
+    let cancel = CancellationToken::new();
+    let client = Client::builder()
+      .http_client(DummyHttpClient(cancel.clone()))
+      .cancellation_token(cancel)
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let client = CachingClient::new(client, cache_dir.path()).unwrap();
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let err = client.fetch_debug_info(&build_id).unwrap_err();
+    assert!(matches!(err, Error::Cancelled), "{err:?}");
+    // Only the record of the last cleanup should be present.
+    let files = read_dir(cache_dir.path())
+      .unwrap()
+      .map(|entry| entry.unwrap().file_name())
+      .collect::<Vec<_>>();
+    assert_eq!(files, [CLEAN_INTERVAL_FILE]);
+  }
 }
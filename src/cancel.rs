@@ -0,0 +1,283 @@
+// Copyright (C) 2026 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+use std::error::Error;
+use std::fmt::Debug;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::mem::take;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::Weak;
+use std::time::Duration;
+
+
+/// An error indicating that an operation was cancelled via a
+/// [`CancellationToken`].
+///
+/// Errors reported while reading response data wrap this type in an
+/// [`std::io::Error`], from which it can be retrieved via
+/// [`std::io::Error::get_ref`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl Display for Cancelled {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    f.write_str("operation was cancelled")
+  }
+}
+
+impl Error for Cancelled {}
+
+
+/// A callback invoked once cancellation is requested.
+type Callback = Box<dyn FnOnce() + Send>;
+
+
+#[derive(Default)]
+struct Inner {
+  /// Whether cancellation has been requested.
+  cancelled: bool,
+  /// The callbacks to invoke on cancellation, along with the IDs of
+  /// their registrations.
+  callbacks: Vec<(u64, Callback)>,
+  /// The ID of the next registration.
+  next_id: u64,
+}
+
+impl Debug for Inner {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    let Self {
+      cancelled,
+      callbacks,
+      next_id,
+    } = self;
+
+    f.debug_struct("Inner")
+      .field("cancelled", cancelled)
+      .field("callbacks", &callbacks.len())
+      .field("next_id", next_id)
+      .finish()
+  }
+}
+
+
+#[derive(Debug, Default)]
+struct State {
+  /// The state protected by a lock.
+  inner: Mutex<Inner>,
+  /// The condition variable used for waking up sleepers on
+  /// cancellation.
+  condvar: Condvar,
+  /// Registrations with other tokens cancelling this one along with
+  /// them.
+  links: Mutex<Vec<Registration>>,
+}
+
+
+/// A callback registered with a [`CancellationToken`], unregistered
+/// when dropped.
+#[derive(Debug)]
+pub(crate) struct Registration {
+  /// The state of the token the callback is registered with.
+  state: Weak<State>,
+  /// The ID of the registration.
+  id: u64,
+}
+
+impl Drop for Registration {
+  fn drop(&mut self) {
+    if let Some(state) = self.state.upgrade() {
+      // SANITY: We never panic while holding the lock.
+      let mut inner = state.inner.lock().unwrap();
+      let callback = inner
+        .callbacks
+        .iter()
+        .position(|(id, _callback)| *id == self.id)
+        .map(|idx| inner.callbacks.swap_remove(idx));
+      let () = drop(inner);
+      // The callback is only dropped once we released the lock, in
+      // case dropping it unregisters other callbacks.
+      let () = drop(callback);
+    }
+  }
+}
+
+
+/// A token for cooperatively cancelling in-flight operations.
+///
+/// A token is shared between all its clones: cancelling one cancels
+/// all of them. Once cancelled, a token stays cancelled.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<State>);
+
+impl CancellationToken {
+  /// Create a new, not yet cancelled, token.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Request cancellation of all operations using this token.
+  pub fn cancel(&self) {
+    let callbacks = {
+      // SANITY: We never panic while holding the lock.
+      let mut inner = self.0.inner.lock().unwrap();
+      inner.cancelled = true;
+      take(&mut inner.callbacks)
+    };
+    let () = self.0.condvar.notify_all();
+
+    for (_id, callback) in callbacks {
+      let () = callback();
+    }
+  }
+
+  /// Check whether cancellation has been requested.
+  pub fn is_cancelled(&self) -> bool {
+    // SANITY: We never panic while holding the lock.
+    self.0.inner.lock().unwrap().cancelled
+  }
+
+  /// Fail with [`Cancelled`] if cancellation has been requested.
+  pub(crate) fn check(&self) -> Result<(), Cancelled> {
+    if self.is_cancelled() {
+      Err(Cancelled)
+    } else {
+      Ok(())
+    }
+  }
+
+  /// Sleep for `duration`, waking up early if cancellation is
+  /// requested in the meantime.
+  pub(crate) fn sleep(&self, duration: Duration) -> Result<(), Cancelled> {
+    // SANITY: We never panic while holding the lock.
+    let inner = self.0.inner.lock().unwrap();
+    // SANITY: We never panic while holding the lock.
+    let (inner, _result) = self
+      .0
+      .condvar
+      .wait_timeout_while(inner, duration, |inner| !inner.cancelled)
+      .unwrap();
+    if inner.cancelled {
+      Err(Cancelled)
+    } else {
+      Ok(())
+    }
+  }
+
+  /// Register `callback` to be invoked once cancellation is requested,
+  /// for as long as the returned [`Registration`] is alive.
+  ///
+  /// If cancellation has been requested already, `callback` is invoked
+  /// right away. Callbacks should return quickly, as they are invoked
+  /// on the thread requesting cancellation.
+  pub(crate) fn on_cancel<F>(&self, callback: F) -> Registration
+  where
+    F: FnOnce() + Send + 'static,
+  {
+    // SANITY: We never panic while holding the lock.
+    let mut inner = self.0.inner.lock().unwrap();
+    if inner.cancelled {
+      let () = drop(inner);
+      let () = callback();
+      return Registration {
+        state: Weak::new(),
+        id: 0,
+      }
+    }
+
+    let id = inner.next_id;
+    inner.next_id += 1;
+    let () = inner.callbacks.push((id, Box::new(callback)));
+    Registration {
+      state: Arc::downgrade(&self.0),
+      id,
+    }
+  }
+
+  /// Cancel this token once `other` got cancelled, for as long as this
+  /// token (or one of its clones) is alive.
+  pub(crate) fn link(&self, other: &Self) {
+    let state = Arc::downgrade(&self.0);
+    let registration = other.on_cancel(move || {
+      if let Some(state) = state.upgrade() {
+        let () = Self(state).cancel();
+      }
+    });
+    // SANITY: We never panic while holding the lock.
+    let () = self.0.links.lock().unwrap().push(registration);
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::sync::mpsc::channel;
+  use std::thread;
+  use std::time::Instant;
+
+
+  /// Check that sleeping on a token is interrupted by cancellation.
+  #[test]
+  fn cancel_sleep() {
+    let token = CancellationToken::new();
+    assert_eq!(token.sleep(Duration::from_millis(1)), Ok(()));
+
+    let token_clone = token.clone();
+    let handle = thread::spawn(move || {
+      let () = thread::sleep(Duration::from_millis(50));
+      let () = token_clone.cancel();
+    });
+
+    let now = Instant::now();
+    assert_eq!(token.sleep(Duration::from_secs(30)), Err(Cancelled));
+    assert!(now.elapsed() < Duration::from_secs(15));
+    assert!(token.is_cancelled());
+    assert_eq!(token.check(), Err(Cancelled));
+    let () = handle.join().unwrap();
+  }
+
+  /// Check that callbacks are invoked on cancellation, unless they
+  /// got unregistered.
+  #[test]
+  fn cancel_callbacks() {
+    let token = CancellationToken::new();
+    let (sender, receiver) = channel();
+    let sender_clone = sender.clone();
+    let _registration = token.on_cancel(move || sender_clone.send(1).unwrap());
+    let sender_clone = sender.clone();
+    let registration = token.on_cancel(move || sender_clone.send(2).unwrap());
+    let () = drop(registration);
+
+    let () = token.cancel();
+    assert_eq!(receiver.try_recv(), Ok(1));
+    assert!(receiver.try_recv().is_err());
+
+    // Callbacks registered after the fact are invoked right away.
+    let _registration = token.on_cancel(move || sender.send(3).unwrap());
+    assert_eq!(receiver.try_recv(), Ok(3));
+  }
+
+  /// Make sure that linked tokens are cancelled along with the ones
+  /// they are linked to, but not vice versa.
+  #[test]
+  fn cancel_link() {
+    let parent1 = CancellationToken::new();
+    let parent2 = CancellationToken::new();
+    let token = CancellationToken::new();
+    let () = token.link(&parent1);
+    let () = token.link(&parent2);
+
+    let () = parent2.cancel();
+    assert!(token.is_cancelled());
+    assert!(!parent1.is_cancelled());
+
+    // Links are removed once the token is gone.
+    let () = drop(token);
+    assert!(parent1.0.inner.lock().unwrap().callbacks.is_empty());
+  }
+}
@@ -1,25 +1,65 @@
 // Copyright (C) 2024-2025 Daniel Mueller <deso@posteo.net>
 // SPDX-License-Identifier: (Apache-2.0 OR MIT)
 
-use std::convert::Infallible;
+use std::collections::HashSet;
 use std::env;
+use std::fs::read_to_string;
+use std::io::Cursor;
+use std::io::ErrorKind;
 use std::io::Read;
+use std::path::PathBuf;
+use std::str::FromStr as _;
+use std::sync::mpsc::channel;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
 
-use anyhow::anyhow;
-use anyhow::Context as _;
-use anyhow::Error;
-use anyhow::Result;
-
+use http::header::CONTENT_LENGTH;
+use http::header::LAST_MODIFIED;
+use http::HeaderMap;
+use http::Request as HttpRequest;
+use http::Response as HttpResponse;
 use http::StatusCode;
+
+use httpdate::parse_http_date;
+
 use url::Url;
 
+use crate::elf::check_prefix;
+use crate::elf::BuildIdMismatch;
+use crate::elf::Verdict;
+use crate::elf::MAX_VERIFY_PREFIX;
+use crate::error::Result;
 use crate::log::debug;
 use crate::log::warn;
+use crate::metadata::parse_metadata;
+use crate::progress::ProgressFn;
+use crate::progress::ProgressReporter;
+use crate::retry::send_with_retry;
+use crate::retry::RetryPolicy;
+use crate::single_flight::SingleFlight;
+use crate::transfer::Timeouts;
+use crate::transfer::Transfer;
+use crate::util::parse_env_var;
+use crate::util::parse_header_lines;
 use crate::util::split_env_var_contents;
+use crate::Attempt;
+use crate::AttemptOutcome;
 use crate::BuildId;
+use crate::CancellationToken;
+use crate::Cancelled;
+use crate::Error;
 use crate::HttpClient;
 use crate::HttpClientError;
+use crate::Metadata;
+use crate::MetadataRecord;
+use crate::Progress;
 use crate::Readable;
+use crate::SizeLimitExceeded;
+
 
 /// A successful response from a debuginfod server.
 #[derive(Debug)]
@@ -28,15 +68,251 @@ pub struct Response<'url, R> {
   pub data: R,
   /// The url of the server that had the found debug info.
   pub server_url: &'url str,
+  /// The size of the file, as reported by the server via the
+  /// `X-DEBUGINFOD-SIZE` header.
+  pub size: Option<u64>,
+  /// The path of the file on the server (or inside of `archive`), as
+  /// reported via the `X-DEBUGINFOD-FILE` header.
+  pub file: Option<String>,
+  /// The path of the archive (e.g., an RPM or Debian package) containing
+  /// the file, as reported via the `X-DEBUGINFOD-ARCHIVE` header.
+  pub archive: Option<String>,
+  /// The length of the response body, as reported via the
+  /// `Content-Length` header.
+  pub content_length: Option<u64>,
+  /// The time the file was last modified, as reported via the
+  /// `Last-Modified` header.
+  pub last_modified: Option<SystemTime>,
+  /// All requests made as part of the fetch that concluded, in the
+  /// order of the servers at the configured base URLs.
+  pub attempts: Vec<Attempt>,
 }
 
 /// Creates a new `DebugInfoResponse`.
-impl<'url, R: Read> Response<'url, R> {
-  fn new(data: R, server_url: &'url str) -> Self {
-    Self { data, server_url }
+impl<'url, R> Response<'url, R> {
+  pub(crate) fn new(data: R, server_url: &'url str, headers: &HeaderMap) -> Self {
+    fn header<'h>(headers: &'h HeaderMap, name: &str) -> Option<&'h str> {
+      let value = headers.get(name)?;
+      value
+        .to_str()
+        .map_err(|_err| debug!("ignoring non-ASCII `{name}` header value {value:?}"))
+        .ok()
+    }
+
+    fn header_u64(headers: &HeaderMap, name: &str) -> Option<u64> {
+      let value = header(headers, name)?;
+      u64::from_str(value.trim())
+        .map_err(|_err| debug!("ignoring malformed `{name}` header value `{value}`"))
+        .ok()
+    }
+
+    Self {
+      data,
+      server_url,
+      size: header_u64(headers, "x-debuginfod-size"),
+      file: header(headers, "x-debuginfod-file").map(str::to_string),
+      archive: header(headers, "x-debuginfod-archive").map(str::to_string),
+      content_length: header_u64(headers, CONTENT_LENGTH.as_str()),
+      last_modified: header(headers, LAST_MODIFIED.as_str()).and_then(|value| {
+        parse_http_date(value)
+          .map_err(|_err| debug!("ignoring malformed `Last-Modified` header value `{value}`"))
+          .ok()
+      }),
+      attempts: Vec::new(),
+    }
+  }
+
+  /// Transform the response's data using `f`.
+  pub(crate) fn map_data<F, S>(self, f: F) -> Response<'url, S>
+  where
+    F: FnOnce(R) -> S,
+  {
+    Response {
+      data: f(self.data),
+      server_url: self.server_url,
+      size: self.size,
+      file: self.file,
+      archive: self.archive,
+      content_length: self.content_length,
+      last_modified: self.last_modified,
+      attempts: self.attempts,
+    }
+  }
+}
+
+
+/// A [`Response`] with fully read data, in a form suitable for sharing
+/// between concurrent callers.
+#[derive(Clone, Debug)]
+struct SharedResponse {
+  /// The index of the server that provided the response.
+  server: usize,
+  /// The data the server returned.
+  data: Arc<[u8]>,
+  /// See [`Response::size`].
+  size: Option<u64>,
+  /// See [`Response::file`].
+  file: Option<String>,
+  /// See [`Response::archive`].
+  archive: Option<String>,
+  /// See [`Response::content_length`].
+  content_length: Option<u64>,
+  /// See [`Response::last_modified`].
+  last_modified: Option<SystemTime>,
+  /// See [`Response::attempts`].
+  attempts: Vec<Attempt>,
+}
+
+impl SharedResponse {
+  /// Convert the shared response into a `Response`, with `base_urls`
+  /// being the base URLs of the servers queried.
+  fn into_response(self, base_urls: &[Url]) -> Response<'_, Arc<[u8]>> {
+    Response {
+      data: self.data,
+      server_url: base_urls[self.server].as_str(),
+      size: self.size,
+      file: self.file,
+      archive: self.archive,
+      content_length: self.content_length,
+      last_modified: self.last_modified,
+      attempts: self.attempts,
+    }
+  }
+}
+
+
+/// The kind of artifact to retrieve from a debuginfod server.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Artifact<'path> {
+  /// Debug information, as found in a separate debug info file.
+  DebugInfo,
+  /// The executable (or shared object) itself.
+  Executable,
+  /// A source file, identified by its (typically absolute) path as
+  /// recorded in the debug information.
+  Source(&'path str),
+  /// An ELF section, identified by its name.
+  Section(&'path str),
+}
+
+/// Split the absolute source file path `path` into its components,
+/// resolving `.` and `..` lexically.
+///
+/// Relative paths as well as paths escaping the root directory are
+/// rejected.
+pub(crate) fn source_path_components(path: &str) -> Result<Vec<&str>> {
+  let relative = path
+    .strip_prefix('/')
+    .ok_or_else(|| Error::invalid_path(path, "source path is not absolute"))?;
+
+  let mut components = Vec::new();
+  for component in relative.split('/') {
+    match component {
+      "" | "." => (),
+      ".." => {
+        let _component = components
+          .pop()
+          .ok_or_else(|| Error::invalid_path(path, "source path escapes root directory"))?;
+      },
+      component => components.push(component),
+    }
+  }
+  Ok(components)
+}
+
+
+/// Check that `name` is usable as the name of a section in a request.
+pub(crate) fn check_section_name(name: &str) -> Result<()> {
+  match name {
+    "" | "." | ".." => Err(Error::invalid_path(name, "section name is invalid")),
+    _ => Ok(()),
+  }
+}
+
+
+impl Artifact<'_> {
+  /// Set the path of `url` to the endpoint serving this artifact for the
+  /// given build ID.
+  fn set_url_path(&self, url: &mut Url, build_id: &str) -> Result<()> {
+    let kind = match self {
+      Self::DebugInfo => "debuginfo",
+      Self::Executable => "executable",
+      Self::Source(..) => "source",
+      Self::Section(..) => "section",
+    };
+    let () = url.set_path(&format!("buildid/{build_id}/{kind}"));
+
+    // SANITY: We only ever work with base URLs, which are checked at
+    //         construction time.
+    let mut segments = url.path_segments_mut().unwrap();
+    match self {
+      Self::DebugInfo | Self::Executable => (),
+      Self::Source(path) => {
+        // Source paths are appended component by component, so that
+        // each gets percent-encoded properly while separators are
+        // preserved. The leading slash of absolute paths is implied by
+        // the endpoint. Note that `Url` silently drops `.` and `..`
+        // segments, which is why we resolve them upfront.
+        let _segments = segments.extend(source_path_components(path)?);
+      },
+      Self::Section(name) => {
+        let () = check_section_name(name)?;
+        let _segments = segments.push(name);
+      },
+    }
+    Ok(())
+  }
+
+  /// Build the URLs at which the servers at `base_urls` serve this
+  /// artifact for the given build ID.
+  pub(crate) fn urls(&self, base_urls: &[Url], build_id: &str) -> Result<Vec<Url>> {
+    // The endpoint we contact is `/buildid/<BUILDID>/<ARTIFACT>`.
+    base_urls
+      .iter()
+      .map(|base_url| {
+        let mut url = base_url.clone();
+        let () = self.set_url_path(&mut url, build_id)?;
+        Ok(url)
+      })
+      .collect()
+  }
+
+  /// Retrieve a human readable description of the artifact.
+  pub(crate) fn describe(&self) -> &'static str {
+    match self {
+      Self::DebugInfo => "debug info",
+      Self::Executable => "executable",
+      Self::Source(..) => "source file",
+      Self::Section(..) => "section",
+    }
+  }
+
+  /// Check whether this artifact is an ELF file carrying the build ID
+  /// it is requested by.
+  pub(crate) fn is_elf(&self) -> bool {
+    match self {
+      Self::DebugInfo | Self::Executable => true,
+      Self::Source(..) | Self::Section(..) => false,
+    }
   }
 }
 
+
+/// The strategy used for querying multiple servers.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) enum Strategy {
+  /// Query one server after the other.
+  #[default]
+  Sequential,
+  /// Query all servers at once, using the first successful response.
+  Concurrent,
+  /// Query servers one after the other, but start querying the next
+  /// one in parallel if the previous one did not respond within the
+  /// given delay.
+  Hedged(Duration),
+}
+
+
 /// A client for interacting with (one or more) `debuginfod` servers.
 #[derive(Debug)]
 pub struct Client {
@@ -44,7 +320,25 @@ pub struct Client {
   /// protocol, in decreasing order of importance.
   base_urls: Vec<Url>,
   /// The HTTP client we use for satisfying requests.
-  client: Box<dyn HttpClient + Send + Sync>,
+  client: Arc<dyn HttpClient + Send + Sync>,
+  /// Headers to send along with each request.
+  headers: HeaderMap,
+  /// The strategy used for querying the servers at `base_urls`.
+  strategy: Strategy,
+  /// The policy for retrying failed requests.
+  retry: RetryPolicy,
+  /// Timeouts applying to requests and data transfers.
+  timeouts: Timeouts,
+  /// The maximum size of a response we accept, in bytes.
+  max_size: Option<u64>,
+  /// The callback informed about the progress of downloads.
+  progress: Option<ProgressFn>,
+  /// The token used for cancelling in-flight operations.
+  cancel: Option<CancellationToken>,
+  /// Whether to verify the build ID of downloaded ELF files.
+  verify_build_id: bool,
+  /// Fetches of debug info in progress, keyed by build ID.
+  flights: SingleFlight<String, Option<SharedResponse>, Error>,
 }
 
 impl Client {
@@ -53,6 +347,47 @@ impl Client {
     ClientBuilder::default()
   }
 
+  /// Create a GET request for the provided URL.
+  fn request(&self, url: &Url) -> Result<HttpRequest<()>, HttpClientError> {
+    make_request(url, &self.headers)
+  }
+
+  /// Perform a GET request to the provided URL.
+  fn get(
+    &self,
+    url: &Url,
+    deadline: Option<Instant>,
+    cancel: Option<&CancellationToken>,
+  ) -> Result<HttpResponse<Box<dyn Readable>>, HttpClientError> {
+    let request = self.request(url)?;
+    send_with_retry(
+      &self.client,
+      &request,
+      &self.retry,
+      &self.timeouts,
+      deadline,
+      cancel,
+    )
+  }
+
+  /// Retrieve the token for cancelling an operation, which gets
+  /// cancelled once either the token configured for the client or the
+  /// per-call token `cancel` is.
+  pub(crate) fn cancel_token(
+    &self,
+    cancel: Option<&CancellationToken>,
+  ) -> Option<CancellationToken> {
+    match (&self.cancel, cancel) {
+      (Some(client_cancel), Some(cancel)) => {
+        let token = CancellationToken::new();
+        let () = token.link(client_cancel);
+        let () = token.link(cancel);
+        Some(token)
+      },
+      (client_cancel, cancel) => client_cancel.as_ref().or(cancel).cloned(),
+    }
+  }
+
   /// Fetch the debug info for the given build ID.
   ///
   /// If debug info data is found for the provided build ID, it can be read
@@ -65,65 +400,750 @@ impl Client {
     &self,
     build_id: &BuildId,
   ) -> Result<Option<Response<'_, impl Readable>>> {
-    fn status_to_error(status: StatusCode) -> Error {
-      let reason = status
-        .canonical_reason()
-        .map(|reason| format!(" ({reason})"))
-        .unwrap_or_default();
+    self.fetch(build_id, Artifact::DebugInfo, self.cancel.as_ref())
+  }
 
-      anyhow!("request failed with HTTP status {status}{reason}")
-    }
+  /// Fetch the debug info for the given build ID, aborting once
+  /// `cancel` got cancelled.
+  ///
+  /// In contrast to the token configured via
+  /// [`ClientBuilder::cancellation_token`], `cancel` only applies to
+  /// this fetch, including the reading of the response's data. See
+  /// [`Client::fetch_debug_info`] for details.
+  pub fn fetch_debug_info_with(
+    &self,
+    build_id: &BuildId,
+    cancel: &CancellationToken,
+  ) -> Result<Option<Response<'_, impl Readable>>> {
+    let cancel = self.cancel_token(Some(cancel));
+    self.fetch(build_id, Artifact::DebugInfo, cancel.as_ref())
+  }
+
+  /// Fetch the debug info for the given build ID into memory, sharing
+  /// the download with concurrent callers asking for the same build ID.
+  ///
+  /// In contrast to [`Client::fetch_debug_info`], the data is read in
+  /// full and provided via a shared buffer, to which all callers receive
+  /// a reference. Should the download fail, all callers waiting for it
+  /// receive a copy of the error. Only if it got cancelled does one of
+  /// them try again, on behalf of all of them.
+  pub fn fetch_debug_info_shared(
+    &self,
+    build_id: &BuildId,
+  ) -> Result<Option<Response<'_, Arc<[u8]>>>> {
+    let key = build_id.normalize()?.into_owned();
+    let cancel = self.cancel.as_ref();
+    let shared = self.flights.run(key, cancel, || -> Result<_> {
+      let mut response =
+        if let Some(response) = self.fetch(build_id, Artifact::DebugInfo, cancel)? {
+          response
+        } else {
+          return Ok(None)
+        };
+
+      let mut data = Vec::new();
+      let _count = response
+        .data
+        .read_to_end(&mut data)
+        .map_err(|err| Error::from_read(err, response.server_url))?;
+
+      // SANITY: The server URL is guaranteed to be one of our base
+      //         URLs.
+      let server = self
+        .base_urls
+        .iter()
+        .position(|url| url.as_str() == response.server_url)
+        .unwrap();
+      let shared = SharedResponse {
+        server,
+        data: Arc::from(data),
+        size: response.size,
+        file: response.file,
+        archive: response.archive,
+        content_length: response.content_length,
+        last_modified: response.last_modified,
+        attempts: response.attempts,
+      };
+      Ok(Some(shared))
+    })?;
+
+    Ok(shared.map(|shared| shared.into_response(&self.base_urls)))
+  }
+
+  /// Fetch the executable for the given build ID.
+  ///
+  /// If an executable is found for the provided build ID, it can be read
+  /// from the response's `data` field.
+  ///
+  /// HTTP errors returned by a subset of servers at the base URLs provided
+  /// during construction will be ignored if and only if one of them returned
+  /// data successfully.
+  pub fn fetch_executable(
+    &self,
+    build_id: &BuildId,
+  ) -> Result<Option<Response<'_, impl Readable>>> {
+    self.fetch(build_id, Artifact::Executable, self.cancel.as_ref())
+  }
+
+  /// Fetch the executable for the given build ID, aborting once
+  /// `cancel` got cancelled.
+  ///
+  /// See [`Client::fetch_debug_info_with`].
+  pub fn fetch_executable_with(
+    &self,
+    build_id: &BuildId,
+    cancel: &CancellationToken,
+  ) -> Result<Option<Response<'_, impl Readable>>> {
+    let cancel = self.cancel_token(Some(cancel));
+    self.fetch(build_id, Artifact::Executable, cancel.as_ref())
+  }
+
+  /// Fetch the source file at `path` for the given build ID.
+  ///
+  /// `path` is the absolute path of the source file as recorded in the
+  /// debug information (e.g.,
+  /// `/usr/src/debug/coreutils-9.1/src/sleep.c`). `.` and `..`
+  /// components are resolved lexically, while relative paths are
+  /// rejected with [`Error::InvalidPath`]. If the source file is found,
+  /// it can be read from the response's `data` field.
+  ///
+  /// HTTP errors returned by a subset of servers at the base URLs provided
+  /// during construction will be ignored if and only if one of them returned
+  /// data successfully.
+  pub fn fetch_source(
+    &self,
+    build_id: &BuildId,
+    path: &str,
+  ) -> Result<Option<Response<'_, impl Readable>>> {
+    self.fetch(build_id, Artifact::Source(path), self.cancel.as_ref())
+  }
+
+  /// Fetch the source file at `path` for the given build ID, aborting
+  /// once `cancel` got cancelled.
+  ///
+  /// See [`Client::fetch_source`] and [`Client::fetch_debug_info_with`].
+  pub fn fetch_source_with(
+    &self,
+    build_id: &BuildId,
+    path: &str,
+    cancel: &CancellationToken,
+  ) -> Result<Option<Response<'_, impl Readable>>> {
+    let cancel = self.cancel_token(Some(cancel));
+    self.fetch(build_id, Artifact::Source(path), cancel.as_ref())
+  }
+
+  /// Fetch the ELF section with the given name (e.g., `.debug_line`)
+  /// for the given build ID.
+  ///
+  /// Retrieving an individual section can be considerably cheaper than
+  /// fetching the full debug info file, if only a subset of the data is
+  /// needed. Note that not all debuginfod servers support this endpoint.
+  /// If the section is found, it can be read from the response's `data`
+  /// field. Empty names as well as `.` and `..` are rejected with
+  /// [`Error::InvalidPath`].
+  ///
+  /// HTTP errors returned by a subset of servers at the base URLs provided
+  /// during construction will be ignored if and only if one of them returned
+  /// data successfully.
+  pub fn fetch_section(
+    &self,
+    build_id: &BuildId,
+    section: &str,
+  ) -> Result<Option<Response<'_, impl Readable>>> {
+    self.fetch(build_id, Artifact::Section(section), self.cancel.as_ref())
+  }
+
+  /// Fetch the ELF section with the given name for the given build ID,
+  /// aborting once `cancel` got cancelled.
+  ///
+  /// See [`Client::fetch_section`] and [`Client::fetch_debug_info_with`].
+  pub fn fetch_section_with(
+    &self,
+    build_id: &BuildId,
+    section: &str,
+    cancel: &CancellationToken,
+  ) -> Result<Option<Response<'_, impl Readable>>> {
+    let cancel = self.cancel_token(Some(cancel));
+    self.fetch(build_id, Artifact::Section(section), cancel.as_ref())
+  }
+
+  /// Query the metadata of files known to the debuginfod servers.
+  ///
+  /// `key` determines how `value` is interpreted. Servers currently
+  /// support `file`, for looking up files by their exact path, and
+  /// `glob`, for matching paths against a glob pattern. E.g., a query
+  /// with key `file` and value `/usr/bin/bash` reports the build IDs
+  /// of all `/usr/bin/bash` binaries known.
+  ///
+  /// All servers at the base URLs provided during construction are
+  /// queried and their results merged. HTTP errors returned by a subset
+  /// of servers will be ignored if and only if one of them responded
+  /// successfully, but they render the result incomplete.
+  pub fn query_metadata(&self, key: &str, value: &str) -> Result<Metadata> {
+    let mut query = MetadataQuery::new(key, value);
 
-    let build_id = build_id.format();
-    let mut issue_err = None;
-    let mut server_err = None;
+    let cancel = self.cancel.as_ref();
 
-    // The endpoint we contact is `/buildid/<BUILDID>/debuginfo`.
     for base_url in &self.base_urls {
-      let mut url = base_url.clone();
-      let () = url.set_path(&format!("buildid/{build_id}/debuginfo"));
+      let () = check_cancelled(cancel)?;
+
+      let url = query.url(base_url);
+      debug!("making GET request to {url}");
+
+      let deadline = self.timeouts.deadline();
+      let result = self.get(&url, deadline, cancel).and_then(|response| {
+        let mut data = Vec::new();
+        let _count = Transfer::new(response.into_body(), &self.timeouts, deadline)
+          .cancel(cancel.cloned())
+          .read_to_end(&mut data)
+          .map_err(|err| HttpClientError::Other(Box::new(err)))?;
+        Ok(data)
+      });
+      let () = query.add(&url, result);
+    }
+
+    query.finish()
+  }
+
+  /// Request the provided URLs in the manner dictated by the
+  /// configured strategy, until the first one succeeds.
+  ///
+  /// Returned are the index of the URL that could be retrieved
+  /// successfully along with its response, if any, as well as all errors
+  /// encountered, ordered by URL index. Each is accompanied by the time
+  /// the respective request took.
+  fn request_any(
+    &self,
+    urls: &[Url],
+    deadline: Option<Instant>,
+    cancel: Option<&CancellationToken>,
+  ) -> (
+    Option<(usize, HttpResponse<Box<dyn Readable>>, Duration)>,
+    Vec<(usize, HttpClientError, Duration)>,
+  ) {
+    match self.strategy {
+      Strategy::Sequential => self.request_sequential(urls, deadline, cancel),
+      Strategy::Concurrent => self.request_hedged(urls, Duration::ZERO, deadline, cancel),
+      Strategy::Hedged(delay) => self.request_hedged(urls, delay, deadline, cancel),
+    }
+  }
+
+  fn request_sequential(
+    &self,
+    urls: &[Url],
+    deadline: Option<Instant>,
+    cancel: Option<&CancellationToken>,
+  ) -> (
+    Option<(usize, HttpResponse<Box<dyn Readable>>, Duration)>,
+    Vec<(usize, HttpClientError, Duration)>,
+  ) {
+    let mut errors = Vec::new();
+
+    for (idx, url) in urls.iter().enumerate() {
+      if let Err(err) = check_cancelled(cancel) {
+        let () = errors.push((idx, HttpClientError::Other(Box::new(err)), Duration::ZERO));
+        break
+      }
+
       debug!("making GET request to {url}");
+      let start = Instant::now();
+      match self.get(url, deadline, cancel) {
+        Ok(response) => return (Some((idx, response, start.elapsed())), errors),
+        Err(err) => errors.push((idx, err, start.elapsed())),
+      }
+    }
+    (None, errors)
+  }
+
+  /// Request the provided URLs in parallel, starting the request for
+  /// the next URL once the previous request has failed or has not
+  /// concluded within `delay`.
+  fn request_hedged(
+    &self,
+    urls: &[Url],
+    delay: Duration,
+    deadline: Option<Instant>,
+    cancel: Option<&CancellationToken>,
+  ) -> (
+    Option<(usize, HttpResponse<Box<dyn Readable>>, Duration)>,
+    Vec<(usize, HttpClientError, Duration)>,
+  ) {
+    let (sender, receiver) = channel();
+    let mut errors = Vec::new();
+    let mut response = None;
+    // The index of the next URL to request.
+    let mut next = 0;
+    // The number of requests currently in flight.
+    let mut pending = 0;
+    // The point in time at which we start the next request, even if
+    // requests are still in flight.
+    let mut hedge_time = Instant::now();
+
+    loop {
+      if next < urls.len() && check_cancelled(cancel).is_err() {
+        // Don't start any new requests; the ones in flight will
+        // conclude promptly.
+        next = urls.len();
+      }
+
+      if next < urls.len() && (pending == 0 || Instant::now() >= hedge_time) {
+        let idx = next;
+        let url = &urls[idx];
+        let result = self.request(url).and_then(|request| {
+          let client = Arc::clone(&self.client);
+          let retry = self.retry;
+          let timeouts = self.timeouts;
+          let cancel = cancel.cloned();
+          let sender = sender.clone();
+          debug!("making GET request to {url}");
+          thread::Builder::new()
+            .name(format!("debuginfod-{idx}"))
+            .spawn(move || {
+              let start = Instant::now();
+              let result = send_with_retry(
+                &client,
+                &request,
+                &retry,
+                &timeouts,
+                deadline,
+                cancel.as_ref(),
+              );
+              // The receiver may be gone already if another request
+              // succeeded in the meantime, in which case we just drop
+              // the response.
+              let _result = sender.send((idx, result, start.elapsed()));
+            })
+            .map_err(|err| HttpClientError::Other(Box::new(err)))
+        });
+
+        match result {
+          Ok(_handle) => pending += 1,
+          Err(err) => errors.push((idx, err, Duration::ZERO)),
+        }
+        next += 1;
+        hedge_time = Instant::now() + delay;
+        continue
+      }
+
+      if pending == 0 {
+        break
+      }
+
+      let (idx, result, duration) = if next < urls.len() {
+        let timeout = hedge_time.saturating_duration_since(Instant::now());
+        match receiver.recv_timeout(timeout) {
+          Ok(message) => message,
+          Err(RecvTimeoutError::Timeout) => continue,
+          // SANITY: We hold on to a sender ourselves, so the channel
+          //         can't be disconnected.
+          Err(RecvTimeoutError::Disconnected) => unreachable!(),
+        }
+      } else {
+        // SANITY: We hold on to a sender ourselves, so the channel
+        //         can't be disconnected.
+        receiver.recv().unwrap()
+      };
 
-      let result = self.client.get(url.as_str());
+      pending -= 1;
       match result {
-        Ok(response) => return Ok(Some(Response::new(response, base_url.as_str()))),
-        Err(HttpClientError::StatusCode(StatusCode::NOT_FOUND)) => continue,
-        Err(HttpClientError::StatusCode(s)) => {
-          warn!(
-            "failed to retrieve debug info from `{url}`{}",
-            s.canonical_reason()
-              .map(|s| format!(" {s}"))
-              .unwrap_or_default()
-          );
-          server_err = server_err.or_else(|| Some(status_to_error(s)));
+        Ok(resp) => {
+          response = Some((idx, resp, duration));
+          break
+        },
+        Err(err) => errors.push((idx, err, duration)),
+      }
+    }
+
+    let () = errors.sort_by_key(|(idx, _err, _duration)| *idx);
+    (response, errors)
+  }
+
+  /// Fetch `artifact` for the given build ID, aborting once `cancel`
+  /// got cancelled.
+  pub(crate) fn fetch(
+    &self,
+    build_id: &BuildId,
+    artifact: Artifact,
+    cancel: Option<&CancellationToken>,
+  ) -> Result<Option<Response<'_, impl Readable>>> {
+    let build_id = build_id.normalize()?;
+    let urls = artifact.urls(&self.base_urls, &build_id)?;
+    let deadline = self.timeouts.deadline();
+    let verify = self.verify_build_id && artifact.is_elf();
+    let mut errors = Vec::new();
+    // The indices of the servers that have not been asked yet.
+    let mut candidates = (0..urls.len()).collect::<Vec<_>>();
+
+    let found = loop {
+      let round_urls = candidates
+        .iter()
+        .map(|idx| urls[*idx].clone())
+        .collect::<Vec<_>>();
+      let (response, round_errors) = self.request_any(&round_urls, deadline, cancel);
+      let () = check_cancelled(cancel)?;
+
+      let () = errors.extend(
+        round_errors
+          .into_iter()
+          .map(|(idx, err, duration)| (candidates[idx], err, duration)),
+      );
+      let (idx, response, duration) = match response {
+        Some((idx, response, duration)) => (candidates[idx], response, duration),
+        None => break None,
+      };
+
+      let (parts, data) = response.into_parts();
+      let data = Transfer::new(data, &self.timeouts, deadline)
+        .max_size(self.max_size)
+        .cancel(cancel.cloned());
+      let mut response = Response::new(data, self.base_urls[idx].as_str(), &parts.headers);
+      let progress = progress_reporter(self.progress.as_ref(), &response, &build_id);
+      response.data = response.data.progress(progress);
+      let () = check_size(&response, self.max_size)?;
+
+      let prefix = if verify {
+        if let Some(prefix) = verify_build_id(&mut response.data, &build_id, response.server_url)? {
+          prefix
+        } else {
+          let () = errors.push((
+            idx,
+            HttpClientError::Other(Box::new(BuildIdMismatch)),
+            duration,
+          ));
+          // Fall through to the servers not asked so far.
+          let () = candidates.retain(|idx| errors.iter().all(|(asked, ..)| asked != idx));
           continue
+        }
+      } else {
+        Vec::new()
+      };
+
+      // Data we read for verification is handed out before the rest.
+      let response = response.map_data(|data| Cursor::new(prefix).chain(data));
+      break Some((idx, duration, response))
+    };
+
+    let success = found
+      .as_ref()
+      .map(|(idx, duration, _response)| (*idx, *duration));
+    let attempts = fetch_outcome(&artifact, &self.base_urls, &urls, success, errors)?;
+    let response = found.map(|(_idx, _duration, mut response)| {
+      response.attempts = attempts;
+      response
+    });
+    Ok(response)
+  }
+}
+
+
+/// Read the beginning of `reader`, the data of a response from `url`,
+/// and check whether it is an ELF file with build ID `build_id`.
+///
+/// Returned is the data read if the build ID matches (or could not be
+/// located), or `None` if it does not.
+fn verify_build_id<R>(reader: &mut R, build_id: &str, url: &str) -> Result<Option<Vec<u8>>>
+where
+  R: Read,
+{
+  let mut prefix = Vec::new();
+  let mut eof = false;
+
+  loop {
+    match check_prefix(&prefix, eof, build_id) {
+      Verdict::Match => break Ok(Some(prefix)),
+      Verdict::Mismatch => break Ok(None),
+      Verdict::Unknown => {
+        debug!("failed to locate build ID in data from `{url}`; skipping verification");
+        break Ok(Some(prefix))
+      },
+      Verdict::NeedMore => {
+        let len = prefix.len();
+        let () = prefix.resize((len * 2).clamp(4096, MAX_VERIFY_PREFIX), 0);
+        let count = loop {
+          match reader.read(&mut prefix[len..]) {
+            Ok(count) => break count,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(Error::from_read(err, url)),
+          }
+        };
+        let () = prefix.truncate(len + count);
+        eof = count == 0;
+      },
+    }
+  }
+}
+
+
+/// The state of a metadata query, accumulating the results reported by
+/// the individual servers.
+#[derive(Debug)]
+pub(crate) struct MetadataQuery<'query> {
+  /// The key of the query.
+  key: &'query str,
+  /// The value of the query.
+  value: &'query str,
+  /// The merged metadata reported so far.
+  metadata: Metadata,
+  /// The records reported so far, for detecting duplicates.
+  seen: HashSet<MetadataRecord>,
+  /// Whether any server responded successfully.
+  succeeded: bool,
+  /// The first error reported by a server.
+  server_err: Option<Error>,
+  /// The first error encountered issuing a request.
+  issue_err: Option<Error>,
+}
+
+impl<'query> MetadataQuery<'query> {
+  pub(crate) fn new(key: &'query str, value: &'query str) -> Self {
+    Self {
+      key,
+      value,
+      metadata: Metadata {
+        records: Vec::new(),
+        complete: true,
+      },
+      seen: HashSet::new(),
+      succeeded: false,
+      server_err: None,
+      issue_err: None,
+    }
+  }
+
+  /// Build the URL at which the server at `base_url` answers the query.
+  pub(crate) fn url(&self, base_url: &Url) -> Url {
+    // The endpoint we contact is `/metadata?key=<KEY>&value=<VALUE>`.
+    let mut url = base_url.clone();
+    let () = url.set_path("metadata");
+    let _query = url
+      .query_pairs_mut()
+      .clear()
+      .append_pair("key", self.key)
+      .append_pair("value", self.value);
+    url
+  }
+
+  /// Add the result of querying `url`.
+  pub(crate) fn add(&mut self, url: &Url, result: Result<Vec<u8>, HttpClientError>) {
+    match result {
+      Ok(data) => match parse_metadata(&data) {
+        Ok((records, complete)) => {
+          self.succeeded = true;
+          self.metadata.complete &= complete;
+          let seen = &mut self.seen;
+          let () = self.metadata.records.extend(
+            records
+              .into_iter()
+              .filter(|record| seen.insert(record.clone())),
+          );
         },
         Err(err) => {
-          warn!("failed to issue GET request `{url}`: {err}");
-          // `anyhow` only lets us add context to `Result`s, not to errors.
-          // So temporarily wrap in a `Result`.
-          let err = Err::<Infallible, _>(err)
-            .with_context(|| format!("failed to issue request to `{url}`"))
-            .unwrap_err();
-          issue_err = issue_err.or_else(|| Some(err));
-          continue
+          warn!("received invalid metadata from `{url}`: {err}");
+          let err = Error::InvalidMetadata {
+            url: url.to_string(),
+            source: err,
+          };
+          self.server_err = self.server_err.take().or(Some(err));
         },
-      };
+      },
+      // Servers not supporting the endpoint report it as not found.
+      // We have no choice but to treat the result as incomplete.
+      Err(HttpClientError::StatusCode(StatusCode::NOT_FOUND)) => self.metadata.complete = false,
+      Err(HttpClientError::StatusCode(s)) => {
+        warn!(
+          "failed to query metadata from `{url}`{}",
+          s.canonical_reason()
+            .map(|s| format!(" {s}"))
+            .unwrap_or_default()
+        );
+        self.server_err = self.server_err.take().or_else(|| {
+          Some(Error::from_http(
+            HttpClientError::StatusCode(s),
+            url.as_str(),
+          ))
+        });
+      },
+      Err(err) => {
+        warn!("failed to issue GET request `{url}`: {err}");
+        self.issue_err = self
+          .issue_err
+          .take()
+          .or_else(|| Some(Error::from_http(err, url.as_str())));
+      },
     }
+  }
 
-    if let Some(err) = server_err.or(issue_err) {
-      Err(err).with_context(|| format!("failed to fetch debug info for build ID `{build_id}`"))
-    } else {
-      Ok(None)
+  /// Conclude the query, producing the merged metadata.
+  pub(crate) fn finish(self) -> Result<Metadata> {
+    let mut metadata = self.metadata;
+    match self.server_err.or(self.issue_err) {
+      Some(err) if !self.succeeded => Err(err),
+      Some(_err) => {
+        metadata.complete = false;
+        Ok(metadata)
+      },
+      None => Ok(metadata),
+    }
+  }
+}
+
+
+/// Fail with [`Cancelled`] if `cancel` got cancelled.
+fn check_cancelled(cancel: Option<&CancellationToken>) -> Result<(), Cancelled> {
+  cancel.map_or(Ok(()), CancellationToken::check)
+}
+
+
+/// Create a GET request for the provided URL, including `headers`.
+pub(crate) fn make_request(
+  url: &Url,
+  headers: &HeaderMap,
+) -> Result<HttpRequest<()>, HttpClientError> {
+  let mut request = HttpRequest::get(url.as_str())
+    .body(())
+    .map_err(|err| HttpClientError::InvalidUrl(Box::new(err)))?;
+  *request.headers_mut() = headers.clone();
+  Ok(request)
+}
+
+
+/// Condense the outcome of requests to `urls` for `artifact`, served by
+/// the servers at `base_urls`, into the list of attempts made or, if
+/// no request succeeded, the error to report.
+///
+/// Servers not knowing the artifact are not considered to have failed
+/// and errors reported by servers take precedence over errors issuing
+/// requests.
+pub(crate) fn fetch_outcome(
+  artifact: &Artifact<'_>,
+  base_urls: &[Url],
+  urls: &[Url],
+  success: Option<(usize, Duration)>,
+  errors: Vec<(usize, HttpClientError, Duration)>,
+) -> Result<Vec<Attempt>> {
+  let mut attempts = Vec::with_capacity(errors.len() + 1);
+  let mut issue_err = None;
+  let mut server_err = None;
+
+  for (idx, err, duration) in errors {
+    let url = &urls[idx];
+    let attempt = Attempt {
+      server_url: base_urls[idx].to_string(),
+      outcome: AttemptOutcome::from_error(&err),
+      duration,
+    };
+    let () = attempts.push((idx, attempt));
+
+    match err {
+      HttpClientError::StatusCode(StatusCode::NOT_FOUND) => (),
+      HttpClientError::StatusCode(s) => {
+        warn!(
+          "failed to retrieve {} from `{url}`{}",
+          artifact.describe(),
+          s.canonical_reason()
+            .map(|s| format!(" {s}"))
+            .unwrap_or_default()
+        );
+        server_err = server_err.or_else(|| {
+          Some(Error::from_http(
+            HttpClientError::StatusCode(s),
+            url.as_str(),
+          ))
+        });
+      },
+      HttpClientError::Other(err) if err.is::<BuildIdMismatch>() => {
+        warn!(
+          "{} retrieved from `{url}` does not match the requested build ID",
+          artifact.describe()
+        );
+        server_err =
+          server_err.or_else(|| Some(Error::from_http(HttpClientError::Other(err), url.as_str())));
+      },
+      err => {
+        warn!("failed to issue GET request `{url}`: {err}");
+        issue_err = issue_err.or_else(|| Some(Error::from_http(err, url.as_str())));
+      },
+    }
+  }
+
+  if let Some((idx, duration)) = success {
+    let attempt = Attempt {
+      server_url: base_urls[idx].to_string(),
+      outcome: AttemptOutcome::Success,
+      duration,
+    };
+    let () = attempts.push((idx, attempt));
+  }
+
+  let () = attempts.sort_by_key(|(idx, _attempt)| *idx);
+  let attempts = attempts
+    .into_iter()
+    .map(|(_idx, attempt)| attempt)
+    .collect();
+
+  match server_err.or(issue_err) {
+    Some(err) if success.is_none() => Err(err.with_attempts(attempts)),
+    _ => Ok(attempts),
+  }
+}
+
+
+/// Create a reporter for the progress of reading the data of
+/// `response`, if a `progress` callback is present.
+pub(crate) fn progress_reporter<R>(
+  progress: Option<&ProgressFn>,
+  response: &Response<'_, R>,
+  build_id: &str,
+) -> Option<ProgressReporter> {
+  progress.map(|progress| {
+    let total = response.size.or(response.content_length);
+    ProgressReporter::new(progress.clone(), response.server_url, build_id, total)
+  })
+}
+
+
+/// Check that the advertised size of `response` does not exceed
+/// `max_size`.
+pub(crate) fn check_size<R>(response: &Response<'_, R>, max_size: Option<u64>) -> Result<()> {
+  if let Some(max_size) = max_size {
+    let size = response.size.max(response.content_length);
+    if size > Some(max_size) {
+      warn!(
+        "response from `{}` of size {} exceeds limit of {max_size} bytes",
+        response.server_url,
+        size.unwrap_or_default(),
+      );
+      let err = SizeLimitExceeded {
+        limit: max_size,
+        size,
+      };
+      return Err(Error::TooLarge(err))
     }
   }
+  Ok(())
 }
 
 /// A builder for `Client` objects. Create via `Client::builder()`.
 #[derive(Debug, Default)]
 pub struct ClientBuilder<C = ()> {
   /// The HTTP client we use for satisfying requests.
-  client: C,
+  pub(crate) client: C,
+  /// Headers to send along with each request.
+  pub(crate) headers: HeaderMap,
+  /// The strategy used for querying multiple servers.
+  pub(crate) strategy: Strategy,
+  /// The policy for retrying failed requests.
+  pub(crate) retry: RetryPolicy,
+  /// Timeouts applying to requests and data transfers.
+  pub(crate) timeouts: Timeouts,
+  /// The maximum size of a response we accept, in bytes.
+  pub(crate) max_size: Option<u64>,
+  /// The callback informed about the progress of downloads.
+  pub(crate) progress: Option<ProgressFn>,
+  /// The token used for cancelling in-flight operations.
+  pub(crate) cancel: Option<CancellationToken>,
+  /// Whether to verify the build ID of downloaded ELF files.
+  pub(crate) verify_build_id: bool,
 }
 
 impl ClientBuilder<()> {
@@ -132,36 +1152,229 @@ impl ClientBuilder<()> {
   where
     C: HttpClient + 'static,
   {
-    ClientBuilder { client }
+    self.with_client(client)
+  }
+
+  /// Replace the (absent) HTTP client with `client`.
+  pub(crate) fn with_client<C>(self, client: C) -> ClientBuilder<C> {
+    ClientBuilder {
+      client,
+      headers: self.headers,
+      strategy: self.strategy,
+      retry: self.retry,
+      timeouts: self.timeouts,
+      max_size: self.max_size,
+      progress: self.progress,
+      cancel: self.cancel,
+      verify_build_id: self.verify_build_id,
+    }
   }
 }
 
-impl<C> ClientBuilder<C>
-where
-  C: HttpClient + Send + Sync + 'static,
-{
-  /// Build a new `Client` able to speak the debuginfod protocol.
+impl<C> ClientBuilder<C> {
+  /// Set additional headers to send along with each request.
   ///
-  /// The provided `base_urls` is a list of URLs in decreasing order of
-  /// importance. `Ok(None)` will be returned if this list is empty. If
-  /// any of the URLs could not be parsed, an error will be emitted.
-  pub fn build<'url, U>(self, base_urls: U) -> Result<Option<Client>>
-  where
-    U: IntoIterator<Item = &'url str>,
-  {
-    let base_urls = base_urls
-      .into_iter()
-      .map(|url| Url::parse(url.trim()).with_context(|| format!("failed to parse URL `{url}`")))
-      .collect::<Result<Vec<_>>>()?;
+  /// When building from the environment, headers read from the file
+  /// referenced by `DEBUGINFOD_HEADERS_FILE` are appended to these. A
+  /// header present in both is sent with all of its values.
+  pub fn headers(mut self, headers: HeaderMap) -> Self {
+    self.headers = headers;
+    self
+  }
 
-    if base_urls.is_empty() {
-      return Ok(None);
-    }
-    debug!("using debuginfod URLs: {base_urls:#?}");
+  /// Enable or disable concurrent querying of servers.
+  ///
+  /// By default, servers are queried one after the other, in the order
+  /// in which they were provided. When concurrent querying is enabled,
+  /// requests to all servers are issued at once (each on a separate
+  /// thread) and the first successful response is used. Responses
+  /// arriving later are dropped.
+  pub fn concurrent(mut self, concurrent: bool) -> Self {
+    self.strategy = if concurrent {
+      Strategy::Concurrent
+    } else {
+      Strategy::Sequential
+    };
+    self
+  }
+
+  /// Set the delay after which requests are hedged.
+  ///
+  /// With hedging, servers are queried in order, but if a server has
+  /// not responded within `delay`, the next one is queried in parallel
+  /// (and so on). The first successful response is used. This can cut
+  /// down on tail latencies without putting load on all servers for
+  /// every request, as [`ClientBuilder::concurrent`] does.
+  ///
+  /// This setting and [`ClientBuilder::concurrent`] are mutually
+  /// exclusive: whichever is set last takes effect.
+  pub fn hedge_delay(mut self, delay: Duration) -> Self {
+    self.strategy = Strategy::Hedged(delay);
+    self
+  }
+
+  /// Set the maximum number of times a failed request to a server is
+  /// retried, before moving on to the next server.
+  ///
+  /// Only transient failures are retried: connection problems as well
+  /// as server errors (HTTP status 5xx) and rate limiting (HTTP status
+  /// 429). Custom [`HttpClient`] implementations report connection
+  /// problems as [`std::io::Error`] of kind `ConnectionReset`,
+  /// `ConnectionRefused`, `ConnectionAborted`, `TimedOut`, or
+  /// `UnexpectedEof`. Retries are spaced out with exponential backoff,
+  /// unless the server asks for a specific delay via the `Retry-After`
+  /// header.
+  ///
+  /// The default is to not retry failed requests. When building from
+  /// the environment, the limit may be overwritten by the
+  /// `DEBUGINFOD_RETRY_LIMIT` variable.
+  pub fn retry_limit(mut self, limit: u32) -> Self {
+    self.retry.limit = limit;
+    self
+  }
+
+  /// Set the base delay for the exponential backoff between retries.
+  ///
+  /// The delay is doubled for every retry, and some random jitter
+  /// applied. The default base delay is 250ms.
+  pub fn retry_backoff(mut self, backoff: Duration) -> Self {
+    self.retry.backoff = backoff;
+    self
+  }
+
+  /// Set the maximum time to wait for a server to respond to a request.
+  ///
+  /// Despite its name, the timeout covers not only establishing the
+  /// connection, but everything up to the arrival of the response
+  /// headers, as that is all that can be observed for arbitrary
+  /// `HttpClient` implementations. Servers failing to respond in time
+  /// are treated as unreachable. By default, no timeout is applied.
+  pub fn connect_timeout(mut self, timeout: Option<Duration>) -> Self {
+    self.timeouts.response = timeout;
+    self
+  }
+
+  /// Set the maximum time a transfer of data may make no progress,
+  /// before it is aborted.
+  ///
+  /// The timeout is enforced for all `HttpClient` implementations, even
+  /// while a read is blocked. By default, no timeout is applied.
+  pub fn low_speed_timeout(mut self, timeout: Option<Duration>) -> Self {
+    self.timeouts.low_speed = timeout;
+    self
+  }
+
+  /// Set the maximum time a fetch may take overall, including all
+  /// requests and the transfer of data.
+  ///
+  /// Transfers not concluding in time are aborted. By default, no limit
+  /// is applied.
+  pub fn max_time(mut self, max_time: Option<Duration>) -> Self {
+    self.timeouts.max_time = max_time;
+    self
+  }
+
+  /// Set the maximum size of a response to accept, in bytes.
+  ///
+  /// Responses whose advertised size exceeds the limit are rejected
+  /// upfront. Reading data beyond the limit fails with an error wrapping
+  /// [`SizeLimitExceeded`]. By default, no limit is applied.
+  pub fn max_size(mut self, max_size: Option<u64>) -> Self {
+    self.max_size = max_size;
+    self
+  }
+
+  /// Enable or disable verification of the build ID of downloaded debug
+  /// info and executables.
+  ///
+  /// With verification enabled, the beginning of the data is inspected
+  /// for the ELF build ID note before a response is handed out. Servers
+  /// providing data that is not an ELF file or carries a different build
+  /// ID are skipped, and the remaining ones are asked instead. Files
+  /// with a build ID note not located within their first megabyte are
+  /// accepted without verification. Verification is disabled by
+  /// default.
+  pub fn verify_build_id(mut self, verify: bool) -> Self {
+    self.verify_build_id = verify;
+    self
+  }
+
+  /// Register a callback to be informed about the progress of
+  /// downloads.
+  ///
+  /// The callback is invoked repeatedly as data of a [`Response`] is
+  /// read, reporting the number of bytes received so far, the total
+  /// number of bytes (if known), as well as the server and build ID
+  /// the data belongs to. It should return quickly, as it is invoked
+  /// synchronously during the transfer.
+  ///
+  /// When building from the environment and no callback has been
+  /// registered, setting `DEBUGINFOD_PROGRESS` enables a built-in
+  /// reporter printing progress to stderr.
+  pub fn progress<F>(mut self, callback: F) -> Self
+  where
+    F: Fn(&Progress<'_>) + Send + Sync + 'static,
+  {
+    self.progress = Some(ProgressFn::new(callback));
+    self
+  }
+
+  /// Set a token for cancelling in-flight operations.
+  ///
+  /// Once `cancel` is cancelled, pending requests are abandoned, no
+  /// further servers are contacted, and reading response data fails.
+  /// Operations fail with an error wrapping [`Cancelled`] in this case.
+  ///
+  /// The token applies to all operations of the client and, as tokens
+  /// stay cancelled, renders the client unusable once cancelled. To
+  /// cancel individual fetches instead, provide a token to the
+  /// respective call, e.g., [`Client::fetch_debug_info_with`].
+  ///
+  /// Cancellation is checked between reads of response data. Waiting
+  /// for a response or a blocked read is only stopped right away if a
+  /// timeout is configured as well (see
+  /// [`ClientBuilder::connect_timeout`],
+  /// [`ClientBuilder::low_speed_timeout`], and
+  /// [`ClientBuilder::max_time`]), in which case requests are issued on
+  /// a worker thread, or if the `HttpClient` implementation honors the
+  /// token, which it receives as a request extension.
+  pub fn cancellation_token(mut self, cancel: CancellationToken) -> Self {
+    self.cancel = Some(cancel);
+    self
+  }
+}
+
+impl<C> ClientBuilder<C>
+where
+  C: HttpClient + Send + Sync + 'static,
+{
+  /// Build a new `Client` able to speak the debuginfod protocol.
+  ///
+  /// The provided `base_urls` is a list of URLs in decreasing order of
+  /// importance. `Ok(None)` will be returned if this list is empty. If
+  /// any of the URLs could not be parsed, an error will be emitted.
+  pub fn build<'url, U>(self, base_urls: U) -> Result<Option<Client>>
+  where
+    U: IntoIterator<Item = &'url str>,
+  {
+    let base_urls = if let Some(base_urls) = parse_base_urls(base_urls)? {
+      base_urls
+    } else {
+      return Ok(None)
+    };
 
     let slf = Client {
       base_urls,
-      client: Box::new(self.client),
+      client: Arc::new(self.client),
+      headers: self.headers,
+      strategy: self.strategy,
+      retry: self.retry,
+      timeouts: self.timeouts,
+      max_size: self.max_size,
+      progress: self.progress,
+      cancel: self.cancel,
+      verify_build_id: self.verify_build_id,
+      flights: SingleFlight::default(),
     };
     Ok(Some(slf))
   }
@@ -172,19 +1385,127 @@ where
   /// If `DEBUGINFOD_URLS` is not present or empty, `Ok(None)` will be
   /// returned. If the variable contents could not be parsed, an error
   /// will be emitted.
-  pub fn build_from_env(self) -> Result<Option<Client>> {
-    let urls_str = if let Some(urls_str) = env::var_os("DEBUGINFOD_URLS") {
-      urls_str
+  ///
+  /// If `DEBUGINFOD_HEADERS_FILE` is set, additional headers to send
+  /// along with each request are read from the file it refers to. The
+  /// file is expected to contain one `Name: value` header per line.
+  /// Malformed lines are ignored.
+  ///
+  /// If `DEBUGINFOD_RETRY_LIMIT` is set, it determines the maximum
+  /// number of retries of failed requests (see
+  /// [`ClientBuilder::retry_limit`]).
+  ///
+  /// If `DEBUGINFOD_TIMEOUT` is set, it is interpreted as the number of
+  /// seconds used for both the connect and low speed timeouts (see
+  /// [`ClientBuilder::connect_timeout`] and
+  /// [`ClientBuilder::low_speed_timeout`]). `DEBUGINFOD_MAXTIME`, if
+  /// set, determines the maximum number of seconds a fetch may take
+  /// (see [`ClientBuilder::max_time`]). A value of zero disables the
+  /// respective timeouts.
+  ///
+  /// If `DEBUGINFOD_MAXSIZE` is set, it determines the maximum size of a
+  /// response to accept, in bytes (see [`ClientBuilder::max_size`]). A
+  /// value of zero disables the limit.
+  ///
+  /// If `DEBUGINFOD_PROGRESS` is set and no callback has been
+  /// registered via [`ClientBuilder::progress`], the progress of
+  /// downloads is reported on stderr.
+  pub fn build_from_env(mut self) -> Result<Option<Client>> {
+    let urls = if let Some(urls) = self.apply_env()? {
+      urls
+    } else {
+      return Ok(None)
+    };
+    self.build(split_env_var_contents(&urls))
+  }
+}
+
+impl<C> ClientBuilder<C> {
+  /// Apply the configuration found in the environment, returning the
+  /// contents of `DEBUGINFOD_URLS`, if present.
+  pub(crate) fn apply_env(&mut self) -> Result<Option<String>> {
+    let urls = if let Some(urls) = env::var_os("DEBUGINFOD_URLS") {
+      urls
     } else {
       return Ok(None);
     };
 
-    let urls_str = urls_str
-      .to_str()
-      .context("DEBUGINFOD_URLS does not contain valid Unicode")?;
-    let urls = split_env_var_contents(urls_str);
-    self.build(urls)
+    let urls = urls.into_string().map_err(|_urls| {
+      Error::invalid_env(
+        "DEBUGINFOD_URLS",
+        Some("value does not contain valid Unicode"),
+      )
+    })?;
+
+    if let Some(path) = env::var_os("DEBUGINFOD_HEADERS_FILE") {
+      let contents = read_to_string(&path).map_err(|err| Error::Io {
+        path: PathBuf::from(&path),
+        source: err,
+      })?;
+      // Headers from the file are appended, so as to not replace any
+      // configured on the builder.
+      for (name, value) in &parse_header_lines(&contents) {
+        let _existed = self.headers.append(name, value.clone());
+      }
+    }
+
+    if let Some(limit) = env::var_os("DEBUGINFOD_RETRY_LIMIT") {
+      self.retry.limit = parse_env_var("DEBUGINFOD_RETRY_LIMIT", &limit)?;
+    }
+
+    if let Some(timeout) = env::var_os("DEBUGINFOD_TIMEOUT") {
+      let timeout = parse_env_var("DEBUGINFOD_TIMEOUT", &timeout)?;
+      let timeout = (timeout != 0).then(|| Duration::from_secs(timeout));
+      self.timeouts.response = timeout;
+      self.timeouts.low_speed = timeout;
+    }
+
+    if let Some(max_time) = env::var_os("DEBUGINFOD_MAXTIME") {
+      let max_time = parse_env_var("DEBUGINFOD_MAXTIME", &max_time)?;
+      self.timeouts.max_time = (max_time != 0).then(|| Duration::from_secs(max_time));
+    }
+
+    if let Some(max_size) = env::var_os("DEBUGINFOD_MAXSIZE") {
+      let max_size = parse_env_var("DEBUGINFOD_MAXSIZE", &max_size)?;
+      self.max_size = (max_size != 0).then_some(max_size);
+    }
+
+    if env::var_os("DEBUGINFOD_PROGRESS").is_some() && self.progress.is_none() {
+      self.progress = Some(ProgressFn::stderr());
+    }
+
+    Ok(Some(urls))
+  }
+}
+
+
+/// Parse the provided base URLs, returning `None` if there are none.
+pub(crate) fn parse_base_urls<'url, U>(base_urls: U) -> Result<Option<Vec<Url>>>
+where
+  U: IntoIterator<Item = &'url str>,
+{
+  let base_urls = base_urls
+    .into_iter()
+    .map(|url| {
+      Url::parse(url.trim()).map_err(|err| Error::InvalidUrl {
+        url: url.to_string(),
+        source: Box::new(err),
+      })
+    })
+    .collect::<Result<Vec<_>>>()?;
+
+  if let Some(url) = base_urls.iter().find(|url| url.cannot_be_a_base()) {
+    return Err(Error::InvalidUrl {
+      url: url.to_string(),
+      source: "URL cannot be used as a base URL".into(),
+    })
+  }
+
+  if base_urls.is_empty() {
+    return Ok(None);
   }
+  debug!("using debuginfod URLs: {base_urls:#?}");
+  Ok(Some(base_urls))
 }
 
 
@@ -193,22 +1514,37 @@ mod tests {
   use super::*;
 
   use std::borrow::Cow;
+  use std::error::Error as _;
   use std::fmt::Debug;
   use std::io::copy;
+  use std::io::sink;
   use std::io::Error as IoError;
   use std::io::ErrorKind;
+  use std::io::Result as IoResult;
+  use std::io::Write as _;
+  use std::sync::atomic::AtomicBool;
+  use std::sync::atomic::AtomicUsize;
+  use std::sync::atomic::Ordering;
+  use std::sync::Mutex;
+  use std::time::UNIX_EPOCH;
 
   use blazesym::symbolize::source::Elf;
   use blazesym::symbolize::source::Source;
   use blazesym::symbolize::Input;
   use blazesym::symbolize::Symbolizer;
 
+  use http::header::AUTHORIZATION;
+  use http::header::USER_AGENT;
+  use http::HeaderValue;
+
+
   use reqwest::blocking::Client as ReqwestBlockingClient;
 
   use tempfile::NamedTempFile;
 
   use test_fork::fork;
 
+  use crate::elf::tests::make_elf;
   use crate::Readable;
 
 
@@ -226,6 +1562,11 @@ mod tests {
       .http_client(ReqwestBlockingClient::new())
       .build(["!#&*(@&!"])
       .unwrap_err();
+
+    let _err = Client::builder()
+      .http_client(ReqwestBlockingClient::new())
+      .build(["mailto:debug@info.d"])
+      .unwrap_err();
   }
 
   /// Check that the creation of a `Client` object from information
@@ -254,6 +1595,191 @@ mod tests {
     assert_eq!(client.base_urls.len(), 2);
   }
 
+  /// Check that we honor `DEBUGINFOD_TIMEOUT` and `DEBUGINFOD_MAXTIME`.
+  #[fork]
+  #[test]
+  fn from_env_timeouts() {
+    // SAFETY: `test-fork` ensures that we are in a single-threaded
+    //         context.
+    let () = unsafe { env::set_var("DEBUGINFOD_URLS", "https://debug.infod") };
+    // SAFETY: `test-fork` ensures that we are in a single-threaded
+    //         context.
+    let () = unsafe { env::set_var("DEBUGINFOD_TIMEOUT", "90") };
+    // SAFETY: `test-fork` ensures that we are in a single-threaded
+    //         context.
+    let () = unsafe { env::set_var("DEBUGINFOD_MAXTIME", "0") };
+    let client = Client::builder()
+      .http_client(ReqwestBlockingClient::new())
+      .max_time(Some(Duration::from_secs(1)))
+      .build_from_env()
+      .unwrap()
+      .unwrap();
+    assert_eq!(client.timeouts.response, Some(Duration::from_secs(90)));
+    assert_eq!(client.timeouts.low_speed, Some(Duration::from_secs(90)));
+    assert_eq!(client.timeouts.max_time, None);
+  }
+
+  /// Check that we honor `DEBUGINFOD_MAXSIZE`.
+  #[fork]
+  #[test]
+  fn from_env_max_size() {
+    // SAFETY: `test-fork` ensures that we are in a single-threaded
+    //         context.
+    let () = unsafe { env::set_var("DEBUGINFOD_URLS", "https://debug.infod") };
+    // SAFETY: `test-fork` ensures that we are in a single-threaded
+    //         context.
+    let () = unsafe { env::set_var("DEBUGINFOD_MAXSIZE", "4096") };
+    let client = Client::builder()
+      .http_client(ReqwestBlockingClient::new())
+      .build_from_env()
+      .unwrap()
+      .unwrap();
+    assert_eq!(client.max_size, Some(4096));
+  }
+
+  /// Check that we honor `DEBUGINFOD_PROGRESS`.
+  #[fork]
+  #[test]
+  fn from_env_progress() {
+    // SAFETY: `test-fork` ensures that we are in a single-threaded
+    //         context.
+    let () = unsafe { env::set_var("DEBUGINFOD_URLS", "https://debug.infod") };
+    let client = Client::builder()
+      .http_client(ReqwestBlockingClient::new())
+      .build_from_env()
+      .unwrap()
+      .unwrap();
+    assert!(client.progress.is_none());
+
+    // SAFETY: `test-fork` ensures that we are in a single-threaded
+    //         context.
+    let () = unsafe { env::set_var("DEBUGINFOD_PROGRESS", "1") };
+    let client = Client::builder()
+      .http_client(ReqwestBlockingClient::new())
+      .build_from_env()
+      .unwrap()
+      .unwrap();
+    assert!(client.progress.is_some());
+  }
+
+  /// Check that we honor `DEBUGINFOD_RETRY_LIMIT`.
+  #[fork]
+  #[test]
+  fn from_env_retry_limit() {
+    // SAFETY: `test-fork` ensures that we are in a single-threaded
+    //         context.
+    let () = unsafe { env::set_var("DEBUGINFOD_URLS", "https://debug.infod") };
+    let client = Client::builder()
+      .http_client(ReqwestBlockingClient::new())
+      .retry_limit(1)
+      .build_from_env()
+      .unwrap()
+      .unwrap();
+    assert_eq!(client.retry.limit, 1);
+
+    // SAFETY: `test-fork` ensures that we are in a single-threaded
+    //         context.
+    let () = unsafe { env::set_var("DEBUGINFOD_RETRY_LIMIT", "5") };
+    let client = Client::builder()
+      .http_client(ReqwestBlockingClient::new())
+      .retry_limit(1)
+      .build_from_env()
+      .unwrap()
+      .unwrap();
+    assert_eq!(client.retry.limit, 5);
+
+    // SAFETY: `test-fork` ensures that we are in a single-threaded
+    //         context.
+    let () = unsafe { env::set_var("DEBUGINFOD_RETRY_LIMIT", "five") };
+    let _err = Client::builder()
+      .http_client(ReqwestBlockingClient::new())
+      .build_from_env()
+      .unwrap_err();
+  }
+
+  /// Check that we pick up headers from the file referenced by
+  /// `DEBUGINFOD_HEADERS_FILE`.
+  #[fork]
+  #[test]
+  fn from_env_headers() {
+    let mut file = NamedTempFile::new().unwrap();
+    let () = write!(
+      file,
+      "Authorization: Bearer 1234\nmalformed line\nX-Custom:  foo bar \n"
+    )
+    .unwrap();
+
+    // SAFETY: `test-fork` ensures that we are in a single-threaded
+    //         context.
+    let () = unsafe { env::set_var("DEBUGINFOD_URLS", "https://debug.infod") };
+    // SAFETY: `test-fork` ensures that we are in a single-threaded
+    //         context.
+    let () = unsafe { env::set_var("DEBUGINFOD_HEADERS_FILE", file.path()) };
+    let mut headers = HeaderMap::new();
+    let _prev = headers.insert(USER_AGENT, HeaderValue::from_static("debuginfod-rs"));
+    let _prev = headers.insert("x-custom", HeaderValue::from_static("builder"));
+    let client = Client::builder()
+      .http_client(ReqwestBlockingClient::new())
+      .headers(headers)
+      .build_from_env()
+      .unwrap()
+      .unwrap();
+    assert_eq!(client.headers.len(), 4);
+    assert_eq!(client.headers[USER_AGENT], "debuginfod-rs");
+    assert_eq!(client.headers[AUTHORIZATION], "Bearer 1234");
+    assert_eq!(
+      client
+        .headers
+        .get_all("x-custom")
+        .iter()
+        .collect::<Vec<_>>(),
+      ["builder", "foo bar"]
+    );
+
+    // SAFETY: `test-fork` ensures that we are in a single-threaded
+    //         context.
+    let () = unsafe { env::set_var("DEBUGINFOD_HEADERS_FILE", "/does/not/exist") };
+    let _err = Client::builder()
+      .http_client(ReqwestBlockingClient::new())
+      .build_from_env()
+      .unwrap_err();
+  }
+
+  /// Check that configured headers are sent along with each request.
+  #[test]
+  fn request_headers() {
+    #[derive(Debug)]
+    struct HeaderCheckingHttpClient;
+
+    impl HttpClient for HeaderCheckingHttpClient {
+      fn get(&self, _url: &str) -> Result<HttpResponse<Box<dyn Readable>>, HttpClientError> {
+        unreachable!()
+      }
+
+      fn send(
+        &self,
+        request: HttpRequest<()>,
+      ) -> Result<HttpResponse<Box<dyn Readable>>, HttpClientError> {
+        assert_eq!(request.headers()[AUTHORIZATION], "Bearer 1234");
+        let data: &[u8] = b"Debug info!";
+        Ok(HttpResponse::new(Box::new(data)))
+      }
+    }
+
+    let urls = ["https://debuginfod.fedoraproject.org/"];
+    let mut headers = HeaderMap::new();
+    let _prev = headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer 1234"));
+    let client = Client::builder()
+      .http_client(HeaderCheckingHttpClient)
+      .headers(headers)
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let _info = client.fetch_debug_info(&build_id).unwrap().unwrap();
+    let _metadata = client.query_metadata("file", "/usr/bin/sleep").unwrap_err();
+  }
+
   /// Check that we can successfully fetch debug information.
   #[test]
   fn fetch_debug_info() {
@@ -310,11 +1836,35 @@ mod tests {
   struct DummyHttpClient(fn(&str) -> Result<Box<dyn Readable>, HttpClientError>);
 
   impl HttpClient for DummyHttpClient {
-    fn get(&self, url: &str) -> Result<Box<dyn Readable>, HttpClientError> {
-      (self.0)(url)
+    fn get(&self, url: &str) -> Result<HttpResponse<Box<dyn Readable>>, HttpClientError> {
+      (self.0)(url).map(HttpResponse::new)
+    }
+  }
+
+  /// An `HttpClient` providing a response with debuginfod specific
+  /// headers set.
+  #[derive(Debug)]
+  struct HeaderHttpClient;
+
+  impl HttpClient for HeaderHttpClient {
+    fn get(&self, _url: &str) -> Result<HttpResponse<Box<dyn Readable>>, HttpClientError> {
+      let data: &[u8] = b"Debug info!";
+      let response = HttpResponse::builder()
+        .header("X-DEBUGINFOD-SIZE", "11")
+        .header("X-DEBUGINFOD-FILE", "/usr/lib/debug/usr/bin/sleep.debug")
+        .header(
+          "X-DEBUGINFOD-ARCHIVE",
+          "/packages/coreutils-debuginfo-9.1-12.fc38.x86_64.rpm",
+        )
+        .header("Content-Length", "11")
+        .header("Last-Modified", "Wed, 21 Oct 2015 07:28:00 GMT")
+        .body(Box::new(data) as Box<dyn Readable>)
+        .unwrap();
+      Ok(response)
     }
   }
 
+
   /// Check replacing the http client with a dummy implementation.
   #[test]
   fn custom_http_client_generic_error() {
@@ -332,10 +1882,9 @@ mod tests {
       .unwrap();
     let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
     let err = client.fetch_debug_info(&build_id).unwrap_err();
-    assert!(err
-      .root_cause()
-      .to_string()
-      .contains("DummyHttpClient cannot fetch"));
+    assert!(matches!(err, Error::Transport { .. }), "{err:?}");
+    let source = err.source().unwrap().source().unwrap();
+    assert!(source.to_string().contains("DummyHttpClient cannot fetch"));
   }
 
   /// Check replacing the http client with a dummy implementation.
@@ -383,6 +1932,807 @@ mod tests {
     assert_eq!(buf, "Debug info!");
   }
 
+  /// Check that we expose the meta data reported in response headers.
+  #[test]
+  fn response_headers() {
+    let urls = ["https://debuginfod.fedoraproject.org/"];
+    let client = Client::builder()
+      .http_client(HeaderHttpClient)
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let info = client.fetch_debug_info(&build_id).unwrap().unwrap();
+    assert_eq!(info.size, Some(11));
+    assert_eq!(
+      info.file.as_deref(),
+      Some("/usr/lib/debug/usr/bin/sleep.debug")
+    );
+    assert_eq!(
+      info.archive.as_deref(),
+      Some("/packages/coreutils-debuginfo-9.1-12.fc38.x86_64.rpm")
+    );
+    assert_eq!(info.content_length, Some(11));
+    assert_eq!(
+      info.last_modified,
+      Some(UNIX_EPOCH + Duration::from_secs(1445412480))
+    );
+
+    // Without any headers present, all fields should be empty.
+    let http_client = DummyHttpClient(|_url| {
+      let data: &[u8] = b"Debug info!";
+      Ok(Box::new(data))
+    });
+    let client = Client::builder()
+      .http_client(http_client)
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let info = client.fetch_debug_info(&build_id).unwrap().unwrap();
+    assert_eq!(info.size, None);
+    assert_eq!(info.file, None);
+    assert_eq!(info.archive, None);
+    assert_eq!(info.content_length, None);
+    assert_eq!(info.last_modified, None);
+  }
+
+  /// Check that we request executables from the correct endpoint.
+  #[test]
+  fn fetch_executable() {
+    let urls = ["https://debuginfod.fedoraproject.org/"];
+    let http_client = DummyHttpClient(|url: &str| {
+      if url == "https://debuginfod.fedoraproject.org/buildid/00/executable" {
+        let data: &[u8] = b"Executable!";
+        return Ok(Box::new(data));
+      }
+      Err(HttpClientError::StatusCode(StatusCode::NOT_FOUND))
+    });
+    let client = Client::builder()
+      .http_client(http_client)
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let mut info = client.fetch_executable(&build_id).unwrap().unwrap();
+    assert_eq!(info.server_url, "https://debuginfod.fedoraproject.org/");
+
+    let mut buf = String::new();
+    info.data.read_to_string(&mut buf).unwrap();
+    assert_eq!(buf, "Executable!");
+
+    let info = client.fetch_debug_info(&build_id).unwrap();
+    assert!(info.is_none());
+  }
+
+  /// Check that we request source files from the correct endpoint and
+  /// encode their paths properly.
+  #[test]
+  fn fetch_source() {
+    let urls = ["https://debuginfod.fedoraproject.org/"];
+    let http_client = DummyHttpClient(|url: &str| {
+      if url
+        == "https://debuginfod.fedoraproject.org/buildid/00/source/usr/src/debug/foo%20bar%231%3F.c"
+      {
+        let data: &[u8] = b"int main() {}";
+        return Ok(Box::new(data));
+      }
+      Err(HttpClientError::StatusCode(StatusCode::NOT_FOUND))
+    });
+    let client = Client::builder()
+      .http_client(http_client)
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let mut info = client
+      .fetch_source(&build_id, "/usr/src/debug/foo bar#1?.c")
+      .unwrap()
+      .unwrap();
+
+    let mut buf = String::new();
+    info.data.read_to_string(&mut buf).unwrap();
+    assert_eq!(buf, "int main() {}");
+
+    let info = client
+      .fetch_source(&build_id, "/usr/src/debug/foo.c")
+      .unwrap();
+    assert!(info.is_none());
+
+    // `.` and `..` components are resolved before making the request.
+    let info = client
+      .fetch_source(&build_id, "/usr/src/./debug/pkg/../foo bar#1?.c")
+      .unwrap();
+    assert!(info.is_some());
+
+    for path in ["usr/src/debug/foo.c", "/usr/../../foo.c"] {
+      let err = client.fetch_source(&build_id, path).unwrap_err();
+      assert!(matches!(err, Error::InvalidPath { .. }), "{err:?}");
+    }
+  }
+
+  /// Check that we request ELF sections from the correct endpoint.
+  #[test]
+  fn fetch_section() {
+    let urls = ["https://debuginfod.fedoraproject.org/"];
+    let http_client = DummyHttpClient(|url: &str| {
+      if url == "https://debuginfod.fedoraproject.org/buildid/00/section/.debug_line" {
+        let data: &[u8] = b"\x01\x02\x03";
+        return Ok(Box::new(data));
+      }
+      Err(HttpClientError::StatusCode(StatusCode::NOT_FOUND))
+    });
+    let client = Client::builder()
+      .http_client(http_client)
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let mut info = client
+      .fetch_section(&build_id, ".debug_line")
+      .unwrap()
+      .unwrap();
+
+    let mut buf = Vec::new();
+    info.data.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, [0x01, 0x02, 0x03]);
+
+    let info = client.fetch_section(&build_id, ".gdb_index").unwrap();
+    assert!(info.is_none());
+
+    for name in ["", ".", ".."] {
+      let err = client.fetch_section(&build_id, name).unwrap_err();
+      assert!(matches!(err, Error::InvalidPath { .. }), "{err:?}");
+    }
+  }
+
+  /// Check that we query metadata from all servers and merge the
+  /// results.
+  #[test]
+  fn query_metadata() {
+    let urls = [
+      "https://debuginfod.fedoraproject.org/",
+      "https://debuginfod.archlinux.org/",
+      "https://debuginfod.ubuntu.com/",
+    ];
+    let http_client = DummyHttpClient(|url: &str| {
+      let data: &[u8] = match url {
+        "https://debuginfod.fedoraproject.org/metadata?key=file&value=%2Fusr%2Fbin%2Fsleep" => {
+          br#"{"results": [
+            {"type": "executable", "buildid": "aeb9", "file": "/usr/bin/sleep"},
+            {"type": "executable", "buildid": "a578", "file": "/usr/bin/sleep"}
+          ], "complete": true}"#
+        },
+        "https://debuginfod.archlinux.org/metadata?key=file&value=%2Fusr%2Fbin%2Fsleep" => {
+          br#"{"results": [
+            {"type": "executable", "buildid": "a578", "file": "/usr/bin/sleep"},
+            {"type": "executable", "buildid": "ff00", "file": "/usr/bin/sleep"}
+          ], "complete": true}"#
+        },
+        _ => return Err(HttpClientError::StatusCode(StatusCode::NOT_FOUND)),
+      };
+      Ok(Box::new(data))
+    });
+    let client = Client::builder()
+      .http_client(http_client)
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let metadata = client.query_metadata("file", "/usr/bin/sleep").unwrap();
+    let build_ids = metadata
+      .records
+      .iter()
+      .map(|record| record.build_id.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(build_ids, ["aeb9", "a578", "ff00"]);
+    // The third server does not support the metadata endpoint.
+    assert!(!metadata.complete);
+  }
+
+  /// Check that we report an error if no server could answer a
+  /// metadata query.
+  #[test]
+  fn query_metadata_error() {
+    let urls = ["https://debuginfod.fedoraproject.org/"];
+    let http_client = DummyHttpClient(|_url| {
+      let data: &[u8] = b"<html>Not JSON</html>";
+      Ok(Box::new(data))
+    });
+    let client = Client::builder()
+      .http_client(http_client)
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let err = client.query_metadata("glob", "/usr/bin/*").unwrap_err();
+    assert!(matches!(err, Error::InvalidMetadata { .. }), "{err:?}");
+    assert!(err.source().unwrap().to_string().contains("expected value"));
+  }
+
+  /// Check that we query servers concurrently if asked to.
+  #[test]
+  fn fetch_concurrent() {
+    let urls = [
+      "https://debuginfod.fedoraproject.org/",
+      "https://debuginfod.archlinux.org/",
+      "https://debuginfod.ubuntu.com/",
+    ];
+    let http_client = DummyHttpClient(|url: &str| {
+      if url.contains("debuginfod.fedoraproject.org") {
+        // Simulate a hanging server.
+        let () = thread::sleep(Duration::from_secs(30));
+      } else if url.contains("debuginfod.ubuntu.com") {
+        let data: &[u8] = b"Debug info!";
+        return Ok(Box::new(data));
+      }
+      Err(HttpClientError::StatusCode(StatusCode::NOT_FOUND))
+    });
+    let client = Client::builder()
+      .http_client(http_client)
+      .concurrent(true)
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let now = Instant::now();
+    let mut info = client.fetch_debug_info(&build_id).unwrap().unwrap();
+    assert!(now.elapsed() < Duration::from_secs(15));
+    assert_eq!(info.server_url, "https://debuginfod.ubuntu.com/");
+
+    let mut buf = String::new();
+    info.data.read_to_string(&mut buf).unwrap();
+    assert_eq!(buf, "Debug info!");
+  }
+
+  /// Check that the requests made as part of a successful fetch are
+  /// reported on the response.
+  #[test]
+  fn fetch_attempts() {
+    let urls = [
+      "https://debuginfod.fedoraproject.org/",
+      "https://debuginfod.archlinux.org/",
+      "https://debuginfod.ubuntu.com/",
+    ];
+    let http_client = DummyHttpClient(|url: &str| {
+      if url.contains("debuginfod.fedoraproject.org") {
+        Err(HttpClientError::StatusCode(StatusCode::NOT_FOUND))
+      } else if url.contains("debuginfod.archlinux.org") {
+        Err(HttpClientError::StatusCode(StatusCode::IM_A_TEAPOT))
+      } else {
+        let data: &[u8] = b"Debug info!";
+        Ok(Box::new(data))
+      }
+    });
+    let client = Client::builder()
+      .http_client(http_client)
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let info = client.fetch_debug_info(&build_id).unwrap().unwrap();
+    let outcomes = info
+      .attempts
+      .iter()
+      .map(|attempt| (attempt.server_url.as_str(), attempt.outcome.clone()))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      outcomes,
+      vec![
+        (urls[0], AttemptOutcome::NotFound),
+        (urls[1], AttemptOutcome::Status(StatusCode::IM_A_TEAPOT)),
+        (urls[2], AttemptOutcome::Success),
+      ]
+    );
+  }
+
+  /// Check that we skip servers providing data not matching the
+  /// requested build ID when verification is enabled.
+  #[test]
+  fn fetch_debug_info_verify() {
+    let urls = [
+      "https://debuginfod.fedoraproject.org/",
+      "https://debuginfod.archlinux.org/",
+      "https://debuginfod.ubuntu.com/",
+    ];
+    let http_client = DummyHttpClient(|url: &str| {
+      if url.contains("debuginfod.fedoraproject.org") {
+        let data: &[u8] = b"<html>Not here</html>";
+        Ok(Box::new(data))
+      } else if url.contains("debuginfod.archlinux.org") {
+        Err(HttpClientError::StatusCode(StatusCode::NOT_FOUND))
+      } else {
+        Ok(Box::new(Cursor::new(make_elf(
+          true,
+          false,
+          true,
+          &[0xab, 0xcd],
+        ))))
+      }
+    });
+    let client = Client::builder()
+      .http_client(http_client)
+      .verify_build_id(true)
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let build_id = BuildId::raw(&[0xab, 0xcd]);
+    let mut info = client.fetch_debug_info(&build_id).unwrap().unwrap();
+    assert_eq!(info.server_url, urls[2]);
+    let outcomes = info
+      .attempts
+      .iter()
+      .map(|attempt| attempt.outcome.clone())
+      .collect::<Vec<_>>();
+    assert_eq!(
+      outcomes,
+      vec![
+        AttemptOutcome::BuildIdMismatch,
+        AttemptOutcome::NotFound,
+        AttemptOutcome::Success,
+      ]
+    );
+
+    // The data read for verification has to be handed out as well.
+    let mut data = Vec::new();
+    let _count = info.data.read_to_end(&mut data).unwrap();
+    assert_eq!(data, make_elf(true, false, true, &[0xab, 0xcd]));
+
+    let build_id = BuildId::raw(&[0xab, 0xce]);
+    let err = client.fetch_debug_info(&build_id).unwrap_err();
+    assert!(
+      matches!(&err, Error::BuildIdMismatch { url, .. } if url.contains("debuginfod.fedoraproject.org")),
+      "{err:?}"
+    );
+    assert_eq!(err.attempts().len(), 3);
+  }
+
+  /// Check that we can fetch debug info into a shared buffer.
+  #[test]
+  fn fetch_debug_info_shared() {
+    let urls = ["https://debuginfod.fedoraproject.org/"];
+    let client = Client::builder()
+      .http_client(HeaderHttpClient)
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let info = client.fetch_debug_info_shared(&build_id).unwrap().unwrap();
+    assert_eq!(&*info.data, b"Debug info!");
+    assert_eq!(info.server_url, urls[0]);
+    assert_eq!(info.size, Some(11));
+    assert_eq!(
+      info.file.as_deref(),
+      Some("/usr/lib/debug/usr/bin/sleep.debug")
+    );
+    assert_eq!(info.attempts.len(), 1);
+
+    let http_client =
+      DummyHttpClient(|_url| Err(HttpClientError::StatusCode(StatusCode::NOT_FOUND)));
+    let client = Client::builder()
+      .http_client(http_client)
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let info = client.fetch_debug_info_shared(&build_id).unwrap();
+    assert!(info.is_none());
+  }
+
+  /// Check that concurrent querying of servers reports errors the same
+  /// way sequential querying does.
+  #[test]
+  fn fetch_concurrent_errors() {
+    let urls = [
+      "https://debuginfod.fedoraproject.org/",
+      "https://debuginfod.archlinux.org/",
+      "https://debuginfod.ubuntu.com/",
+    ];
+    let http_client = DummyHttpClient(|url: &str| {
+      if url.contains("debuginfod.fedoraproject.org") {
+        Err(HttpClientError::Other(Box::new(IoError::new(
+          ErrorKind::Other,
+          "connection refused",
+        ))))
+      } else if url.contains("debuginfod.archlinux.org") {
+        let () = thread::sleep(Duration::from_millis(100));
+        Err(HttpClientError::StatusCode(StatusCode::BAD_GATEWAY))
+      } else {
+        Err(HttpClientError::StatusCode(StatusCode::IM_A_TEAPOT))
+      }
+    });
+    let client = Client::builder()
+      .http_client(http_client)
+      .concurrent(true)
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let err = client.fetch_debug_info(&build_id).unwrap_err();
+    // Server errors take precedence over errors issuing the request and
+    // the order of servers is honored.
+    assert!(
+      matches!(
+        &err,
+        Error::HttpStatus {
+          url,
+          status: StatusCode::BAD_GATEWAY,
+          ..
+        } if url.starts_with("https://debuginfod.archlinux.org/")
+      ),
+      "{err:?}"
+    );
+
+    // All attempts should be reported, in server order.
+    let attempts = err.attempts();
+    assert_eq!(attempts.len(), 3);
+    assert_eq!(attempts[0].server_url, urls[0]);
+    assert!(matches!(attempts[0].outcome, AttemptOutcome::Transport(..)));
+    assert_eq!(attempts[1].server_url, urls[1]);
+    assert_eq!(
+      attempts[1].outcome,
+      AttemptOutcome::Status(StatusCode::BAD_GATEWAY)
+    );
+    assert!(attempts[1].duration >= Duration::from_millis(100));
+    assert_eq!(attempts[2].server_url, urls[2]);
+    assert_eq!(
+      attempts[2].outcome,
+      AttemptOutcome::Status(StatusCode::IM_A_TEAPOT)
+    );
+
+    let http_client =
+      DummyHttpClient(|_url| Err(HttpClientError::StatusCode(StatusCode::NOT_FOUND)));
+    let client = Client::builder()
+      .http_client(http_client)
+      .concurrent(true)
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let info = client.fetch_debug_info(&build_id).unwrap();
+    assert!(info.is_none());
+  }
+
+  /// Check that we hedge requests if the first server is slow to
+  /// respond.
+  #[test]
+  fn fetch_hedged() {
+    let urls = [
+      "https://debuginfod.fedoraproject.org/",
+      "https://debuginfod.archlinux.org/",
+    ];
+    let http_client = DummyHttpClient(|url: &str| {
+      if url.contains("debuginfod.fedoraproject.org") {
+        // Simulate a hanging server.
+        let () = thread::sleep(Duration::from_secs(30));
+      } else if url.contains("debuginfod.archlinux.org") {
+        let data: &[u8] = b"Arch Linux";
+        return Ok(Box::new(data));
+      }
+      Err(HttpClientError::StatusCode(StatusCode::NOT_FOUND))
+    });
+    let client = Client::builder()
+      .http_client(http_client)
+      .hedge_delay(Duration::from_millis(50))
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let now = Instant::now();
+    let info = client.fetch_debug_info(&build_id).unwrap().unwrap();
+    assert!(now.elapsed() < Duration::from_secs(15));
+    assert_eq!(info.server_url, "https://debuginfod.archlinux.org/");
+  }
+
+  /// Check that we do not wait for the hedge delay to expire if a
+  /// server fails early.
+  #[test]
+  fn fetch_hedged_early_failure() {
+    let urls = [
+      "https://debuginfod.fedoraproject.org/",
+      "https://debuginfod.archlinux.org/",
+    ];
+    let http_client = DummyHttpClient(|url: &str| {
+      if url.contains("debuginfod.archlinux.org") {
+        let data: &[u8] = b"Arch Linux";
+        return Ok(Box::new(data));
+      }
+      Err(HttpClientError::StatusCode(StatusCode::NOT_FOUND))
+    });
+    let client = Client::builder()
+      .http_client(http_client)
+      .hedge_delay(Duration::from_secs(30))
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let now = Instant::now();
+    let info = client.fetch_debug_info(&build_id).unwrap().unwrap();
+    assert!(now.elapsed() < Duration::from_secs(15));
+    assert_eq!(info.server_url, "https://debuginfod.archlinux.org/");
+  }
+
+  /// Check that we retry failed requests before moving on to the next
+  /// server.
+  #[test]
+  fn fetch_retry() {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    let urls = [
+      "https://debuginfod.fedoraproject.org/",
+      "https://debuginfod.archlinux.org/",
+    ];
+    let http_client = DummyHttpClient(|url: &str| {
+      if url.contains("debuginfod.fedoraproject.org") && COUNT.fetch_add(1, Ordering::Relaxed) == 2
+      {
+        let data: &[u8] = b"Fedora";
+        return Ok(Box::new(data));
+      }
+      Err(HttpClientError::StatusCode(StatusCode::BAD_GATEWAY))
+    });
+    let client = Client::builder()
+      .http_client(http_client)
+      .retry_limit(2)
+      .retry_backoff(Duration::from_millis(1))
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let info = client.fetch_debug_info(&build_id).unwrap().unwrap();
+    assert_eq!(info.server_url, "https://debuginfod.fedoraproject.org/");
+    assert_eq!(COUNT.load(Ordering::Relaxed), 3);
+  }
+
+  /// Check that we abort a fetch promptly once it is cancelled, while
+  /// leaving the client usable. A timeout has us wait for the hanging
+  /// server on a worker thread.
+  #[test]
+  fn fetch_cancel() {
+    static HANG: AtomicBool = AtomicBool::new(true);
+
+    let urls = [
+      "https://debuginfod.fedoraproject.org/",
+      "https://debuginfod.archlinux.org/",
+    ];
+    let http_client = DummyHttpClient(|url: &str| {
+      if url.contains("debuginfod.fedoraproject.org") && HANG.swap(false, Ordering::Relaxed) {
+        // Simulate a hanging server.
+        let () = thread::sleep(Duration::from_secs(30));
+      }
+      let data: &[u8] = b"Debug info!";
+      Ok(Box::new(data))
+    });
+    let client = Client::builder()
+      .http_client(http_client)
+      .connect_timeout(Some(Duration::from_secs(60)))
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let cancel = CancellationToken::new();
+    let cancel_clone = cancel.clone();
+    let _handle = thread::spawn(move || {
+      let () = thread::sleep(Duration::from_millis(50));
+      let () = cancel_clone.cancel();
+    });
+
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let now = Instant::now();
+    let err = client
+      .fetch_debug_info_with(&build_id, &cancel)
+      .unwrap_err();
+    assert!(now.elapsed() < Duration::from_secs(15));
+    assert!(matches!(err, Error::Cancelled), "{err:?}");
+
+    // Further fetches with the same token fail right away, but others
+    // are unaffected.
+    let err = client
+      .fetch_debug_info_with(&build_id, &cancel)
+      .unwrap_err();
+    assert!(matches!(err, Error::Cancelled), "{err:?}");
+    let info = client.fetch_debug_info(&build_id).unwrap().unwrap();
+    assert_eq!(info.server_url, "https://debuginfod.fedoraproject.org/");
+  }
+
+  /// Make sure that the token configured for a client cancels all of
+  /// its operations.
+  #[test]
+  fn fetch_cancel_client() {
+    let urls = ["https://debuginfod.fedoraproject.org/"];
+    let http_client = DummyHttpClient(|_url| {
+      let data: &[u8] = b"Debug info!";
+      Ok(Box::new(data))
+    });
+    let cancel = CancellationToken::new();
+    let client = Client::builder()
+      .http_client(http_client)
+      .cancellation_token(cancel.clone())
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let _info = client.fetch_debug_info(&build_id).unwrap().unwrap();
+
+    let () = cancel.cancel();
+    let err = client.fetch_debug_info(&build_id).unwrap_err();
+    assert!(matches!(err, Error::Cancelled), "{err:?}");
+    let err = client
+      .fetch_debug_info_with(&build_id, &CancellationToken::new())
+      .unwrap_err();
+    assert!(matches!(err, Error::Cancelled), "{err:?}");
+  }
+
+  /// Check that cancellation interrupts reading response data that is
+  /// blocked, given a timeout that has us read on a worker thread.
+  #[test]
+  fn fetch_cancel_read() {
+    /// A reader that never produces any data.
+    #[derive(Debug)]
+    struct HangingReader;
+
+    impl Read for HangingReader {
+      fn read(&mut self, _buf: &mut [u8]) -> IoResult<usize> {
+        let () = thread::sleep(Duration::from_secs(30));
+        Ok(0)
+      }
+    }
+
+    let urls = ["https://debuginfod.fedoraproject.org/"];
+    let http_client = DummyHttpClient(|_url| Ok(Box::new(HangingReader)));
+    let client = Client::builder()
+      .http_client(http_client)
+      .low_speed_timeout(Some(Duration::from_secs(60)))
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let cancel = CancellationToken::new();
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let mut info = client
+      .fetch_debug_info_with(&build_id, &cancel)
+      .unwrap()
+      .unwrap();
+
+    let _handle = thread::spawn(move || {
+      let () = thread::sleep(Duration::from_millis(50));
+      let () = cancel.cancel();
+    });
+    let now = Instant::now();
+    let err = copy(&mut info.data, &mut sink()).unwrap_err();
+    assert!(now.elapsed() < Duration::from_secs(15));
+    assert!(err.get_ref().unwrap().is::<Cancelled>(), "{err}");
+  }
+
+  /// Check that we move on to the next server if one does not respond
+  /// in time.
+  #[test]
+  fn fetch_connect_timeout() {
+    let urls = [
+      "https://debuginfod.fedoraproject.org/",
+      "https://debuginfod.archlinux.org/",
+    ];
+    let http_client = DummyHttpClient(|url: &str| {
+      if url.contains("debuginfod.fedoraproject.org") {
+        // Simulate a hanging server.
+        let () = thread::sleep(Duration::from_secs(30));
+      }
+      let data: &[u8] = b"Debug info!";
+      Ok(Box::new(data))
+    });
+    let client = Client::builder()
+      .http_client(http_client)
+      .connect_timeout(Some(Duration::from_millis(50)))
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let now = Instant::now();
+    let info = client.fetch_debug_info(&build_id).unwrap().unwrap();
+    assert!(now.elapsed() < Duration::from_secs(15));
+    assert_eq!(info.server_url, "https://debuginfod.archlinux.org/");
+  }
+
+  /// Check that we reject responses exceeding the maximum size.
+  #[test]
+  fn fetch_max_size() {
+    let urls = ["https://debuginfod.fedoraproject.org/"];
+    let http_client = DummyHttpClient(|_url| {
+      let data: &[u8] = b"Debug info!";
+      Ok(Box::new(data))
+    });
+    let client = Client::builder()
+      .http_client(http_client)
+      .max_size(Some(5))
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let mut info = client.fetch_debug_info(&build_id).unwrap().unwrap();
+    let err = copy(&mut info.data, &mut sink()).unwrap_err();
+    let err = err
+      .get_ref()
+      .unwrap()
+      .downcast_ref::<SizeLimitExceeded>()
+      .unwrap();
+    assert_eq!(err.limit, 5);
+
+    // If the size is advertised upfront, we should fail early.
+    let client = Client::builder()
+      .http_client(HeaderHttpClient)
+      .max_size(Some(5))
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let err = client.fetch_debug_info(&build_id).unwrap_err();
+    assert!(
+      matches!(
+        err,
+        Error::TooLarge(SizeLimitExceeded {
+          limit: 5,
+          size: Some(11)
+        })
+      ),
+      "{err:?}"
+    );
+  }
+
+  /// Check that the progress of downloads is reported to the
+  /// registered callback.
+  #[test]
+  fn fetch_progress() {
+    let urls = ["https://debuginfod.fedoraproject.org/"];
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let reports_clone = Arc::clone(&reports);
+    let client = Client::builder()
+      .http_client(HeaderHttpClient)
+      .progress(move |progress| {
+        assert_eq!(progress.server_url, "https://debuginfod.fedoraproject.org/");
+        assert_eq!(progress.build_id, "00");
+        let () = reports_clone
+          .lock()
+          .unwrap()
+          .push((progress.received, progress.total));
+      })
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
+    let mut info = client.fetch_debug_info(&build_id).unwrap().unwrap();
+    let mut data = [0; 4];
+    let () = info.data.read_exact(&mut data).unwrap();
+    let _count = copy(&mut info.data, &mut sink()).unwrap();
+
+    let reports = reports.lock().unwrap();
+    assert_eq!(reports.first(), Some(&(4, Some(11))));
+    assert_eq!(reports.last(), Some(&(11, Some(11))));
+    // The final report is not repeated at the end of the data.
+    assert_eq!(
+      reports
+        .iter()
+        .filter(|(received, _total)| *received == 11)
+        .count(),
+      1
+    );
+
+    // Without an advertised size the total is known once all data has
+    // been received.
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let reports_clone = Arc::clone(&reports);
+    let http_client = DummyHttpClient(|_url| {
+      let data: &[u8] = b"Debug info!";
+      Ok(Box::new(data))
+    });
+    let client = Client::builder()
+      .http_client(http_client)
+      .progress(move |progress| {
+        let () = reports_clone
+          .lock()
+          .unwrap()
+          .push((progress.received, progress.total));
+      })
+      .build(urls)
+      .unwrap()
+      .unwrap();
+    let mut info = client.fetch_debug_info(&build_id).unwrap().unwrap();
+    let _count = copy(&mut info.data, &mut sink()).unwrap();
+
+    let reports = reports.lock().unwrap();
+    assert_eq!(*reports, vec![(11, None), (11, Some(11))]);
+  }
+
   // Check replacing the http client with a dummy implementation, other status
   // code
   #[test]
@@ -397,9 +2747,15 @@ mod tests {
       .unwrap();
     let build_id = BuildId::RawBytes(Cow::Borrowed(&[0x00]));
     let err = client.fetch_debug_info(&build_id).unwrap_err();
-    assert!(err
-      .root_cause()
-      .to_string()
-      .contains("request failed with HTTP status 418"));
+    assert!(
+      matches!(
+        err,
+        Error::HttpStatus {
+          status: StatusCode::IM_A_TEAPOT,
+          ..
+        }
+      ),
+      "{err:?}"
+    );
   }
 }
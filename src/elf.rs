@@ -0,0 +1,524 @@
+// Copyright (C) 2026 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+use std::error::Error as StdError;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::io::Error as IoError;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Result as IoResult;
+use std::io::Seek;
+use std::io::SeekFrom;
+
+use crate::util::format_build_id;
+
+
+/// The segment type of a note segment.
+const PT_NOTE: u32 = 4;
+/// The section type of a note section.
+const SHT_NOTE: u32 = 7;
+/// The note type of a GNU build ID note.
+const NT_GNU_BUILD_ID: u32 = 3;
+/// The owner name of GNU notes.
+const ELF_NOTE_GNU: &[u8] = b"GNU\0";
+/// The maximum size of a note segment or section we are willing to
+/// inspect, in bytes.
+const MAX_NOTES_SIZE: u64 = 1024 * 1024;
+/// The maximum amount of data we inspect when verifying the build ID
+/// of data being downloaded, in bytes.
+pub(crate) const MAX_VERIFY_PREFIX: usize = 1024 * 1024;
+
+
+/// An error indicating that data provided by a server does not match
+/// the requested build ID.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct BuildIdMismatch;
+
+impl Display for BuildIdMismatch {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    write!(f, "data does not match the requested build ID")
+  }
+}
+
+impl StdError for BuildIdMismatch {}
+
+
+/// A source of ELF data, supporting reads at arbitrary offsets.
+pub(crate) trait ReadAt {
+  /// Fill `buf` with the data at `offset`, failing with
+  /// [`ErrorKind::UnexpectedEof`] if not enough data is available.
+  fn read_exact_at(&mut self, offset: u64, buf: &mut [u8]) -> IoResult<()>;
+
+  /// Retrieve the total size of the available data, in bytes.
+  fn size(&mut self) -> IoResult<u64>;
+}
+
+impl ReadAt for &[u8] {
+  fn read_exact_at(&mut self, offset: u64, buf: &mut [u8]) -> IoResult<()> {
+    let data = usize::try_from(offset)
+      .ok()
+      .and_then(|start| self.get(start..start.checked_add(buf.len())?))
+      .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "ELF data is truncated"))?;
+    let () = buf.copy_from_slice(data);
+    Ok(())
+  }
+
+  fn size(&mut self) -> IoResult<u64> {
+    // SANITY: A slice can't possibly exceed `u64` capacity.
+    Ok(u64::try_from(self.len()).unwrap())
+  }
+}
+
+
+/// An adapter for reading ELF data from a seekable reader.
+#[derive(Debug)]
+pub(crate) struct SeekReader<R>(pub(crate) R);
+
+impl<R> ReadAt for SeekReader<R>
+where
+  R: Read + Seek,
+{
+  fn read_exact_at(&mut self, offset: u64, buf: &mut [u8]) -> IoResult<()> {
+    let _pos = self.0.seek(SeekFrom::Start(offset))?;
+    self.0.read_exact(buf)
+  }
+
+  fn size(&mut self) -> IoResult<u64> {
+    self.0.seek(SeekFrom::End(0))
+  }
+}
+
+
+/// Create an error indicating malformed ELF data.
+fn invalid(msg: &'static str) -> IoError {
+  IoError::new(ErrorKind::InvalidData, msg)
+}
+
+
+/// Read `size` bytes at `offset` into a newly allocated buffer.
+///
+/// The range is checked against the data available before allocating
+/// anything, as sizes come straight from untrusted ELF data.
+fn read_vec_at<S>(source: &mut S, offset: u64, size: u64) -> IoResult<Vec<u8>>
+where
+  S: ReadAt,
+{
+  let end = offset
+    .checked_add(size)
+    .ok_or_else(|| invalid("ELF data range is out of bounds"))?;
+  if end > source.size()? {
+    return Err(IoError::new(
+      ErrorKind::UnexpectedEof,
+      "ELF data is truncated",
+    ))
+  }
+
+  let size = usize::try_from(size).map_err(|_err| invalid("ELF data range is out of bounds"))?;
+  let mut data = vec![0; size];
+  let () = source.read_exact_at(offset, &mut data)?;
+  Ok(data)
+}
+
+
+/// The decoder for the integers of an ELF file, which depend on its
+/// class and byte order.
+#[derive(Clone, Copy, Debug)]
+struct Decoder {
+  /// Whether we are dealing with a 64 bit file.
+  is_64: bool,
+  /// Whether the data is stored in big endian byte order.
+  big_endian: bool,
+}
+
+impl Decoder {
+  fn u16(&self, data: &[u8], offset: usize) -> u16 {
+    let bytes = [data[offset], data[offset + 1]];
+    if self.big_endian {
+      u16::from_be_bytes(bytes)
+    } else {
+      u16::from_le_bytes(bytes)
+    }
+  }
+
+  fn u32(&self, data: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    let () = bytes.copy_from_slice(&data[offset..offset + 4]);
+    if self.big_endian {
+      u32::from_be_bytes(bytes)
+    } else {
+      u32::from_le_bytes(bytes)
+    }
+  }
+
+  fn u64(&self, data: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0; 8];
+    let () = bytes.copy_from_slice(&data[offset..offset + 8]);
+    if self.big_endian {
+      u64::from_be_bytes(bytes)
+    } else {
+      u64::from_le_bytes(bytes)
+    }
+  }
+
+  /// Decode a word the size of which depends on the class of the file
+  /// (e.g., an offset), given its offsets in 32 and 64 bit structures.
+  fn word(&self, data: &[u8], offset32: usize, offset64: usize) -> u64 {
+    if self.is_64 {
+      self.u64(data, offset64)
+    } else {
+      u64::from(self.u32(data, offset32))
+    }
+  }
+}
+
+
+/// Search the notes contained in `data` for a GNU build ID.
+fn find_build_id_note(decoder: &Decoder, data: &[u8], align: u64) -> Option<Vec<u8>> {
+  // Notes are four byte aligned, unless the containing segment or
+  // section asks for eight byte alignment.
+  let align = if align == 8 { 8 } else { 4 };
+  let align_up = |value: usize| {
+    value
+      .checked_add(align - 1)
+      .map(|value| value & !(align - 1))
+  };
+
+  let mut offset = 0;
+  while data.len().saturating_sub(offset) >= 12 {
+    let name_size = decoder.u32(data, offset) as usize;
+    let desc_size = decoder.u32(data, offset + 4) as usize;
+    let type_ = decoder.u32(data, offset + 8);
+
+    let name_start = offset + 12;
+    let desc_start = name_start.checked_add(align_up(name_size)?)?;
+    let name = data.get(name_start..name_start.checked_add(name_size)?)?;
+    let desc = data.get(desc_start..desc_start.checked_add(desc_size)?)?;
+
+    if type_ == NT_GNU_BUILD_ID && name == ELF_NOTE_GNU {
+      return Some(desc.to_vec())
+    }
+    offset = desc_start.checked_add(align_up(desc_size)?)?;
+  }
+  None
+}
+
+
+/// Read the table of `count` program or section headers, each
+/// `entry_size` bytes in size, at `offset`.
+fn read_headers<S>(
+  source: &mut S,
+  offset: u64,
+  entry_size: u16,
+  count: u16,
+  min_entry_size: u16,
+) -> IoResult<Vec<u8>>
+where
+  S: ReadAt,
+{
+  if count == 0 {
+    return Ok(Vec::new())
+  }
+  if entry_size < min_entry_size {
+    return Err(invalid("ELF header table entries are too small"))
+  }
+
+  read_vec_at(source, offset, u64::from(entry_size) * u64::from(count))
+}
+
+
+/// Inspect the notes at `offset` of size `size` for a GNU build ID.
+fn read_build_id_note<S>(
+  source: &mut S,
+  decoder: &Decoder,
+  offset: u64,
+  size: u64,
+  align: u64,
+) -> IoResult<Option<Vec<u8>>>
+where
+  S: ReadAt,
+{
+  if size > MAX_NOTES_SIZE {
+    return Ok(None)
+  }
+
+  let notes = read_vec_at(source, offset, size)?;
+  Ok(find_build_id_note(decoder, &notes, align))
+}
+
+
+/// Read the GNU build ID of the ELF file provided by `source`.
+///
+/// Both program headers and section headers are searched for notes.
+/// `None` is returned if the file does not contain a build ID.
+pub(crate) fn read_build_id<S>(source: &mut S) -> IoResult<Option<Vec<u8>>>
+where
+  S: ReadAt,
+{
+  let mut magic = [0; 4];
+  let () = source.read_exact_at(0, &mut magic)?;
+  if magic != *b"\x7fELF" {
+    return Err(invalid("data is not an ELF file"))
+  }
+
+  let mut ident = [0; 16];
+  let () = source.read_exact_at(0, &mut ident)?;
+
+  let is_64 = match ident[4] {
+    1 => false,
+    2 => true,
+    _ => return Err(invalid("ELF file has invalid class")),
+  };
+  let big_endian = match ident[5] {
+    1 => false,
+    2 => true,
+    _ => return Err(invalid("ELF file has invalid byte order")),
+  };
+  let decoder = Decoder { is_64, big_endian };
+
+  let mut ehdr = vec![0; if is_64 { 64 } else { 52 }];
+  let () = source.read_exact_at(0, &mut ehdr)?;
+  let phoff = decoder.word(&ehdr, 0x1c, 0x20);
+  let shoff = decoder.word(&ehdr, 0x20, 0x28);
+  let base = if is_64 { 0x36 } else { 0x2a };
+  let phentsize = decoder.u16(&ehdr, base);
+  let phnum = decoder.u16(&ehdr, base + 2);
+  let shentsize = decoder.u16(&ehdr, base + 4);
+  let shnum = decoder.u16(&ehdr, base + 6);
+
+  // Program headers are usually located at the start of the file, so
+  // we check them first.
+  let min_size = if is_64 { 56 } else { 32 };
+  let phdrs = read_headers(source, phoff, phentsize, phnum, min_size)?;
+  for phdr in phdrs.chunks_exact(usize::from(phentsize.max(1))) {
+    if decoder.u32(phdr, 0) == PT_NOTE {
+      let offset = decoder.word(phdr, 0x04, 0x08);
+      let size = decoder.word(phdr, 0x10, 0x20);
+      let align = decoder.word(phdr, 0x1c, 0x30);
+      if let Some(build_id) = read_build_id_note(source, &decoder, offset, size, align)? {
+        return Ok(Some(build_id))
+      }
+    }
+  }
+
+  let min_size = if is_64 { 64 } else { 40 };
+  let shdrs = read_headers(source, shoff, shentsize, shnum, min_size)?;
+  for shdr in shdrs.chunks_exact(usize::from(shentsize.max(1))) {
+    if decoder.u32(shdr, 0x04) == SHT_NOTE {
+      let offset = decoder.word(shdr, 0x10, 0x18);
+      let size = decoder.word(shdr, 0x14, 0x20);
+      let align = decoder.word(shdr, 0x20, 0x30);
+      if let Some(build_id) = read_build_id_note(source, &decoder, offset, size, align)? {
+        return Ok(Some(build_id))
+      }
+    }
+  }
+
+  Ok(None)
+}
+
+
+/// The result of checking the beginning of some data against a build
+/// ID.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Verdict {
+  /// The data is an ELF file with the expected build ID.
+  Match,
+  /// The data is not an ELF file with the expected build ID.
+  Mismatch,
+  /// The build ID could not be located within the data we are willing
+  /// to inspect.
+  Unknown,
+  /// More data is needed to reach a verdict.
+  NeedMore,
+}
+
+
+/// Check whether `prefix`, the beginning of some data, is an ELF file
+/// with build ID `build_id`, in normalized form. `eof` indicates
+/// whether `prefix` is all there is.
+pub(crate) fn check_prefix(prefix: &[u8], eof: bool, build_id: &str) -> Verdict {
+  match read_build_id(&mut &*prefix) {
+    Ok(Some(found)) if format_build_id(&found) == build_id => Verdict::Match,
+    Ok(_) => Verdict::Mismatch,
+    Err(err) if err.kind() == ErrorKind::UnexpectedEof && !eof => {
+      if prefix.len() < MAX_VERIFY_PREFIX {
+        Verdict::NeedMore
+      } else {
+        Verdict::Unknown
+      }
+    },
+    Err(_err) => Verdict::Mismatch,
+  }
+}
+
+
+#[cfg(test)]
+pub(crate) mod tests {
+  use super::*;
+
+
+  /// Build a minimal ELF file with the given class and byte order,
+  /// containing a GNU build ID note referenced by a program header
+  /// (if `phdr` is set) or a section header (otherwise).
+  pub(crate) fn make_elf(is_64: bool, big_endian: bool, phdr: bool, build_id: &[u8]) -> Vec<u8> {
+    fn put(data: &mut Vec<u8>, big_endian: bool, value: u64, size: usize) {
+      let bytes = if big_endian {
+        value.to_be_bytes()[8 - size..].to_vec()
+      } else {
+        value.to_le_bytes()[..size].to_vec()
+      };
+      let () = data.extend_from_slice(&bytes);
+    }
+
+    let word = if is_64 { 8 } else { 4 };
+    let ehdr_size = if is_64 { 64 } else { 52 };
+    let phdr_size = if is_64 { 56 } else { 32 };
+    let shdr_size = if is_64 { 64 } else { 40 };
+
+    let mut note = Vec::new();
+    let () = put(&mut note, big_endian, 4, 4);
+    let () = put(&mut note, big_endian, build_id.len() as u64, 4);
+    let () = put(&mut note, big_endian, u64::from(NT_GNU_BUILD_ID), 4);
+    let () = note.extend_from_slice(ELF_NOTE_GNU);
+    let () = note.extend_from_slice(build_id);
+    let () = note.resize((note.len() + 3) & !3, 0);
+
+    let (phnum, shnum) = if phdr { (1, 0) } else { (0, 1) };
+    let note_offset = (ehdr_size + phdr_size * phnum) as u64;
+    let shoff = if phdr {
+      0
+    } else {
+      note_offset + note.len() as u64
+    };
+
+    let mut elf = b"\x7fELF".to_vec();
+    let () = elf.push(if is_64 { 2 } else { 1 });
+    let () = elf.push(if big_endian { 2 } else { 1 });
+    let () = elf.push(1);
+    let () = elf.resize(16, 0);
+    // e_type, e_machine, e_version, e_entry
+    let () = put(&mut elf, big_endian, 2, 2);
+    let () = put(&mut elf, big_endian, 0, 2);
+    let () = put(&mut elf, big_endian, 1, 4);
+    let () = put(&mut elf, big_endian, 0, word);
+    // e_phoff, e_shoff, e_flags, e_ehsize
+    let () = put(&mut elf, big_endian, ehdr_size as u64, word);
+    let () = put(&mut elf, big_endian, shoff, word);
+    let () = put(&mut elf, big_endian, 0, 4);
+    let () = put(&mut elf, big_endian, ehdr_size as u64, 2);
+    // e_phentsize, e_phnum, e_shentsize, e_shnum, e_shstrndx
+    let () = put(&mut elf, big_endian, phdr_size as u64, 2);
+    let () = put(&mut elf, big_endian, phnum as u64, 2);
+    let () = put(&mut elf, big_endian, shdr_size as u64, 2);
+    let () = put(&mut elf, big_endian, shnum as u64, 2);
+    let () = put(&mut elf, big_endian, 0, 2);
+    assert_eq!(elf.len(), ehdr_size);
+
+    if phdr {
+      let () = put(&mut elf, big_endian, u64::from(PT_NOTE), 4);
+      if is_64 {
+        // p_flags, p_offset, p_vaddr, p_paddr, p_filesz, p_memsz, p_align
+        let () = put(&mut elf, big_endian, 4, 4);
+        let () = put(&mut elf, big_endian, note_offset, 8);
+        let () = put(&mut elf, big_endian, 0, 8);
+        let () = put(&mut elf, big_endian, 0, 8);
+        let () = put(&mut elf, big_endian, note.len() as u64, 8);
+        let () = put(&mut elf, big_endian, note.len() as u64, 8);
+        let () = put(&mut elf, big_endian, 4, 8);
+      } else {
+        // p_offset, p_vaddr, p_paddr, p_filesz, p_memsz, p_flags, p_align
+        let () = put(&mut elf, big_endian, note_offset, 4);
+        let () = put(&mut elf, big_endian, 0, 4);
+        let () = put(&mut elf, big_endian, 0, 4);
+        let () = put(&mut elf, big_endian, note.len() as u64, 4);
+        let () = put(&mut elf, big_endian, note.len() as u64, 4);
+        let () = put(&mut elf, big_endian, 4, 4);
+        let () = put(&mut elf, big_endian, 4, 4);
+      }
+    }
+
+    let () = elf.extend_from_slice(&note);
+
+    if !phdr {
+      // sh_name, sh_type, sh_flags, sh_addr, sh_offset, sh_size,
+      // sh_link, sh_info, sh_addralign, sh_entsize
+      let () = put(&mut elf, big_endian, 0, 4);
+      let () = put(&mut elf, big_endian, u64::from(SHT_NOTE), 4);
+      let () = put(&mut elf, big_endian, 2, word);
+      let () = put(&mut elf, big_endian, 0, word);
+      let () = put(&mut elf, big_endian, note_offset, word);
+      let () = put(&mut elf, big_endian, note.len() as u64, word);
+      let () = put(&mut elf, big_endian, 0, 4);
+      let () = put(&mut elf, big_endian, 0, 4);
+      let () = put(&mut elf, big_endian, 4, word);
+      let () = put(&mut elf, big_endian, 0, word);
+    }
+    elf
+  }
+
+
+  /// Check that we can read build IDs from ELF files of all classes
+  /// and byte orders, via program and section headers.
+  #[test]
+  fn build_id_reading() {
+    let build_id = [0xae, 0xb9, 0xa9, 0x83];
+
+    for is_64 in [false, true] {
+      for big_endian in [false, true] {
+        for phdr in [false, true] {
+          let elf = make_elf(is_64, big_endian, phdr, &build_id);
+          let found = read_build_id(&mut elf.as_slice()).unwrap();
+          assert_eq!(
+            found.as_deref(),
+            Some(build_id.as_slice()),
+            "is_64={is_64} big_endian={big_endian} phdr={phdr}"
+          );
+        }
+      }
+    }
+  }
+
+  /// Make sure that we reject data that is not an ELF file.
+  #[test]
+  fn build_id_reading_invalid() {
+    let err = read_build_id(&mut b"<html></html>".as_slice()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+    let elf = make_elf(true, false, true, &[0xab]);
+    let err = read_build_id(&mut &elf[..40]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+  }
+
+  /// Make sure that we do not trust header table sizes before checking
+  /// them against the data available.
+  #[test]
+  fn build_id_reading_huge_tables() {
+    let mut elf = make_elf(true, false, true, &[0xab]);
+    let () = elf.truncate(64);
+    // e_phentsize, e_phnum, e_shentsize, e_shnum
+    let () = elf[0x36..0x3e].copy_from_slice(&[0xff; 8]);
+    let err = read_build_id(&mut elf.as_slice()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    assert_eq!(check_prefix(&elf, false, "ab"), Verdict::NeedMore);
+  }
+
+  /// Check that we judge prefixes of data correctly.
+  #[test]
+  fn prefix_checking() {
+    let elf = make_elf(true, false, true, &[0xab, 0xcd]);
+    assert_eq!(check_prefix(&elf, true, "abcd"), Verdict::Match);
+    assert_eq!(check_prefix(&elf, true, "abce"), Verdict::Mismatch);
+    assert_eq!(check_prefix(&elf[..20], false, "abcd"), Verdict::NeedMore);
+    assert_eq!(check_prefix(&elf[..20], true, "abcd"), Verdict::Mismatch);
+    assert_eq!(check_prefix(b"<html>", false, "abcd"), Verdict::Mismatch);
+
+    // Section headers located beyond what we are willing to inspect.
+    let mut prefix = make_elf(true, false, false, &[0xab, 0xcd]);
+    let shoff = (2 * MAX_VERIFY_PREFIX as u64).to_le_bytes();
+    let () = prefix[0x28..0x30].copy_from_slice(&shoff);
+    let () = prefix.resize(MAX_VERIFY_PREFIX, 0);
+    assert_eq!(check_prefix(&prefix, false, "abcd"), Verdict::Unknown);
+  }
+}
@@ -0,0 +1,478 @@
+// Copyright (C) 2026 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+use std::error::Error as StdError;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::io::Error as IoError;
+use std::path::PathBuf;
+use std::result;
+
+use http::StatusCode;
+
+use crate::elf::BuildIdMismatch;
+use crate::single_flight::Share;
+use crate::Attempt;
+use crate::Cancelled;
+use crate::HttpClientError;
+use crate::InvalidBuildId;
+use crate::SizeLimitExceeded;
+
+
+/// A result type using [`Error`] as the default error.
+pub(crate) type Result<T, E = Error> = result::Result<T, E>;
+
+
+/// Check whether `err` represents a cancellation, possibly wrapped in
+/// an I/O error.
+fn is_cancelled(err: &(dyn StdError + 'static)) -> bool {
+  if let Some(inner) = err.downcast_ref::<IoError>().and_then(IoError::get_ref) {
+    inner.is::<Cancelled>()
+  } else {
+    err.is::<Cancelled>()
+  }
+}
+
+
+/// A copy of the source chain of an error, used when sharing the error
+/// with other callers.
+#[derive(Debug)]
+struct SharedSource {
+  /// The message of the original error.
+  message: String,
+  /// The copy of the source of the original error, if any.
+  source: Option<Box<Self>>,
+}
+
+impl SharedSource {
+  fn new(err: &(dyn StdError + 'static)) -> Self {
+    Self {
+      message: err.to_string(),
+      source: err.source().map(|source| Box::new(Self::new(source))),
+    }
+  }
+
+  fn boxed(err: &(dyn StdError + 'static)) -> Box<dyn StdError + Send + Sync> {
+    Box::new(Self::new(err))
+  }
+}
+
+impl Display for SharedSource {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    f.write_str(&self.message)
+  }
+}
+
+impl StdError for SharedSource {
+  fn source(&self) -> Option<&(dyn StdError + 'static)> {
+    self.source.as_deref().map(|source| source as _)
+  }
+}
+
+
+/// The error type used by this crate.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+  /// A build ID is malformed.
+  InvalidBuildId(InvalidBuildId),
+  /// A URL could not be parsed or is unsuitable for use.
+  InvalidUrl {
+    /// The offending URL.
+    url: String,
+    /// The reason the URL is invalid.
+    source: Box<dyn StdError + Send + Sync>,
+  },
+  /// A source path or section name is unsuitable for use in a
+  /// request.
+  InvalidPath {
+    /// The offending path or name.
+    path: String,
+    /// The reason the path is invalid.
+    source: Box<dyn StdError + Send + Sync>,
+  },
+  /// A server responded with a non-success HTTP status.
+  HttpStatus {
+    /// The URL of the failed request.
+    url: String,
+    /// The status the server responded with.
+    status: StatusCode,
+    /// All requests made as part of a fetch.
+    attempts: Vec<Attempt>,
+  },
+  /// A request could not be issued or the transfer of data failed,
+  /// e.g., because a server is unreachable or timed out.
+  Transport {
+    /// The URL of the failed request.
+    url: String,
+    /// The underlying error.
+    source: HttpClientError,
+    /// All requests made as part of a fetch.
+    attempts: Vec<Attempt>,
+  },
+  /// A server provided data that does not belong to the requested build
+  /// ID.
+  BuildIdMismatch {
+    /// The URL of the request.
+    url: String,
+    /// All requests made as part of a fetch.
+    attempts: Vec<Attempt>,
+  },
+  /// A response exceeded the configured maximum size.
+  TooLarge(SizeLimitExceeded),
+  /// The operation was cancelled via a
+  /// [`CancellationToken`][crate::CancellationToken].
+  Cancelled,
+  /// A server reported malformed metadata.
+  InvalidMetadata {
+    /// The URL of the metadata request.
+    url: String,
+    /// The reason the metadata is considered malformed.
+    source: Box<dyn StdError + Send + Sync>,
+  },
+  /// An environment variable is missing or contains an invalid value.
+  InvalidEnv {
+    /// The name of the variable.
+    var: String,
+    /// The reason the value is invalid, if any.
+    source: Option<Box<dyn StdError + Send + Sync>>,
+  },
+  /// An I/O error occurred while accessing a file.
+  Io {
+    /// The path of the file.
+    path: PathBuf,
+    /// The underlying error.
+    source: IoError,
+  },
+  /// An ELF file could not be read or is malformed.
+  Elf {
+    /// The path of the file, if known.
+    path: Option<PathBuf>,
+    /// The underlying error.
+    source: IoError,
+  },
+  /// An error occurred while accessing the cache.
+  Cache {
+    /// The path inside the cache that was accessed.
+    path: PathBuf,
+    /// The underlying error.
+    source: IoError,
+  },
+}
+
+impl Error {
+  /// Create an error from one reported by an `HttpClient` when
+  /// requesting `url`.
+  pub(crate) fn from_http(err: HttpClientError, url: &str) -> Self {
+    match err {
+      HttpClientError::StatusCode(status) => Self::HttpStatus {
+        url: url.to_string(),
+        status,
+        attempts: Vec::new(),
+      },
+      HttpClientError::InvalidUrl(source) => Self::InvalidUrl {
+        url: url.to_string(),
+        source,
+      },
+      HttpClientError::Other(err) if is_cancelled(&*err) => Self::Cancelled,
+      HttpClientError::Other(err) if err.is::<BuildIdMismatch>() => Self::BuildIdMismatch {
+        url: url.to_string(),
+        attempts: Vec::new(),
+      },
+      err => Self::Transport {
+        url: url.to_string(),
+        source: err,
+        attempts: Vec::new(),
+      },
+    }
+  }
+
+  /// Create an error from one reported while reading the data of a
+  /// response from `url`.
+  pub(crate) fn from_read(err: IoError, url: &str) -> Self {
+    if let Some(inner) = err.get_ref() {
+      if let Some(inner) = inner.downcast_ref::<SizeLimitExceeded>() {
+        return Self::TooLarge(inner.clone())
+      }
+    }
+
+    if is_cancelled(&err) {
+      return Self::Cancelled
+    }
+
+    Self::Transport {
+      url: url.to_string(),
+      source: HttpClientError::Other(Box::new(err)),
+      attempts: Vec::new(),
+    }
+  }
+
+  /// Attach the requests made as part of a fetch to the error.
+  pub(crate) fn with_attempts(mut self, new: Vec<Attempt>) -> Self {
+    match &mut self {
+      Self::HttpStatus { attempts, .. }
+      | Self::Transport { attempts, .. }
+      | Self::BuildIdMismatch { attempts, .. } => *attempts = new,
+      _ => (),
+    }
+    self
+  }
+
+  /// Retrieve all requests made as part of the fetch that failed with
+  /// this error, in the order of the servers at the configured base
+  /// URLs.
+  ///
+  /// Errors not caused by failed requests report an empty list.
+  pub fn attempts(&self) -> &[Attempt] {
+    match self {
+      Self::HttpStatus { attempts, .. }
+      | Self::Transport { attempts, .. }
+      | Self::BuildIdMismatch { attempts, .. } => attempts,
+      _ => &[],
+    }
+  }
+
+  /// Create an error for `path` being invalid for `reason`.
+  pub(crate) fn invalid_path(path: &str, reason: &'static str) -> Self {
+    Self::InvalidPath {
+      path: path.to_string(),
+      source: reason.into(),
+    }
+  }
+
+  /// Create an error for the environment variable `var` being invalid.
+  pub(crate) fn invalid_env<E>(var: &str, source: Option<E>) -> Self
+  where
+    E: Into<Box<dyn StdError + Send + Sync>>,
+  {
+    Self::InvalidEnv {
+      var: var.to_string(),
+      source: source.map(E::into),
+    }
+  }
+}
+
+impl Display for Error {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    match self {
+      Self::InvalidBuildId(err) => Display::fmt(err, f),
+      Self::InvalidUrl { url, .. } => write!(f, "URL `{url}` is invalid"),
+      Self::InvalidPath { path, .. } => write!(f, "path `{path}` is invalid"),
+      Self::HttpStatus { url, status, .. } => {
+        write!(f, "request to `{url}` failed with HTTP status {status}")
+      },
+      Self::Transport { url, .. } => write!(f, "failed to issue request to `{url}`"),
+      Self::BuildIdMismatch { url, .. } => {
+        write!(
+          f,
+          "data received from `{url}` does not match the requested build ID"
+        )
+      },
+      Self::TooLarge(err) => Display::fmt(err, f),
+      Self::Cancelled => Display::fmt(&Cancelled, f),
+      Self::InvalidMetadata { url, .. } => {
+        write!(f, "received invalid metadata from `{url}`")
+      },
+      Self::InvalidEnv { var, .. } => {
+        write!(f, "environment variable {var} is missing or invalid")
+      },
+      Self::Io { path, .. } => write!(f, "failed to access `{}`", path.display()),
+      Self::Elf {
+        path: Some(path), ..
+      } => {
+        write!(f, "failed to read ELF file `{}`", path.display())
+      },
+      Self::Elf { path: None, .. } => write!(f, "failed to read ELF data"),
+      Self::Cache { path, .. } => {
+        write!(f, "failed to access cache at `{}`", path.display())
+      },
+    }
+  }
+}
+
+impl StdError for Error {
+  fn source(&self) -> Option<&(dyn StdError + 'static)> {
+    match self {
+      Self::InvalidUrl { source, .. }
+      | Self::InvalidPath { source, .. }
+      | Self::InvalidMetadata { source, .. } => Some(&**source),
+      Self::Transport { source, .. } => Some(source),
+      Self::InvalidEnv { source, .. } => source.as_deref().map(|err| err as _),
+      Self::Io { source, .. } | Self::Elf { source, .. } | Self::Cache { source, .. } => {
+        Some(source)
+      },
+      Self::InvalidBuildId(..)
+      | Self::HttpStatus { .. }
+      | Self::BuildIdMismatch { .. }
+      | Self::TooLarge(..)
+      | Self::Cancelled => None,
+    }
+  }
+}
+
+impl Share for Error {
+  /// Create a copy of the error, as reported by a fetch shared between
+  /// multiple callers.
+  ///
+  /// Sources are copied by message, as they can't be cloned. A
+  /// cancellation only concerns the caller that cancelled and is not
+  /// shared.
+  fn share(&self) -> Option<Self> {
+    let io_err = |err: &IoError| IoError::new(err.kind(), SharedSource::new(err));
+    let err = match self {
+      Self::InvalidBuildId(err) => Self::InvalidBuildId(err.clone()),
+      Self::InvalidUrl { url, source } => Self::InvalidUrl {
+        url: url.clone(),
+        source: SharedSource::boxed(&**source),
+      },
+      Self::InvalidPath { path, source } => Self::InvalidPath {
+        path: path.clone(),
+        source: SharedSource::boxed(&**source),
+      },
+      Self::HttpStatus {
+        url,
+        status,
+        attempts,
+      } => Self::HttpStatus {
+        url: url.clone(),
+        status: *status,
+        attempts: attempts.clone(),
+      },
+      Self::Transport {
+        url,
+        source,
+        attempts,
+      } => Self::Transport {
+        url: url.clone(),
+        source: match source {
+          HttpClientError::StatusCode(status) => HttpClientError::StatusCode(*status),
+          HttpClientError::InvalidUrl(err) => {
+            HttpClientError::InvalidUrl(SharedSource::boxed(&**err))
+          },
+          HttpClientError::Other(err) => HttpClientError::Other(SharedSource::boxed(&**err)),
+        },
+        attempts: attempts.clone(),
+      },
+      Self::BuildIdMismatch { url, attempts } => Self::BuildIdMismatch {
+        url: url.clone(),
+        attempts: attempts.clone(),
+      },
+      Self::TooLarge(err) => Self::TooLarge(err.clone()),
+      Self::Cancelled => return None,
+      Self::InvalidMetadata { url, source } => Self::InvalidMetadata {
+        url: url.clone(),
+        source: SharedSource::boxed(&**source),
+      },
+      Self::InvalidEnv { var, source } => Self::InvalidEnv {
+        var: var.clone(),
+        source: source.as_deref().map(|err| SharedSource::boxed(err)),
+      },
+      Self::Io { path, source } => Self::Io {
+        path: path.clone(),
+        source: io_err(source),
+      },
+      Self::Elf { path, source } => Self::Elf {
+        path: path.clone(),
+        source: io_err(source),
+      },
+      Self::Cache { path, source } => Self::Cache {
+        path: path.clone(),
+        source: io_err(source),
+      },
+    };
+    Some(err)
+  }
+}
+
+impl From<InvalidBuildId> for Error {
+  fn from(err: InvalidBuildId) -> Self {
+    Self::InvalidBuildId(err)
+  }
+}
+
+impl From<Cancelled> for Error {
+  fn from(_cancelled: Cancelled) -> Self {
+    Self::Cancelled
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::io::ErrorKind;
+
+
+  /// Check that we classify errors reported by HTTP clients correctly.
+  #[test]
+  fn http_error_conversion() {
+    let url = "https://debug.infod/buildid/00/debuginfo";
+    let err = Error::from_http(HttpClientError::StatusCode(StatusCode::BAD_GATEWAY), url);
+    assert!(matches!(
+      err,
+      Error::HttpStatus {
+        status: StatusCode::BAD_GATEWAY,
+        ..
+      }
+    ));
+    assert_eq!(
+      err.to_string(),
+      format!("request to `{url}` failed with HTTP status 502 Bad Gateway")
+    );
+
+    let err = Error::from_http(HttpClientError::Other(Box::new(Cancelled)), url);
+    assert!(matches!(err, Error::Cancelled));
+
+    let io_err = IoError::new(ErrorKind::ConnectionRefused, "connection refused");
+    let err = Error::from_http(HttpClientError::Other(Box::new(io_err)), url);
+    assert!(matches!(err, Error::Transport { .. }));
+    // The `source` chain should lead us to the original error.
+    let source = err.source().unwrap().source().unwrap();
+    assert_eq!(source.to_string(), "connection refused");
+  }
+
+  /// Check that we classify errors reported while reading response
+  /// data correctly.
+  #[test]
+  fn read_error_conversion() {
+    let url = "https://debug.infod/";
+    let limit = SizeLimitExceeded {
+      limit: 5,
+      size: None,
+    };
+    let err = Error::from_read(IoError::new(ErrorKind::Other, limit.clone()), url);
+    assert!(matches!(err, Error::TooLarge(err) if err == limit));
+
+    let err = Error::from_read(IoError::new(ErrorKind::Other, Cancelled), url);
+    assert!(matches!(err, Error::Cancelled));
+
+    let err = Error::from_read(IoError::new(ErrorKind::TimedOut, "timed out"), url);
+    assert!(matches!(err, Error::Transport { .. }));
+  }
+
+  /// Make sure that shared errors retain their details.
+  #[test]
+  fn error_sharing() {
+    let url = "https://debug.infod/";
+    let io_err = IoError::new(ErrorKind::ConnectionRefused, "connection refused");
+    let err = Error::from_http(HttpClientError::Other(Box::new(io_err)), url);
+    let shared = err.share().unwrap();
+    assert!(matches!(&shared, Error::Transport { url: u, .. } if u == url));
+    assert_eq!(shared.to_string(), err.to_string());
+    let source = shared.source().unwrap().source().unwrap();
+    assert_eq!(source.to_string(), "connection refused");
+
+    let err = Error::Cache {
+      path: PathBuf::from("/cache"),
+      source: IoError::new(ErrorKind::PermissionDenied, "denied"),
+    };
+    let shared = err.share().unwrap();
+    assert!(
+      matches!(&shared, Error::Cache { source, .. } if source.kind() == ErrorKind::PermissionDenied)
+    );
+
+    assert!(Error::Cancelled.share().is_none());
+  }
+}
@@ -0,0 +1,366 @@
+// Copyright (C) 2026 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+use std::fs::read_dir;
+use std::fs::remove_dir;
+use std::fs::remove_file;
+use std::fs::symlink_metadata;
+use std::fs::OpenOptions;
+use std::io::ErrorKind;
+use std::io::Write as _;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use filetime::set_file_mtime;
+use filetime::FileTime;
+
+use crate::caching_client::cache_error;
+use crate::caching_client::ignore_not_found;
+use crate::error::Result;
+use crate::lock::is_lock_file;
+use crate::log::debug;
+
+
+/// The name of the file in the cache root containing the number of
+/// seconds between cleanups, the modification time of which records
+/// the last cleanup.
+pub(crate) const CLEAN_INTERVAL_FILE: &str = "cache_clean_interval_s";
+/// The name of the file in the cache root containing the number of
+/// seconds after which unused entries are removed.
+pub(crate) const MAX_UNUSED_AGE_FILE: &str = "max_unused_age_s";
+/// The interval between cleanups by default, in seconds.
+pub(crate) const DEFAULT_CLEAN_INTERVAL_S: u64 = 86400;
+/// The time after which unused entries are removed by default, in
+/// seconds.
+pub(crate) const DEFAULT_MAX_UNUSED_AGE_S: u64 = 604800;
+
+
+/// A file in the cache.
+#[derive(Debug)]
+struct Entry {
+  /// The path to the file.
+  path: PathBuf,
+  /// The size of the file, in bytes.
+  size: u64,
+  /// The time the file was last used.
+  used: SystemTime,
+}
+
+
+/// Collect all files in `dir` and its sub-directories into `entries`,
+/// removing directories that are empty.
+fn collect(dir: &Path, entries: &mut Vec<Entry>) -> Result<()> {
+  let iter = match read_dir(dir) {
+    Ok(iter) => iter,
+    // Another cleanup may have raced us.
+    Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+    Err(err) => return Err(cache_error(dir, err)),
+  };
+
+  let mut empty = true;
+  for result in iter {
+    let entry = result.map_err(|err| cache_error(dir, err))?;
+    let path = entry.path();
+    let metadata = match symlink_metadata(&path) {
+      Ok(metadata) => metadata,
+      Err(err) if err.kind() == ErrorKind::NotFound => continue,
+      Err(err) => return Err(cache_error(&path, err)),
+    };
+
+    empty = false;
+    if metadata.is_dir() {
+      let () = collect(&path, entries)?;
+    } else if is_lock_file(&entry.file_name()) {
+      // Lock files are not cache entries and are removed by their
+      // owners.
+      continue
+    } else {
+      // Access times may not be maintained by the file system, in
+      // which case we fall back to the modification time.
+      let modified = metadata.modified().map_err(|err| cache_error(&path, err))?;
+      let used = metadata
+        .accessed()
+        .map_or(modified, |accessed| accessed.max(modified));
+      let entry = Entry {
+        path,
+        size: metadata.len(),
+        used,
+      };
+      let () = entries.push(entry);
+    }
+  }
+
+  if empty {
+    // The directory may have been populated concurrently, in which case
+    // we just leave it be.
+    let _result = remove_dir(dir);
+  }
+  Ok(())
+}
+
+
+/// The policy for evicting entries from the cache.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Eviction {
+  /// The interval at which the cache is cleaned up.
+  pub(crate) interval: Duration,
+  /// The time after which entries that have not been used are removed.
+  pub(crate) max_unused_age: Option<Duration>,
+  /// The maximum total size of all entries, in bytes.
+  pub(crate) max_size: Option<u64>,
+}
+
+impl Default for Eviction {
+  fn default() -> Self {
+    Self {
+      interval: Duration::from_secs(DEFAULT_CLEAN_INTERVAL_S),
+      max_unused_age: Some(Duration::from_secs(DEFAULT_MAX_UNUSED_AGE_S)),
+      max_size: None,
+    }
+  }
+}
+
+impl Eviction {
+  /// Clean up the cache at `cache_dir` if the configured interval has
+  /// passed since the last cleanup.
+  ///
+  /// The first check merely records the current time as the time of the
+  /// last cleanup.
+  pub(crate) fn clean_if_due(&self, cache_dir: &Path) -> Result<()> {
+    let path = cache_dir.join(CLEAN_INTERVAL_FILE);
+    let modified = match symlink_metadata(&path) {
+      Ok(metadata) => metadata.modified().map_err(|err| cache_error(&path, err))?,
+      Err(err) if err.kind() == ErrorKind::NotFound => return record_clean(cache_dir),
+      Err(err) => return Err(cache_error(&path, err)),
+    };
+
+    let elapsed = SystemTime::now()
+      .duration_since(modified)
+      .unwrap_or_default();
+    if elapsed >= self.interval {
+      self.clean(cache_dir)
+    } else {
+      Ok(())
+    }
+  }
+
+  /// Clean up the cache at `cache_dir`, removing entries that have not
+  /// been used for longer than the maximum unused age and then, least
+  /// recently used first, as many as necessary to bring the cache within
+  /// its maximum size.
+  pub(crate) fn clean(&self, cache_dir: &Path) -> Result<()> {
+    let mut entries = Vec::new();
+    let iter = read_dir(cache_dir).map_err(|err| cache_error(cache_dir, err))?;
+    for result in iter {
+      let entry = result.map_err(|err| cache_error(cache_dir, err))?;
+      let path = entry.path();
+      // Files in the cache root hold configuration and in-progress
+      // downloads, not entries.
+      let is_dir = entry
+        .file_type()
+        .map_err(|err| cache_error(&path, err))?
+        .is_dir();
+      if is_dir {
+        let () = collect(&path, &mut entries)?;
+      }
+    }
+
+    let now = SystemTime::now();
+    let () = entries.sort_by_key(|entry| entry.used);
+    let mut total = entries.iter().map(|entry| entry.size).sum::<u64>();
+
+    for entry in entries {
+      let age = now.duration_since(entry.used).unwrap_or_default();
+      let expired = self
+        .max_unused_age
+        .map_or(false, |max_unused_age| age > max_unused_age);
+      let oversized = self.max_size.map_or(false, |max_size| total > max_size);
+      if !expired && !oversized {
+        // Entries are sorted by time of last use and the total size
+        // only ever shrinks, so none of the remaining ones is due for
+        // eviction either.
+        break
+      }
+
+      debug!("evicting `{}` from cache", entry.path.display());
+      let () = remove_file(&entry.path)
+        .or_else(ignore_not_found)
+        .map_err(|err| cache_error(&entry.path, err))?;
+      total -= entry.size;
+
+      // SANITY: All entries are located in sub-directories of the cache
+      //         root.
+      let dir = entry.path.parent().unwrap();
+      if dir != cache_dir {
+        // Fails if the directory is not empty, which is fine.
+        let _result = remove_dir(dir);
+      }
+    }
+
+    record_clean(cache_dir)
+  }
+}
+
+
+/// Record the current time as the time of the last cleanup of the
+/// cache at `cache_dir`.
+///
+/// The file is shared with other debuginfod clients. Just like
+/// elfutils, we only ever create it with the default interval and
+/// otherwise leave its contents alone, updating just its modification
+/// time.
+fn record_clean(cache_dir: &Path) -> Result<()> {
+  let path = cache_dir.join(CLEAN_INTERVAL_FILE);
+  let result = OpenOptions::new().write(true).create_new(true).open(&path);
+  match result {
+    Ok(mut file) => writeln!(file, "{DEFAULT_CLEAN_INTERVAL_S}"),
+    Err(err) if err.kind() == ErrorKind::AlreadyExists => set_file_mtime(&path, FileTime::now()),
+    Err(err) => Err(err),
+  }
+  .map_err(|err| cache_error(&path, err))
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::fs::create_dir_all;
+  use std::fs::read_to_string;
+  use std::fs::write;
+  use std::fs::File;
+  use std::time::UNIX_EPOCH;
+
+  use filetime::set_file_times;
+
+  use tempfile::tempdir;
+
+  use crate::lock::lock_path;
+
+
+  /// Create a cache entry at `path` with `size` bytes, last used at
+  /// `used`.
+  fn create_entry(path: &Path, size: u64, used: SystemTime) {
+    let () = create_dir_all(path.parent().unwrap()).unwrap();
+    let file = File::create(path).unwrap();
+    let () = file.set_len(size).unwrap();
+    let used = FileTime::from_system_time(used);
+    let () = set_file_times(path, used, used).unwrap();
+  }
+
+
+  /// Check that we remove entries that have not been used in a while.
+  #[test]
+  fn clean_unused() {
+    let cache_dir = tempdir().unwrap();
+    let cache_dir = cache_dir.path();
+    let old = cache_dir.join("00").join("debuginfo");
+    let () = create_entry(&old, 10, UNIX_EPOCH);
+    let new = cache_dir.join("01").join("debuginfo");
+    let () = create_entry(&new, 10, SystemTime::now());
+
+    let eviction = Eviction::default();
+    let () = eviction.clean(cache_dir).unwrap();
+    assert!(!old.exists());
+    assert!(!old.parent().unwrap().exists());
+    assert!(new.exists());
+    assert!(cache_dir.join(CLEAN_INTERVAL_FILE).exists());
+  }
+
+  /// Make sure that lock files are left alone.
+  #[test]
+  fn clean_locks() {
+    let cache_dir = tempdir().unwrap();
+    let cache_dir = cache_dir.path();
+    let lock = lock_path(&cache_dir.join("00").join("debuginfo"));
+    let () = create_entry(&lock, 0, UNIX_EPOCH);
+
+    let eviction = Eviction {
+      max_size: Some(0),
+      ..Default::default()
+    };
+    let () = eviction.clean(cache_dir).unwrap();
+    assert!(lock.exists());
+  }
+
+  /// Check that we evict the least recently used entries when the
+  /// cache exceeds its maximum size.
+  #[test]
+  fn clean_oversized() {
+    let cache_dir = tempdir().unwrap();
+    let cache_dir = cache_dir.path();
+    let now = SystemTime::now();
+    let paths = (0..3)
+      .map(|idx| {
+        let path = cache_dir.join(format!("0{idx}")).join("debuginfo");
+        let used = now
+          .checked_sub(Duration::from_secs(60 * (3 - idx)))
+          .unwrap();
+        let () = create_entry(&path, 10, used);
+        path
+      })
+      .collect::<Vec<_>>();
+
+    let eviction = Eviction {
+      max_size: Some(25),
+      ..Default::default()
+    };
+    let () = eviction.clean(cache_dir).unwrap();
+    assert!(!paths[0].exists());
+    assert!(paths[1].exists());
+    assert!(paths[2].exists());
+  }
+
+  /// Make sure that we only clean up once the configured interval has
+  /// passed.
+  #[test]
+  fn clean_interval() {
+    let cache_dir = tempdir().unwrap();
+    let cache_dir = cache_dir.path();
+    let path = cache_dir.join("00").join("debuginfo");
+    let () = create_entry(&path, 10, UNIX_EPOCH);
+
+    let eviction = Eviction::default();
+    // The first check only records the time.
+    let () = eviction.clean_if_due(cache_dir).unwrap();
+    assert!(path.exists());
+    let () = eviction.clean_if_due(cache_dir).unwrap();
+    assert!(path.exists());
+
+    let eviction = Eviction {
+      interval: Duration::ZERO,
+      ..Default::default()
+    };
+    let () = eviction.clean_if_due(cache_dir).unwrap();
+    assert!(!path.exists());
+  }
+
+  /// Make sure that we do not overwrite the shared cleanup interval
+  /// with our own.
+  #[test]
+  fn clean_interval_file() {
+    let cache_dir = tempdir().unwrap();
+    let cache_dir = cache_dir.path();
+    let path = cache_dir.join(CLEAN_INTERVAL_FILE);
+
+    let eviction = Eviction {
+      interval: Duration::from_secs(42),
+      ..Default::default()
+    };
+    let () = eviction.clean(cache_dir).unwrap();
+    assert_eq!(
+      read_to_string(&path).unwrap(),
+      format!("{DEFAULT_CLEAN_INTERVAL_S}\n")
+    );
+
+    let () = write(&path, "1337\n").unwrap();
+    let old = FileTime::from_system_time(UNIX_EPOCH);
+    let () = set_file_times(&path, old, old).unwrap();
+    let () = eviction.clean(cache_dir).unwrap();
+    assert_eq!(read_to_string(&path).unwrap(), "1337\n");
+    let modified = path.metadata().unwrap().modified().unwrap();
+    assert!(modified > UNIX_EPOCH);
+  }
+}
@@ -6,8 +6,13 @@ use std::fmt::Debug;
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std::fmt::Result as FmtResult;
+use std::io::Error as IoError;
+use std::io::ErrorKind;
 use std::io::Read;
 
+use http::Method;
+use http::Request;
+use http::Response;
 use http::StatusCode;
 
 /// An error that occurred while performing an HTTP request.
@@ -51,14 +56,49 @@ impl Error for HttpClientError {
 
 
 /// A trait representing an entity that can be read from.
-pub trait Readable: Read + Debug {}
+pub trait Readable: Read + Debug + Send {}
 
-impl<R> Readable for R where R: Read + Debug {}
+impl<R> Readable for R where R: Read + Debug + Send {}
 
 
 /// A trait representing HTTP client capable of performing blocking GET
 /// requests, used to download debug information from `debuginfod` servers.
 pub trait HttpClient: Debug {
   /// Perform a blocking HTTP GET request to the specified URL.
-  fn get(&self, url: &str) -> Result<Box<dyn Readable>, HttpClientError>;
+  ///
+  /// On success, the returned response provides access to the response
+  /// headers as well as a reader for the body. Responses with a
+  /// non-success status code should be reported as
+  /// [`HttpClientError::StatusCode`].
+  fn get(&self, url: &str) -> Result<Response<Box<dyn Readable>>, HttpClientError>;
+
+  /// Perform a blocking HTTP request as described by `request`.
+  ///
+  /// In contrast to [`HttpClient::get`], this method allows for the
+  /// provision of request headers (such as `User-Agent` or
+  /// `Authorization`), which implementations are expected to send
+  /// along. Responses are to be reported in the same manner, except
+  /// that responses with a non-success status code may also be returned
+  /// as-is. Doing so provides access to their headers, e.g., to honor a
+  /// `Retry-After` header.
+  ///
+  /// Requests issued on behalf of a cancellable operation carry the
+  /// [`CancellationToken`][crate::CancellationToken] as an extension.
+  /// Unless timeouts are configured, waiting for a response is only
+  /// abandoned on cancellation if the implementation honors it.
+  ///
+  /// The default implementation forwards `GET` requests to
+  /// [`HttpClient::get`], dropping all request headers, and fails all
+  /// other requests. Implementations are encouraged to override it.
+  fn send(&self, request: Request<()>) -> Result<Response<Box<dyn Readable>>, HttpClientError> {
+    if request.method() != Method::GET {
+      let err = IoError::new(
+        ErrorKind::Unsupported,
+        format!("HTTP method {} is not supported", request.method()),
+      );
+      return Err(HttpClientError::Other(Box::new(err)))
+    }
+
+    self.get(&request.uri().to_string())
+  }
 }
@@ -13,25 +13,71 @@
 )]
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
+#[cfg(all(feature = "fs-cache", feature = "tokio"))]
+mod async_caching_client;
+#[cfg(feature = "tokio")]
+mod async_client;
+#[cfg(feature = "tokio")]
+mod async_http_client;
+#[cfg(all(feature = "reqwest", feature = "tokio"))]
+mod async_reqwest_client;
+mod attempt;
 mod buildid;
 #[cfg(feature = "fs-cache")]
 mod caching_client;
+mod cancel;
 mod client;
+mod elf;
+mod error;
+#[cfg(feature = "fs-cache")]
+mod eviction;
 mod http_client;
+#[cfg(feature = "fs-cache")]
+mod lock;
+mod metadata;
+mod progress;
 #[cfg(feature = "reqwest")]
 mod reqwest_client;
+mod retry;
+mod single_flight;
+mod transfer;
 mod util;
 
+#[cfg(all(feature = "fs-cache", feature = "tokio"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "fs-cache", feature = "tokio"))))]
+pub use async_caching_client::AsyncCachingClient;
+#[cfg(feature = "tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+pub use async_client::AsyncClient;
+#[cfg(feature = "tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+pub use async_http_client::AsyncHttpClient;
+#[cfg(feature = "tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+pub use async_http_client::AsyncReadable;
+#[cfg(feature = "tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+pub use async_http_client::BoxFuture;
+pub use attempt::Attempt;
+pub use attempt::AttemptOutcome;
 pub use buildid::BuildId;
+pub use buildid::InvalidBuildId;
 #[cfg(feature = "fs-cache")]
 #[cfg_attr(docsrs, doc(cfg(feature = "fs-cache")))]
 pub use caching_client::CachingClient;
+pub use cancel::CancellationToken;
+pub use cancel::Cancelled;
 pub use client::Client;
 pub use client::ClientBuilder;
 pub use client::Response;
+pub use error::Error;
 pub use http_client::HttpClient;
 pub use http_client::HttpClientError;
 pub use http_client::Readable;
+pub use metadata::Metadata;
+pub use metadata::MetadataRecord;
+pub use progress::Progress;
+pub use transfer::SizeLimitExceeded;
 
 
 #[cfg(feature = "tracing")]
@@ -0,0 +1,299 @@
+// Copyright (C) 2026 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::fs::create_dir_all;
+use std::fs::read_to_string;
+use std::fs::remove_dir;
+use std::fs::remove_file;
+use std::fs::symlink_metadata;
+use std::fs::OpenOptions;
+use std::io::ErrorKind;
+use std::io::Write as _;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+use filetime::set_file_mtime;
+use filetime::FileTime;
+
+use crate::caching_client::cache_error;
+use crate::caching_client::ignore_not_found;
+use crate::error::Result;
+use crate::log::debug;
+
+
+/// The prefix of the name of a lock file guarding a cache entry. Lock
+/// files live next to the entry they guard.
+const LOCK_PREFIX: &str = ".";
+/// The suffix of the name of a lock file guarding a cache entry.
+const LOCK_SUFFIX: &str = ".lock";
+/// The interval at which we check whether a lock held by somebody else
+/// got released.
+pub(crate) const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// The age after which a lock is considered stale, no matter whether
+/// its owner is still alive.
+const STALE_LOCK_AGE: Duration = Duration::from_secs(3600);
+/// The interval at which the owner of a lock refreshes its
+/// modification time while downloading, so that it does not become
+/// stale.
+const LOCK_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+
+/// Retrieve the path of the lock file guarding the cache entry at
+/// `entry`.
+pub(crate) fn lock_path(entry: &Path) -> PathBuf {
+  let mut name = OsString::from(LOCK_PREFIX);
+  // SANITY: Cache entries are guaranteed to always have a file name.
+  let () = name.push(entry.file_name().unwrap());
+  let () = name.push(LOCK_SUFFIX);
+  entry.with_file_name(name)
+}
+
+
+/// Check whether `name` is the file name of a lock file.
+///
+/// Cache entries never start with a dot, so lock files can't be
+/// mistaken for them.
+pub(crate) fn is_lock_file(name: &OsStr) -> bool {
+  name.to_str().map_or(false, |name| {
+    name.starts_with(LOCK_PREFIX) && name.ends_with(LOCK_SUFFIX)
+  })
+}
+
+
+/// Read the PID of the owner of the lock file at `path`.
+///
+/// `None` is returned if the lock file does not exist (anymore) or the
+/// owner has not yet written its PID.
+fn read_owner(path: &Path) -> Result<Option<u32>> {
+  match read_to_string(path) {
+    Ok(contents) => Ok(contents.trim().parse::<u32>().ok()),
+    Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+    Err(err) => Err(cache_error(path, err)),
+  }
+}
+
+
+/// Check whether the lock file at `path` was left behind by a process
+/// that no longer exists.
+///
+/// The owner's liveness can only be checked on systems providing
+/// `/proc`. As owners in a different PID namespace appear dead, we
+/// additionally require the lock to have missed two refreshes before
+/// breaking it. Breaking a lock held by a live owner only results in a
+/// duplicate download, as entries are only ever persisted atomically
+/// and without replacing existing ones.
+fn is_stale(path: &Path) -> Result<bool> {
+  let modified = match symlink_metadata(path) {
+    Ok(metadata) => metadata.modified().map_err(|err| cache_error(path, err))?,
+    // The lock got released in the meantime.
+    Err(err) if err.kind() == ErrorKind::NotFound => return Ok(true),
+    Err(err) => return Err(cache_error(path, err)),
+  };
+
+  let age = SystemTime::now()
+    .duration_since(modified)
+    .unwrap_or_default();
+  if age > STALE_LOCK_AGE {
+    return Ok(true)
+  }
+
+  // A lock refreshed recently is in use, no matter what `/proc` says.
+  if age <= LOCK_REFRESH_INTERVAL * 2 {
+    return Ok(false)
+  }
+
+  match read_owner(path)? {
+    Some(pid) if Path::new("/proc/self").exists() => {
+      Ok(!Path::new("/proc").join(pid.to_string()).exists())
+    },
+    // Either we cannot check whether the owner is alive or it has not
+    // yet written its PID.
+    _ => Ok(false),
+  }
+}
+
+
+/// Update the modification time of the lock file at `path`, marking
+/// it as still in use.
+pub(crate) fn refresh_lock(path: &Path) -> Result<()> {
+  set_file_mtime(path, FileTime::now()).map_err(|err| cache_error(path, err))
+}
+
+
+/// A lock file, removed when dropped.
+#[derive(Debug)]
+pub(crate) struct LockFile {
+  /// The path to the lock file.
+  path: PathBuf,
+  /// The time the lock file was last refreshed.
+  refreshed: Instant,
+}
+
+impl LockFile {
+  /// Try to acquire the lock file at `path`.
+  ///
+  /// `None` is returned if the lock is held by another live process or
+  /// thread. Stale locks are broken.
+  pub(crate) fn try_acquire(path: &Path) -> Result<Option<Self>> {
+    // SANITY: Lock files are guaranteed to always have a parent.
+    let dir = path.parent().unwrap();
+
+    loop {
+      let () = create_dir_all(dir).map_err(|err| cache_error(dir, err))?;
+
+      match OpenOptions::new().write(true).create_new(true).open(path) {
+        Ok(mut file) => {
+          let lock = Self {
+            path: path.to_path_buf(),
+            refreshed: Instant::now(),
+          };
+          let () = writeln!(file, "{}", process::id()).map_err(|err| cache_error(path, err))?;
+          break Ok(Some(lock))
+        },
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+          if !is_stale(path)? {
+            break Ok(None)
+          }
+
+          // Should another process have broken the lock and acquired
+          // it in the meantime, we may remove its lock. That results
+          // in a duplicate download at worst.
+          debug!("breaking stale lock `{}`", path.display());
+          let () = remove_file(path)
+            .or_else(ignore_not_found)
+            .map_err(|err| cache_error(path, err))?;
+        },
+        // The directory may have been removed by a concurrent cleanup.
+        Err(err) if err.kind() == ErrorKind::NotFound => (),
+        Err(err) => break Err(cache_error(path, err)),
+      }
+    }
+  }
+
+  /// Check whether the lock is due for a refresh, returning the path
+  /// to refresh via [`refresh_lock`] if so.
+  ///
+  /// Owners of a lock are expected to check periodically while
+  /// downloading, so that lengthy downloads don't render the lock
+  /// stale.
+  pub(crate) fn refresh_due(&mut self) -> Option<&Path> {
+    let now = Instant::now();
+    if now.saturating_duration_since(self.refreshed) < LOCK_REFRESH_INTERVAL {
+      return None
+    }
+    self.refreshed = now;
+    Some(&self.path)
+  }
+}
+
+impl Drop for LockFile {
+  fn drop(&mut self) {
+    // The lock may have been broken and acquired by somebody else in
+    // the meantime, in which case it is not ours to remove.
+    if matches!(read_owner(&self.path), Ok(Some(pid)) if pid == process::id()) {
+      let _result = remove_file(&self.path);
+    }
+    // Don't leave behind an empty directory if the fetch failed. This
+    // fails if the directory is not empty, which is fine.
+    // SANITY: Lock files are guaranteed to always have a parent.
+    let _result = remove_dir(self.path.parent().unwrap());
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::fs::write;
+  use std::time::UNIX_EPOCH;
+
+  use tempfile::tempdir;
+
+
+  /// Check that a lock can only be held once at a time.
+  #[test]
+  fn lock_exclusion() {
+    let dir = tempdir().unwrap();
+    let path = lock_path(&dir.path().join("00").join("debuginfo"));
+    assert!(is_lock_file(path.file_name().unwrap()));
+
+    let lock = LockFile::try_acquire(&path).unwrap().unwrap();
+    let pid = read_to_string(&path).unwrap();
+    assert_eq!(pid.trim(), process::id().to_string());
+    assert!(LockFile::try_acquire(&path).unwrap().is_none());
+
+    // Other entries of the same build ID are locked independently.
+    let other = lock_path(&dir.path().join("00").join("executable"));
+    let other = LockFile::try_acquire(&other).unwrap().unwrap();
+    let () = drop(other);
+
+    let () = drop(lock);
+    assert!(!path.exists());
+    assert!(!path.parent().unwrap().exists());
+    let _lock = LockFile::try_acquire(&path).unwrap().unwrap();
+  }
+
+  /// Make sure that we break stale locks.
+  #[test]
+  fn lock_stale() {
+    let dir = tempdir().unwrap();
+    let path = lock_path(&dir.path().join("00").join("debuginfo"));
+    let () = create_dir_all(path.parent().unwrap()).unwrap();
+
+    // A lock held by ourselves, but a long time ago.
+    let () = write(&path, format!("{}\n", process::id())).unwrap();
+    let () = set_file_mtime(&path, FileTime::from_system_time(UNIX_EPOCH)).unwrap();
+    let lock = LockFile::try_acquire(&path).unwrap().unwrap();
+    let () = drop(lock);
+
+    if Path::new("/proc/self").exists() {
+      // A lock held by a process that we cannot see, but which got
+      // refreshed recently. The owner may live in a different PID
+      // namespace.
+      let () = create_dir_all(path.parent().unwrap()).unwrap();
+      let () = write(&path, format!("{}\n", u32::MAX)).unwrap();
+      assert!(LockFile::try_acquire(&path).unwrap().is_none());
+
+      // Once it missed a few refreshes, the owner is considered dead.
+      let modified = SystemTime::now() - LOCK_REFRESH_INTERVAL * 3;
+      let () = set_file_mtime(&path, FileTime::from_system_time(modified)).unwrap();
+      let _lock = LockFile::try_acquire(&path).unwrap().unwrap();
+    }
+  }
+
+  /// Make sure that we don't remove a lock that somebody else acquired
+  /// after breaking ours.
+  #[test]
+  fn lock_broken() {
+    let dir = tempdir().unwrap();
+    let path = lock_path(&dir.path().join("00").join("debuginfo"));
+
+    let lock = LockFile::try_acquire(&path).unwrap().unwrap();
+    let () = write(&path, format!("{}\n", u32::MAX)).unwrap();
+    let () = drop(lock);
+    assert_eq!(read_to_string(&path).unwrap(), format!("{}\n", u32::MAX));
+  }
+
+  /// Check that locks are refreshed periodically.
+  #[test]
+  fn lock_refresh() {
+    let dir = tempdir().unwrap();
+    let path = lock_path(&dir.path().join("00").join("debuginfo"));
+
+    let mut lock = LockFile::try_acquire(&path).unwrap().unwrap();
+    assert_eq!(lock.refresh_due(), None);
+
+    lock.refreshed -= LOCK_REFRESH_INTERVAL;
+    let () = set_file_mtime(&path, FileTime::from_system_time(UNIX_EPOCH)).unwrap();
+    let () = refresh_lock(lock.refresh_due().unwrap()).unwrap();
+    assert_eq!(lock.refresh_due(), None);
+    assert!(!is_stale(&path).unwrap());
+  }
+}
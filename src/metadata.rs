@@ -0,0 +1,172 @@
+// Copyright (C) 2026 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+use std::error::Error as StdError;
+
+use serde_json::Map;
+use serde_json::Value;
+
+
+/// The error reported for malformed metadata.
+type ParseError = Box<dyn StdError + Send + Sync>;
+
+
+/// A single file known to a debuginfod server, as reported by its
+/// `/metadata` endpoint.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MetadataRecord {
+  /// The type of the file, e.g., `executable` or `debuginfo`.
+  pub kind: String,
+  /// The hex formatted build ID of the file.
+  pub build_id: String,
+  /// The path of the file.
+  pub file: String,
+  /// The path of the archive (e.g., an RPM or Debian package) that
+  /// contains the file, if any.
+  pub archive: Option<String>,
+}
+
+impl MetadataRecord {
+  fn from_json(object: &Map<String, Value>) -> Result<Self, ParseError> {
+    fn string(object: &Map<String, Value>, key: &str) -> Result<Option<String>, ParseError> {
+      object
+        .get(key)
+        .map(|value| {
+          value
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| format!("`{key}` attribute is not a string").into())
+        })
+        .transpose()
+    }
+
+    fn required(object: &Map<String, Value>, key: &str) -> Result<String, ParseError> {
+      string(object, key)?.ok_or_else(|| format!("`{key}` attribute is missing").into())
+    }
+
+    let slf = Self {
+      kind: required(object, "type")?,
+      build_id: required(object, "buildid")?,
+      file: required(object, "file")?,
+      archive: string(object, "archive")?,
+    };
+    Ok(slf)
+  }
+}
+
+
+/// The result of a metadata query, merged across all servers.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metadata {
+  /// The records matching the query, in the order in which they were
+  /// reported. Duplicates reported by multiple servers are removed.
+  pub records: Vec<MetadataRecord>,
+  /// Whether the result is known to be complete.
+  ///
+  /// Servers may report a partial result, e.g., when a query matched
+  /// too many files. A result is also considered incomplete if any of
+  /// the servers could not be queried successfully.
+  pub complete: bool,
+}
+
+
+/// Parse the JSON document returned by a server's `/metadata`
+/// endpoint, returning the contained records and whether the server
+/// considers them complete.
+pub(crate) fn parse_metadata(data: &[u8]) -> Result<(Vec<MetadataRecord>, bool), ParseError> {
+  let value = serde_json::from_slice::<Value>(data)?;
+  let object = value.as_object().ok_or("metadata is not a JSON object")?;
+  let records = object
+    .get("results")
+    .ok_or("metadata does not contain `results` attribute")?
+    .as_array()
+    .ok_or("metadata `results` attribute is not an array")?
+    .iter()
+    .enumerate()
+    .map(|(idx, result)| {
+      result
+        .as_object()
+        .ok_or_else(|| ParseError::from("record is not a JSON object"))
+        .and_then(MetadataRecord::from_json)
+        .map_err(|err| format!("metadata record {idx} is invalid: {err}").into())
+    })
+    .collect::<Result<Vec<_>, ParseError>>()?;
+  // Servers predating the introduction of the attribute only ever
+  // reported complete results.
+  let complete = object
+    .get("complete")
+    .map(|value| {
+      value
+        .as_bool()
+        .ok_or("metadata `complete` attribute is not a boolean")
+    })
+    .transpose()?
+    .unwrap_or(true);
+
+  Ok((records, complete))
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+
+  /// Check that we can parse a metadata document as returned by a
+  /// debuginfod server.
+  #[test]
+  fn metadata_parsing() {
+    let data = br#"{
+      "results": [
+        {
+          "type": "executable",
+          "buildid": "aeb9a983ace1fb047b2341b1950165440fb2a8b9",
+          "file": "/usr/bin/sleep",
+          "archive": "/packages/coreutils-9.1-12.fc38.x86_64.rpm"
+        },
+        {
+          "type": "debuginfo",
+          "buildid": "aeb9a983ace1fb047b2341b1950165440fb2a8b9",
+          "file": "/usr/lib/debug/usr/bin/sleep.debug"
+        }
+      ],
+      "complete": false
+    }"#;
+    let (records, complete) = parse_metadata(data).unwrap();
+    assert!(!complete);
+    assert_eq!(
+      records,
+      vec![
+        MetadataRecord {
+          kind: "executable".to_string(),
+          build_id: "aeb9a983ace1fb047b2341b1950165440fb2a8b9".to_string(),
+          file: "/usr/bin/sleep".to_string(),
+          archive: Some("/packages/coreutils-9.1-12.fc38.x86_64.rpm".to_string()),
+        },
+        MetadataRecord {
+          kind: "debuginfo".to_string(),
+          build_id: "aeb9a983ace1fb047b2341b1950165440fb2a8b9".to_string(),
+          file: "/usr/lib/debug/usr/bin/sleep.debug".to_string(),
+          archive: None,
+        },
+      ]
+    );
+  }
+
+  /// Make sure that we reject malformed metadata documents.
+  #[test]
+  fn invalid_metadata_parsing() {
+    let data = [
+      &b"<html></html>"[..],
+      br#"[]"#,
+      br#"{"complete": true}"#,
+      br#"{"results": [{"type": "executable"}]}"#,
+      br#"{"results": [{"type": 42, "buildid": "00", "file": "/bin/true"}]}"#,
+      br#"{"results": [], "complete": "yes"}"#,
+    ];
+
+    for data in data {
+      let _err = parse_metadata(data).unwrap_err();
+    }
+  }
+}
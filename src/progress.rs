@@ -0,0 +1,149 @@
+// Copyright (C) 2026 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::io::stderr;
+use std::io::Write as _;
+use std::ops::Deref;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+
+/// Information about the progress of a download.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Progress<'prog> {
+  /// The number of bytes received so far.
+  pub received: u64,
+  /// The total number of bytes to receive, if known.
+  pub total: Option<u64>,
+  /// The URL of the server the data is received from.
+  pub server_url: &'prog str,
+  /// The hex formatted build ID the data is received for.
+  pub build_id: &'prog str,
+}
+
+
+/// A callback invoked as a download progresses.
+#[derive(Clone)]
+pub(crate) struct ProgressFn(Arc<dyn Fn(&Progress<'_>) + Send + Sync>);
+
+impl ProgressFn {
+  pub fn new<F>(f: F) -> Self
+  where
+    F: Fn(&Progress<'_>) + Send + Sync + 'static,
+  {
+    Self(Arc::new(f))
+  }
+
+  /// Create a callback reporting progress on stderr, in a manner
+  /// similar to what elfutils does.
+  pub fn stderr() -> Self {
+    /// The minimum time between two reports.
+    const INTERVAL: Duration = Duration::from_millis(100);
+
+    let last = Mutex::new(None::<Instant>);
+    Self::new(move |progress| {
+      let done = Some(progress.received) == progress.total;
+      let now = Instant::now();
+      // SANITY: We never panic while holding the lock.
+      let mut last = last.lock().unwrap();
+      if !done && matches!(*last, Some(last) if now.duration_since(last) < INTERVAL) {
+        return
+      }
+      *last = Some(now);
+
+      let mut stderr = stderr().lock();
+      let _result = if let Some(total) = progress.total {
+        write!(
+          stderr,
+          "\rDownloading from {} {}/{}",
+          progress.server_url, progress.received, total
+        )
+      } else {
+        write!(
+          stderr,
+          "\rDownloading from {} {}",
+          progress.server_url, progress.received
+        )
+      };
+      if done {
+        let _result = writeln!(stderr);
+      }
+    })
+  }
+}
+
+impl Deref for ProgressFn {
+  type Target = dyn Fn(&Progress<'_>) + Send + Sync;
+
+  fn deref(&self) -> &Self::Target {
+    &*self.0
+  }
+}
+
+impl Debug for ProgressFn {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    f.debug_tuple("ProgressFn")
+      .field(&Arc::as_ptr(&self.0))
+      .finish()
+  }
+}
+
+
+/// The state necessary for reporting the progress of a single
+/// transfer.
+#[derive(Debug)]
+pub(crate) struct ProgressReporter {
+  /// The callback to invoke.
+  callback: ProgressFn,
+  /// The URL of the server the data is received from.
+  server_url: String,
+  /// The hex formatted build ID the data is received for.
+  build_id: String,
+  /// The total number of bytes to receive, if known.
+  total: Option<u64>,
+  /// Whether the end of the data has been reported already.
+  done: bool,
+}
+
+impl ProgressReporter {
+  pub fn new(callback: ProgressFn, server_url: &str, build_id: &str, total: Option<u64>) -> Self {
+    Self {
+      callback,
+      server_url: server_url.to_string(),
+      build_id: build_id.to_string(),
+      total,
+      done: false,
+    }
+  }
+
+  /// Report that `received` bytes have been received so far, with
+  /// `eof` indicating whether the end of the data has been reached.
+  pub fn report(&mut self, received: u64, eof: bool) {
+    if eof {
+      if self.done {
+        return
+      }
+      self.done = true;
+      // Once all data has been received, we know the total.
+      self.total = self.total.or(Some(received));
+    }
+
+    let progress = Progress {
+      received,
+      total: self.total,
+      server_url: &self.server_url,
+      build_id: &self.build_id,
+    };
+    let () = (self.callback)(&progress);
+    // If the total was advertised, the last chunk of data concludes the
+    // transfer already and the end of the data need not be reported
+    // again.
+    self.done |= Some(received) == self.total;
+  }
+}
@@ -1,11 +1,15 @@
 // Copyright (C) 2025 Arvid Norlander <VorpalBlade@users.noreply.github.com>
 // SPDX-License-Identifier: (Apache-2.0 OR MIT)
 
-use http::Method;
+use std::time::Instant;
+
+use http::Request;
+use http::Response;
 
 use reqwest::blocking::Client as BlockingClient;
-use reqwest::blocking::Request;
+use reqwest::blocking::Request as BlockingRequest;
 
+use crate::transfer::Deadline;
 use crate::HttpClient;
 use crate::HttpClientError;
 use crate::Readable;
@@ -13,21 +17,133 @@ use crate::Readable;
 /// Implements the `HttpClient` trait for the `reqwest` crate.
 impl HttpClient for BlockingClient {
   /// Perform a blocking HTTP GET request to the specified URL.
-  fn get(&self, url: &str) -> Result<Box<dyn Readable>, HttpClientError> {
-    let resp = self
-      .execute(Request::new(
-        Method::GET,
-        url
-          .try_into()
-          .map_err(|err| HttpClientError::InvalidUrl(Box::new(err)))?,
-      ))
-      .map_err(|err| HttpClientError::Other(Box::new(err)))?;
+  fn get(&self, url: &str) -> Result<Response<Box<dyn Readable>>, HttpClientError> {
+    let request = Request::get(url)
+      .body(())
+      .map_err(|err| HttpClientError::InvalidUrl(Box::new(err)))?;
+    let response = self.send(request)?;
 
-    let status = resp.status();
+    let status = response.status();
     if !status.is_success() {
       return Err(HttpClientError::StatusCode(status));
     }
+    Ok(response)
+  }
+
+  /// Perform a blocking HTTP request, including all provided headers.
+  ///
+  /// Responses are reported irrespective of their status code. The
+  /// request, including the transfer of the response body, is aborted
+  /// once the deadline of the fetch it is part of has passed.
+  fn send(&self, request: Request<()>) -> Result<Response<Box<dyn Readable>>, HttpClientError> {
+    let (parts, ()) = request.into_parts();
+    let url = parts
+      .uri
+      .to_string()
+      .as_str()
+      .try_into()
+      .map_err(|err| HttpClientError::InvalidUrl(Box::new(err)))?;
+    let mut request = BlockingRequest::new(parts.method, url);
+    *request.headers_mut() = parts.headers;
+    if let Some(Deadline(deadline)) = parts.extensions.get::<Deadline>() {
+      *request.timeout_mut() = Some(deadline.saturating_duration_since(Instant::now()));
+    }
+
+    let resp = self
+      .execute(request)
+      .map_err(|err| HttpClientError::Other(Box::new(err)))?;
+
+    let status = resp.status();
+    let headers = resp.headers().clone();
+    let mut response = Response::new(Box::new(resp) as Box<dyn Readable>);
+    *response.status_mut() = status;
+    *response.headers_mut() = headers;
+    Ok(response)
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::io::BufRead as _;
+  use std::io::BufReader;
+  use std::io::Read as _;
+  use std::io::Write as _;
+  use std::net::TcpListener;
+  use std::thread;
+  use std::time::Duration;
+
+  use http::header::USER_AGENT;
+
+
+  /// Check that we send along request headers and report response
+  /// headers.
+  #[test]
+  fn request_headers() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+      let (stream, _addr) = listener.accept().unwrap();
+      let mut reader = BufReader::new(stream);
+      let mut headers = Vec::new();
+      loop {
+        let mut line = String::new();
+        let _count = reader.read_line(&mut line).unwrap();
+        if line.trim().is_empty() {
+          break
+        }
+        let () = headers.push(line.trim().to_ascii_lowercase());
+      }
+
+      let body = b"Debug info!";
+      let mut stream = reader.into_inner();
+      let () = write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nX-DEBUGINFOD-SIZE: 11\r\n\r\n",
+        body.len()
+      )
+      .unwrap();
+      let () = stream.write_all(body).unwrap();
+      headers
+    });
+
+    let request = Request::get(format!("http://{addr}/buildid/00/debuginfo"))
+      .header(USER_AGENT, "debuginfod-rs")
+      .header("X-DEBUGINFOD-MAXSIZE", "1024")
+      .body(())
+      .unwrap();
+    let response = HttpClient::send(&BlockingClient::new(), request).unwrap();
+    assert_eq!(response.headers()["x-debuginfod-size"], "11");
+
+    let mut data = String::new();
+    let _count = response.into_body().read_to_string(&mut data).unwrap();
+    assert_eq!(data, "Debug info!");
+
+    let headers = server.join().unwrap();
+    assert_eq!(headers[0], "get /buildid/00/debuginfo http/1.1");
+    assert!(headers.contains(&"user-agent: debuginfod-rs".to_string()));
+    assert!(headers.contains(&"x-debuginfod-maxsize: 1024".to_string()));
+  }
+
+  /// Make sure that we give up on requests once the deadline of the
+  /// fetch has passed.
+  #[test]
+  fn request_deadline() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    // Accept the connection, but never respond.
+    let _server = thread::spawn(move || listener.accept().unwrap());
+
+    let mut request = Request::get(format!("http://{addr}/buildid/00/debuginfo"))
+      .body(())
+      .unwrap();
+    let deadline = Instant::now() + Duration::from_millis(100);
+    let _prev = request.extensions_mut().insert(Deadline(deadline));
 
-    Ok(Box::new(resp))
+    let now = Instant::now();
+    let _err = HttpClient::send(&BlockingClient::new(), request).unwrap_err();
+    assert!(now.elapsed() < Duration::from_secs(15));
   }
 }
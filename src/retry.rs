@@ -0,0 +1,657 @@
+// Copyright (C) 2026 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+use std::collections::hash_map::RandomState;
+use std::error::Error;
+use std::hash::BuildHasher as _;
+use std::hash::Hasher as _;
+use std::io::Error as IoError;
+use std::io::ErrorKind;
+use std::str::FromStr as _;
+use std::sync::Arc;
+use std::thread::sleep;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+use http::header::RETRY_AFTER;
+use http::HeaderMap;
+use http::Request;
+use http::Response;
+use http::StatusCode;
+use http::Uri;
+
+use httpdate::parse_http_date;
+
+#[cfg(feature = "tokio")]
+use tokio::select;
+#[cfg(feature = "tokio")]
+use tokio::sync::Notify;
+#[cfg(feature = "tokio")]
+use tokio::time::sleep as sleep_async;
+#[cfg(feature = "tokio")]
+use tokio::time::timeout as timeout_async;
+
+use crate::log::debug;
+use crate::transfer::send_with_timeout;
+#[cfg(feature = "tokio")]
+use crate::transfer::timed_out;
+use crate::transfer::Timeouts;
+#[cfg(feature = "tokio")]
+use crate::AsyncHttpClient;
+#[cfg(feature = "tokio")]
+use crate::AsyncReadable;
+use crate::CancellationToken;
+use crate::Cancelled;
+use crate::HttpClient;
+use crate::HttpClientError;
+use crate::Readable;
+
+
+/// The maximum delay we are willing to wait before retrying a request.
+///
+/// If a server asks us to wait longer (via `Retry-After`), we give up
+/// on it instead.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+
+/// A policy describing if and how failed requests are retried.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RetryPolicy {
+  /// The maximum number of times a request is retried.
+  pub limit: u32,
+  /// The base delay used for exponential backoff.
+  pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+  fn default() -> Self {
+    Self {
+      limit: 0,
+      backoff: Duration::from_millis(250),
+    }
+  }
+}
+
+impl RetryPolicy {
+  /// Calculate the delay before the retry with index `attempt`
+  /// (zero-based), including some random jitter.
+  fn delay(&self, attempt: u32) -> Duration {
+    let delay = self
+      .backoff
+      .saturating_mul(2u32.saturating_pow(attempt))
+      .min(MAX_RETRY_DELAY);
+    // Use "equal jitter": half of the delay is fixed, the other half
+    // random.
+    let half = delay / 2;
+    half + half.mul_f64(random_fraction())
+  }
+}
+
+
+/// Generate a pseudo-random number in the range `[0, 1)`.
+fn random_fraction() -> f64 {
+  // `RandomState` is seeded randomly, which is good enough for our
+  // jitter purposes and saves us a dependency.
+  let random = RandomState::new().build_hasher().finish();
+  (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+
+/// Check whether `err`, part of the source chain of a failed request,
+/// indicates a connection problem that may go away on its own.
+fn is_connection_error(err: &(dyn Error + 'static)) -> bool {
+  if let Some(err) = err.downcast_ref::<IoError>() {
+    return matches!(
+      err.kind(),
+      ErrorKind::ConnectionReset
+        | ErrorKind::ConnectionRefused
+        | ErrorKind::ConnectionAborted
+        | ErrorKind::TimedOut
+        | ErrorKind::UnexpectedEof
+    )
+  }
+
+  #[cfg(feature = "reqwest")]
+  if let Some(err) = err.downcast_ref::<reqwest::Error>() {
+    return err.is_connect() || err.is_timeout()
+  }
+
+  false
+}
+
+
+/// Check whether a failed request may succeed when retried.
+fn is_transient(err: &HttpClientError) -> bool {
+  match err {
+    HttpClientError::StatusCode(status) => {
+      status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+    },
+    HttpClientError::InvalidUrl(..) => false,
+    HttpClientError::Other(err) => {
+      let mut transient = false;
+      let mut next = Some(&**err as &(dyn Error + 'static));
+      while let Some(err) = next {
+        if err.is::<Cancelled>() {
+          return false
+        }
+        transient |= is_connection_error(err);
+        // `IoError::source` skips over the error wrapped by an
+        // `IoError`, so we step into it explicitly.
+        next = err
+          .downcast_ref::<IoError>()
+          .and_then(IoError::get_ref)
+          .map(|err| err as &(dyn Error + 'static))
+          .or_else(|| err.source());
+      }
+      transient
+    },
+  }
+}
+
+
+/// Parse the contents of a `Retry-After` header, which could either
+/// be a number of seconds or a date.
+fn parse_retry_after(headers: &HeaderMap, now: SystemTime) -> Option<Duration> {
+  let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+  if let Ok(secs) = u64::from_str(value) {
+    Some(Duration::from_secs(secs))
+  } else {
+    let date = parse_http_date(value).ok()?;
+    Some(date.duration_since(now).unwrap_or_default())
+  }
+}
+
+
+/// Calculate the timeout of a single attempt, given the overall
+/// `deadline`.
+fn attempt_timeout(timeout: Option<Duration>, deadline: Option<Instant>) -> Option<Duration> {
+  match deadline {
+    Some(deadline) => {
+      let remaining = deadline.saturating_duration_since(Instant::now());
+      Some(timeout.map_or(remaining, |timeout| timeout.min(remaining)))
+    },
+    None => timeout,
+  }
+}
+
+
+/// Check the outcome of an attempt, reporting non-success responses
+/// as errors along with the delay requested by the server, if any.
+fn check_response<R>(
+  result: Result<Response<R>, HttpClientError>,
+) -> Result<Response<R>, (HttpClientError, Option<Duration>)> {
+  match result {
+    Ok(response) if response.status().is_success() => Ok(response),
+    Ok(response) => {
+      let retry_after = parse_retry_after(response.headers(), SystemTime::now());
+      Err((HttpClientError::StatusCode(response.status()), retry_after))
+    },
+    Err(err) => Err((err, None)),
+  }
+}
+
+
+/// Determine the delay before retrying the request for `uri` after
+/// attempt `attempt` failed with `err`, if it is to be retried at all.
+fn retry_delay(
+  policy: &RetryPolicy,
+  attempt: u32,
+  err: &HttpClientError,
+  retry_after: Option<Duration>,
+  deadline: Option<Instant>,
+  uri: &Uri,
+) -> Option<Duration> {
+  if attempt >= policy.limit || !is_transient(err) {
+    return None
+  }
+
+  let delay = match retry_after {
+    Some(delay) if delay > MAX_RETRY_DELAY => {
+      debug!("server asked to retry `{uri}` after {delay:?}; giving up");
+      return None
+    },
+    Some(delay) => delay,
+    None => policy.delay(attempt),
+  };
+
+  if let Some(deadline) = deadline {
+    if Instant::now() + delay >= deadline {
+      return None
+    }
+  }
+
+  debug!("request to `{uri}` failed ({err}); retrying in {delay:?}");
+  Some(delay)
+}
+
+
+/// Send `request` using `client`, retrying transient failures as per
+/// `policy`.
+///
+/// Each attempt has to produce a response within the response timeout
+/// of `timeouts`, the body of which is subject to the low speed
+/// timeout. No attempts are made past `deadline` or once `cancel` got
+/// cancelled.
+pub(crate) fn send_with_retry(
+  client: &Arc<dyn HttpClient + Send + Sync>,
+  request: &Request<()>,
+  policy: &RetryPolicy,
+  timeouts: &Timeouts,
+  deadline: Option<Instant>,
+  cancel: Option<&CancellationToken>,
+) -> Result<Response<Box<dyn Readable>>, HttpClientError> {
+  let mut attempt = 0;
+
+  loop {
+    if let Some(cancel) = cancel {
+      let () = cancel
+        .check()
+        .map_err(|err| HttpClientError::Other(Box::new(err)))?;
+    }
+
+    let timeout = attempt_timeout(timeouts.response, deadline);
+    let result = send_with_timeout(
+      client,
+      request.clone(),
+      timeout,
+      timeouts.low_speed,
+      deadline,
+      cancel,
+    );
+    let (err, retry_after) = match check_response(result) {
+      Ok(response) => return Ok(response),
+      Err(err) => err,
+    };
+
+    let delay = match retry_delay(policy, attempt, &err, retry_after, deadline, request.uri()) {
+      Some(delay) => delay,
+      None => return Err(err),
+    };
+
+    if let Some(cancel) = cancel {
+      let () = cancel
+        .sleep(delay)
+        .map_err(|err| HttpClientError::Other(Box::new(err)))?;
+    } else {
+      let () = sleep(delay);
+    }
+    attempt += 1;
+  }
+}
+
+
+/// The asynchronous counterpart to [`send_with_retry`].
+#[cfg(feature = "tokio")]
+pub(crate) async fn send_with_retry_async(
+  client: &dyn AsyncHttpClient,
+  request: &Request<()>,
+  policy: &RetryPolicy,
+  timeout: Option<Duration>,
+  deadline: Option<Instant>,
+  cancel: Option<&CancellationToken>,
+) -> Result<Response<Box<dyn AsyncReadable>>, HttpClientError> {
+  let mut attempt = 0;
+
+  loop {
+    if let Some(cancel) = cancel {
+      let () = cancel
+        .check()
+        .map_err(|err| HttpClientError::Other(Box::new(err)))?;
+    }
+
+    let result = if let Some(timeout) = attempt_timeout(timeout, deadline) {
+      timeout_async(timeout, client.send(request.clone()))
+        .await
+        .unwrap_or_else(|_elapsed| {
+          Err(HttpClientError::Other(Box::new(timed_out(&format!(
+            "request to `{}` timed out after {timeout:?}",
+            request.uri()
+          )))))
+        })
+    } else {
+      client.send(request.clone()).await
+    };
+    let (err, retry_after) = match check_response(result) {
+      Ok(response) => return Ok(response),
+      Err(err) => err,
+    };
+
+    let delay = match retry_delay(policy, attempt, &err, retry_after, deadline, request.uri()) {
+      Some(delay) => delay,
+      None => return Err(err),
+    };
+
+    if let Some(cancel) = cancel {
+      let notify = Arc::new(Notify::new());
+      let notify_clone = Arc::clone(&notify);
+      // `notify_one` stores a permit, so we won't miss a cancellation
+      // happening before we start waiting.
+      let _registration = cancel.on_cancel(move || notify_clone.notify_one());
+      select! {
+        () = sleep_async(delay) => (),
+        () = notify.notified() => {
+          return Err(HttpClientError::Other(Box::new(Cancelled)))
+        },
+      }
+    } else {
+      let () = sleep_async(delay).await;
+    }
+    attempt += 1;
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::sync::atomic::AtomicUsize;
+  use std::sync::atomic::Ordering;
+  use std::thread;
+
+  use http::HeaderValue;
+
+  #[cfg(feature = "tokio")]
+  use crate::BoxFuture;
+
+
+  /// An `HttpClient` replaying a list of canned results.
+  #[derive(Debug)]
+  struct ReplayHttpClient {
+    results: Vec<fn() -> Result<Response<Box<dyn Readable>>, HttpClientError>>,
+    count: AtomicUsize,
+  }
+
+  impl HttpClient for ReplayHttpClient {
+    fn get(&self, _url: &str) -> Result<Response<Box<dyn Readable>>, HttpClientError> {
+      let count = self.count.fetch_add(1, Ordering::Relaxed);
+      (self.results[count])()
+    }
+  }
+
+  /// An `AsyncHttpClient` for which every server is unavailable.
+  #[cfg(feature = "tokio")]
+  #[derive(Debug)]
+  struct UnavailableAsyncHttpClient;
+
+  #[cfg(feature = "tokio")]
+  impl AsyncHttpClient for UnavailableAsyncHttpClient {
+    fn get<'slf>(
+      &'slf self,
+      _url: &'slf str,
+    ) -> BoxFuture<'slf, Result<Response<Box<dyn AsyncReadable>>, HttpClientError>> {
+      Box::pin(async { Err(HttpClientError::StatusCode(StatusCode::SERVICE_UNAVAILABLE)) })
+    }
+  }
+
+  fn send(
+    client: &Arc<ReplayHttpClient>,
+    request: &Request<()>,
+    policy: &RetryPolicy,
+  ) -> Result<Response<Box<dyn Readable>>, HttpClientError> {
+    let client = Arc::clone(client) as Arc<dyn HttpClient + Send + Sync>;
+    send_with_retry(&client, request, policy, &Timeouts::default(), None, None)
+  }
+
+  fn success() -> Result<Response<Box<dyn Readable>>, HttpClientError> {
+    let data: &[u8] = b"Debug info!";
+    Ok(Response::new(Box::new(data)))
+  }
+
+  fn unavailable() -> Result<Response<Box<dyn Readable>>, HttpClientError> {
+    Err(HttpClientError::StatusCode(StatusCode::SERVICE_UNAVAILABLE))
+  }
+
+  fn refused() -> Result<Response<Box<dyn Readable>>, HttpClientError> {
+    Err(HttpClientError::Other(Box::new(IoError::new(
+      ErrorKind::ConnectionRefused,
+      "connection refused",
+    ))))
+  }
+
+  fn bad_certificate() -> Result<Response<Box<dyn Readable>>, HttpClientError> {
+    Err(HttpClientError::Other(Box::new(IoError::new(
+      ErrorKind::InvalidData,
+      "invalid peer certificate: UnknownIssuer",
+    ))))
+  }
+
+  fn cancelled() -> Result<Response<Box<dyn Readable>>, HttpClientError> {
+    Err(HttpClientError::Other(Box::new(IoError::new(
+      ErrorKind::TimedOut,
+      Cancelled,
+    ))))
+  }
+
+  fn not_found() -> Result<Response<Box<dyn Readable>>, HttpClientError> {
+    Err(HttpClientError::StatusCode(StatusCode::NOT_FOUND))
+  }
+
+  fn too_many_requests() -> Result<Response<Box<dyn Readable>>, HttpClientError> {
+    let data: &[u8] = b"";
+    let response = Response::builder()
+      .status(StatusCode::TOO_MANY_REQUESTS)
+      .header(RETRY_AFTER, "0")
+      .body(Box::new(data) as Box<dyn Readable>)
+      .unwrap();
+    Ok(response)
+  }
+
+  fn too_many_requests_long() -> Result<Response<Box<dyn Readable>>, HttpClientError> {
+    let data: &[u8] = b"";
+    let response = Response::builder()
+      .status(StatusCode::TOO_MANY_REQUESTS)
+      .header(RETRY_AFTER, "3600")
+      .body(Box::new(data) as Box<dyn Readable>)
+      .unwrap();
+    Ok(response)
+  }
+
+
+  /// Check that the backoff delay grows exponentially, is capped, and
+  /// stays within the expected jitter bounds.
+  #[test]
+  fn backoff_delay() {
+    let policy = RetryPolicy {
+      limit: 10,
+      backoff: Duration::from_millis(100),
+    };
+
+    for attempt in 0..4 {
+      let max = Duration::from_millis(100 * 2u64.pow(attempt));
+      let delay = policy.delay(attempt);
+      assert!(delay >= max / 2, "{delay:?}");
+      assert!(delay <= max, "{delay:?}");
+    }
+
+    let delay = policy.delay(u32::MAX);
+    assert!(delay <= MAX_RETRY_DELAY, "{delay:?}");
+  }
+
+  /// Check that we can parse the different representations of the
+  /// `Retry-After` header.
+  #[test]
+  fn retry_after_parsing() {
+    let now = parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+    let mut headers = HeaderMap::new();
+    assert_eq!(parse_retry_after(&headers, now), None);
+
+    let _prev = headers.insert(RETRY_AFTER, HeaderValue::from_static("120"));
+    assert_eq!(
+      parse_retry_after(&headers, now),
+      Some(Duration::from_secs(120))
+    );
+
+    let _prev = headers.insert(
+      RETRY_AFTER,
+      HeaderValue::from_static("Wed, 21 Oct 2015 07:29:00 GMT"),
+    );
+    assert_eq!(
+      parse_retry_after(&headers, now),
+      Some(Duration::from_secs(60))
+    );
+
+    // Dates in the past mean "retry right away".
+    let _prev = headers.insert(
+      RETRY_AFTER,
+      HeaderValue::from_static("Wed, 21 Oct 2015 07:27:00 GMT"),
+    );
+    assert_eq!(parse_retry_after(&headers, now), Some(Duration::ZERO));
+
+    let _prev = headers.insert(RETRY_AFTER, HeaderValue::from_static("soon"));
+    assert_eq!(parse_retry_after(&headers, now), None);
+  }
+
+  /// Check that we retry transient failures up to the configured limit.
+  #[test]
+  fn retry_transient_failures() {
+    let policy = RetryPolicy {
+      limit: 3,
+      backoff: Duration::from_millis(1),
+    };
+    let request = Request::get("https://debug.infod/").body(()).unwrap();
+
+    let client = Arc::new(ReplayHttpClient {
+      results: vec![unavailable, refused, too_many_requests, success],
+      count: AtomicUsize::new(0),
+    });
+    let _response = send(&client, &request, &policy).unwrap();
+    assert_eq!(client.count.load(Ordering::Relaxed), 4);
+
+    let client = Arc::new(ReplayHttpClient {
+      results: vec![unavailable, unavailable, unavailable, unavailable],
+      count: AtomicUsize::new(0),
+    });
+    let err = send(&client, &request, &policy).unwrap_err();
+    assert!(matches!(
+      err,
+      HttpClientError::StatusCode(StatusCode::SERVICE_UNAVAILABLE)
+    ));
+    assert_eq!(client.count.load(Ordering::Relaxed), 4);
+  }
+
+  /// Check that we do not retry failures that are not transient.
+  #[test]
+  fn no_retry_permanent_failures() {
+    let policy = RetryPolicy {
+      limit: 3,
+      backoff: Duration::from_millis(1),
+    };
+    let request = Request::get("https://debug.infod/").body(()).unwrap();
+
+    let client = Arc::new(ReplayHttpClient {
+      results: vec![not_found],
+      count: AtomicUsize::new(0),
+    });
+    let err = send(&client, &request, &policy).unwrap_err();
+    assert!(matches!(
+      err,
+      HttpClientError::StatusCode(StatusCode::NOT_FOUND)
+    ));
+    assert_eq!(client.count.load(Ordering::Relaxed), 1);
+
+    // TLS and similar errors won't go away by retrying.
+    let client = Arc::new(ReplayHttpClient {
+      results: vec![bad_certificate, success],
+      count: AtomicUsize::new(0),
+    });
+    let err = send(&client, &request, &policy).unwrap_err();
+    assert!(
+      matches!(&err, HttpClientError::Other(err) if err.to_string().contains("certificate")),
+      "{err}"
+    );
+    assert_eq!(client.count.load(Ordering::Relaxed), 1);
+
+    // Cancellation is never retried, whatever it is wrapped in.
+    let client = Arc::new(ReplayHttpClient {
+      results: vec![cancelled, success],
+      count: AtomicUsize::new(0),
+    });
+    let _err = send(&client, &request, &policy).unwrap_err();
+    assert_eq!(client.count.load(Ordering::Relaxed), 1);
+
+    // We don't wait for an hour to retry.
+    let client = Arc::new(ReplayHttpClient {
+      results: vec![too_many_requests_long],
+      count: AtomicUsize::new(0),
+    });
+    let err = send(&client, &request, &policy).unwrap_err();
+    assert!(matches!(
+      err,
+      HttpClientError::StatusCode(StatusCode::TOO_MANY_REQUESTS)
+    ));
+    assert_eq!(client.count.load(Ordering::Relaxed), 1);
+  }
+
+  /// Check that cancellation interrupts the delay between retries.
+  #[test]
+  fn retry_cancel() {
+    let policy = RetryPolicy {
+      limit: 3,
+      backoff: Duration::from_secs(30),
+    };
+    let request = Request::get("https://debug.infod/").body(()).unwrap();
+    let client = Arc::new(ReplayHttpClient {
+      results: vec![unavailable, success],
+      count: AtomicUsize::new(0),
+    }) as Arc<dyn HttpClient + Send + Sync>;
+    let cancel = CancellationToken::new();
+    let cancel_clone = cancel.clone();
+    let _handle = thread::spawn(move || {
+      let () = sleep(Duration::from_millis(50));
+      let () = cancel_clone.cancel();
+    });
+
+    let now = Instant::now();
+    let err = send_with_retry(
+      &client,
+      &request,
+      &policy,
+      &Timeouts::default(),
+      None,
+      Some(&cancel),
+    )
+    .unwrap_err();
+    assert!(now.elapsed() < Duration::from_secs(15));
+    assert!(
+      matches!(&err, HttpClientError::Other(err) if err.is::<Cancelled>()),
+      "{err}"
+    );
+  }
+
+  /// Check that cancellation interrupts the delay between asynchronous
+  /// retries.
+  #[cfg(feature = "tokio")]
+  #[tokio::test]
+  async fn retry_cancel_async() {
+    let policy = RetryPolicy {
+      limit: 3,
+      backoff: Duration::from_secs(30),
+    };
+    let request = Request::get("https://debug.infod/").body(()).unwrap();
+    let cancel = CancellationToken::new();
+    let cancel_clone = cancel.clone();
+    let _handle = thread::spawn(move || {
+      let () = sleep(Duration::from_millis(50));
+      let () = cancel_clone.cancel();
+    });
+
+    let now = Instant::now();
+    let err = send_with_retry_async(
+      &UnavailableAsyncHttpClient,
+      &request,
+      &policy,
+      None,
+      None,
+      Some(&cancel),
+    )
+    .await
+    .unwrap_err();
+    assert!(now.elapsed() < Duration::from_secs(15));
+    assert!(
+      matches!(&err, HttpClientError::Other(err) if err.is::<Cancelled>()),
+      "{err}"
+    );
+  }
+}
@@ -0,0 +1,417 @@
+// Copyright (C) 2026 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+
+use crate::CancellationToken;
+use crate::Cancelled;
+
+
+/// An error that can be shared with the callers waiting for a call to
+/// conclude.
+pub(crate) trait Share: Sized {
+  /// Create a copy of the error for a waiting caller.
+  ///
+  /// `None` indicates that the error only concerns the caller that
+  /// encountered it (e.g., because it cancelled the call), in which
+  /// case a waiting caller makes the call on its own instead.
+  fn share(&self) -> Option<Self>;
+}
+
+
+/// Create a copy of `result` for a waiting caller, if it can be shared.
+fn share<V, E>(result: &Result<V, E>) -> Option<Result<V, E>>
+where
+  V: Clone,
+  E: Share,
+{
+  match result {
+    Ok(value) => Some(Ok(value.clone())),
+    Err(err) => err.share().map(Err),
+  }
+}
+
+
+/// A call in progress, the result of which is shared with concurrent
+/// callers.
+#[derive(Debug)]
+struct Flight<V, E> {
+  /// The result of the call, once it concluded. `Some(None)` indicates
+  /// that the call was abandoned and its result can't be shared.
+  result: Mutex<Option<Option<Result<V, E>>>>,
+  /// Signaled once the call concluded.
+  done: Condvar,
+}
+
+impl<V, E> Default for Flight<V, E> {
+  fn default() -> Self {
+    Self {
+      result: Mutex::new(None),
+      done: Condvar::new(),
+    }
+  }
+}
+
+impl<V, E> Flight<V, E>
+where
+  V: Clone + Send + 'static,
+  E: Share + Send + 'static,
+{
+  /// Wait for the call to conclude, returning its result if it can be
+  /// shared.
+  ///
+  /// Waiting stops early once `cancel` got cancelled.
+  fn wait(
+    self: &Arc<Self>,
+    cancel: Option<&CancellationToken>,
+  ) -> Result<Option<Result<V, E>>, Cancelled> {
+    let _registration = cancel.map(|cancel| {
+      let flight = Arc::downgrade(self);
+      cancel.on_cancel(move || {
+        if let Some(flight) = flight.upgrade() {
+          // Holding the lock makes sure that the notification can't get
+          // lost between the waiter checking for cancellation and going
+          // to sleep.
+          // SANITY: We never panic while holding the lock.
+          let _result = flight.result.lock().unwrap();
+          let () = flight.done.notify_all();
+        }
+      })
+    });
+
+    let cancelled = || cancel.map_or(false, CancellationToken::is_cancelled);
+    // SANITY: We never panic while holding the lock.
+    let result = self.result.lock().unwrap();
+    // SANITY: We never panic while holding the lock.
+    let result = self
+      .done
+      .wait_while(result, |result| result.is_none() && !cancelled())
+      .unwrap();
+    match &*result {
+      Some(Some(result)) => Ok(share(result)),
+      Some(None) => Ok(None),
+      None => Err(Cancelled),
+    }
+  }
+}
+
+
+/// The leader of a flight, publishing the flight's result when dropped.
+///
+/// Publishing on drop makes sure that waiters are woken up even if the
+/// call panicked.
+struct Leader<'flights, K, V, E>
+where
+  K: Eq + Hash,
+{
+  /// The flights in progress.
+  flights: &'flights Mutex<HashMap<K, Arc<Flight<V, E>>>>,
+  /// The key of the flight we lead.
+  key: K,
+  /// The flight we lead.
+  flight: Arc<Flight<V, E>>,
+  /// The result of the call to share with waiters, if any.
+  result: Option<Result<V, E>>,
+}
+
+impl<K, V, E> Drop for Leader<'_, K, V, E>
+where
+  K: Eq + Hash,
+{
+  fn drop(&mut self) {
+    // SANITY: We never panic while holding the lock.
+    let _flight = self.flights.lock().unwrap().remove(&self.key);
+    // SANITY: We never panic while holding the lock.
+    *self.flight.result.lock().unwrap() = Some(self.result.take());
+    let () = self.flight.done.notify_all();
+  }
+}
+
+
+/// A mechanism for coalescing concurrent calls for the same key into a
+/// single one, the result of which is shared with all callers.
+#[derive(Debug)]
+pub(crate) struct SingleFlight<K, V, E> {
+  /// The flights currently in progress.
+  flights: Mutex<HashMap<K, Arc<Flight<V, E>>>>,
+}
+
+impl<K, V, E> Default for SingleFlight<K, V, E> {
+  fn default() -> Self {
+    Self {
+      flights: Mutex::new(HashMap::new()),
+    }
+  }
+}
+
+impl<K, V, E> SingleFlight<K, V, E>
+where
+  K: Clone + Eq + Hash,
+  V: Clone + Send + 'static,
+  E: Share + From<Cancelled> + Send + 'static,
+{
+  /// Invoke `f`, unless a call for `key` is in progress already, in
+  /// which case we wait for it to conclude and share its result.
+  ///
+  /// Failures are shared just like values, unless they can't be (see
+  /// [`Share::share`]) or the call panicked. In that case the first
+  /// waiter to notice takes over as the leader of a new flight and
+  /// invokes its `f`, while all others wait for that call instead.
+  /// Waiting fails with [`Cancelled`] once `cancel` got cancelled.
+  pub(crate) fn run<F>(&self, key: K, cancel: Option<&CancellationToken>, f: F) -> Result<V, E>
+  where
+    F: FnOnce() -> Result<V, E>,
+  {
+    let flight = loop {
+      // SANITY: We never panic while holding the lock.
+      let mut flights = self.flights.lock().unwrap();
+      match flights.entry(key.clone()) {
+        Entry::Occupied(entry) => {
+          let flight = Arc::clone(entry.get());
+          let () = drop(flights);
+          if let Some(result) = flight.wait(cancel)? {
+            return result
+          }
+          // The leader removed the abandoned flight before waking us
+          // up, so we either find a new one or take the lead ourselves.
+        },
+        Entry::Vacant(entry) => break Arc::clone(entry.insert(Arc::default())),
+      }
+    };
+
+    let mut leader = Leader {
+      flights: &self.flights,
+      key,
+      flight,
+      result: None,
+    };
+    let result = f();
+    leader.result = share(&result);
+    result
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::sync::atomic::AtomicBool;
+  use std::sync::atomic::AtomicUsize;
+  use std::sync::atomic::Ordering;
+  use std::thread;
+  use std::thread::sleep;
+  use std::time::Duration;
+  use std::time::Instant;
+
+
+  /// The error type used by our tests.
+  #[derive(Debug, PartialEq)]
+  enum TestError {
+    Failure,
+    Cancelled,
+  }
+
+  impl From<Cancelled> for TestError {
+    fn from(_cancelled: Cancelled) -> Self {
+      Self::Cancelled
+    }
+  }
+
+  impl Share for TestError {
+    fn share(&self) -> Option<Self> {
+      match self {
+        Self::Failure => Some(Self::Failure),
+        Self::Cancelled => None,
+      }
+    }
+  }
+
+
+  /// Wait until `count` callers are waiting on the flight for `key`.
+  fn wait_for_waiters(single: &SingleFlight<u32, u32, TestError>, key: u32, count: usize) {
+    loop {
+      let waiters = single
+        .flights
+        .lock()
+        .unwrap()
+        .get(&key)
+        // The map and the leader hold one reference each.
+        .map(|flight| Arc::strong_count(flight) - 2)
+        .unwrap();
+      if waiters == count {
+        break
+      }
+      let () = sleep(Duration::from_millis(1));
+    }
+  }
+
+
+  /// Check that concurrent calls for the same key share a single
+  /// invocation.
+  #[test]
+  fn coalescing() {
+    const WAITERS: usize = 4;
+
+    let single = SingleFlight::<u32, u32, TestError>::default();
+    let calls = AtomicUsize::new(0);
+
+    let () = thread::scope(|scope| {
+      let leader = scope.spawn(|| {
+        single.run(1, None, || {
+          let () = wait_for_waiters(&single, 1, WAITERS);
+          let _count = calls.fetch_add(1, Ordering::Relaxed);
+          Ok::<_, TestError>(42)
+        })
+      });
+
+      // Make sure that the leader is in flight before starting the
+      // waiters.
+      while single.flights.lock().unwrap().is_empty() {
+        let () = sleep(Duration::from_millis(1));
+      }
+
+      let waiters = (0..WAITERS)
+        .map(|_| {
+          scope.spawn(|| {
+            single.run(1, None, || {
+              let _count = calls.fetch_add(1, Ordering::Relaxed);
+              Ok::<_, TestError>(0)
+            })
+          })
+        })
+        .collect::<Vec<_>>();
+
+      assert_eq!(leader.join().unwrap(), Ok(42));
+      for waiter in waiters {
+        assert_eq!(waiter.join().unwrap(), Ok(42));
+      }
+    });
+
+    assert_eq!(calls.load(Ordering::Relaxed), 1);
+    assert!(single.flights.lock().unwrap().is_empty());
+  }
+
+  /// Make sure that a failure is shared with all waiters.
+  #[test]
+  fn leader_failure() {
+    const WAITERS: usize = 4;
+
+    let single = SingleFlight::<u32, u32, TestError>::default();
+    let calls = AtomicUsize::new(0);
+
+    let () = thread::scope(|scope| {
+      let leader = scope.spawn(|| {
+        single.run(1, None, || {
+          let () = wait_for_waiters(&single, 1, WAITERS);
+          let _count = calls.fetch_add(1, Ordering::Relaxed);
+          Err(TestError::Failure)
+        })
+      });
+
+      while single.flights.lock().unwrap().is_empty() {
+        let () = sleep(Duration::from_millis(1));
+      }
+
+      let waiters = (0..WAITERS)
+        .map(|_| {
+          scope.spawn(|| {
+            single.run(1, None, || {
+              let _count = calls.fetch_add(1, Ordering::Relaxed);
+              Ok(7)
+            })
+          })
+        })
+        .collect::<Vec<_>>();
+
+      assert_eq!(leader.join().unwrap(), Err(TestError::Failure));
+      for waiter in waiters {
+        assert_eq!(waiter.join().unwrap(), Err(TestError::Failure));
+      }
+    });
+
+    assert_eq!(calls.load(Ordering::Relaxed), 1);
+  }
+
+  /// Make sure that a single waiter takes over should the leader get
+  /// cancelled.
+  #[test]
+  fn leader_cancelled() {
+    const WAITERS: usize = 4;
+
+    let single = SingleFlight::<u32, u32, TestError>::default();
+    let calls = AtomicUsize::new(0);
+
+    let () = thread::scope(|scope| {
+      let leader = scope.spawn(|| {
+        single.run(1, None, || {
+          let () = wait_for_waiters(&single, 1, WAITERS);
+          Err(TestError::Cancelled)
+        })
+      });
+
+      while single.flights.lock().unwrap().is_empty() {
+        let () = sleep(Duration::from_millis(1));
+      }
+
+      let waiters = (0..WAITERS)
+        .map(|_| {
+          scope.spawn(|| {
+            single.run(1, None, || {
+              // All other waiters wait for the new leader.
+              let () = wait_for_waiters(&single, 1, WAITERS - 1);
+              let _count = calls.fetch_add(1, Ordering::Relaxed);
+              Ok::<_, TestError>(7)
+            })
+          })
+        })
+        .collect::<Vec<_>>();
+
+      assert_eq!(leader.join().unwrap(), Err(TestError::Cancelled));
+      for waiter in waiters {
+        assert_eq!(waiter.join().unwrap(), Ok(7));
+      }
+    });
+
+    assert_eq!(calls.load(Ordering::Relaxed), 1);
+  }
+
+  /// Check that waiting for a flight can be cancelled.
+  #[test]
+  fn waiter_cancel() {
+    let single = SingleFlight::<u32, u32, TestError>::default();
+    let cancel = CancellationToken::new();
+    let done = AtomicBool::new(false);
+
+    let () = thread::scope(|scope| {
+      let _leader = scope.spawn(|| {
+        single.run(1, None, || {
+          let () = wait_for_waiters(&single, 1, 1);
+          let () = cancel.cancel();
+          // Keep the flight going until the waiter gave up (or for long
+          // enough to notice that it didn't).
+          let start = Instant::now();
+          while !done.load(Ordering::Relaxed) && start.elapsed() < Duration::from_secs(30) {
+            let () = sleep(Duration::from_millis(1));
+          }
+          Ok::<_, TestError>(42)
+        })
+      });
+
+      while single.flights.lock().unwrap().is_empty() {
+        let () = sleep(Duration::from_millis(1));
+      }
+
+      let now = Instant::now();
+      let result = single.run(1, Some(&cancel), || Ok(0));
+      assert!(now.elapsed() < Duration::from_secs(15));
+      assert_eq!(result, Err(TestError::Cancelled));
+      let () = done.store(true, Ordering::Relaxed);
+    });
+  }
+}
@@ -0,0 +1,913 @@
+// Copyright (C) 2026 Daniel Mueller <deso@posteo.net>
+// SPDX-License-Identifier: (Apache-2.0 OR MIT)
+
+use std::error::Error;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+#[cfg(feature = "tokio")]
+use std::future::Future as _;
+use std::io::Error as IoError;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Result as IoResult;
+use std::mem::take;
+#[cfg(feature = "tokio")]
+use std::pin::Pin;
+use std::sync::mpsc::sync_channel;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::mpsc::SyncSender;
+use std::sync::Arc;
+#[cfg(feature = "tokio")]
+use std::task::Context;
+#[cfg(feature = "tokio")]
+use std::task::Poll;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use http::Request;
+use http::Response;
+use http::Uri;
+
+#[cfg(feature = "tokio")]
+use tokio::io::AsyncRead;
+#[cfg(feature = "tokio")]
+use tokio::io::ReadBuf;
+#[cfg(feature = "tokio")]
+use tokio::time::sleep;
+#[cfg(feature = "tokio")]
+use tokio::time::sleep_until;
+#[cfg(feature = "tokio")]
+use tokio::time::Instant as TokioInstant;
+#[cfg(feature = "tokio")]
+use tokio::time::Sleep;
+
+use crate::cancel::Registration;
+use crate::progress::ProgressReporter;
+use crate::CancellationToken;
+use crate::Cancelled;
+use crate::HttpClient;
+use crate::HttpClientError;
+use crate::Readable;
+
+
+/// The maximum number of bytes a worker reads from a response body at
+/// a time.
+const MAX_WORKER_CHUNK: usize = 64 * 1024;
+
+
+/// Timeouts applying to requests and the transfer of data.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Timeouts {
+  /// The maximum time to wait for a server to respond to a request,
+  /// i.e., until the response headers arrived.
+  pub response: Option<Duration>,
+  /// The maximum time to wait for any progress while transferring
+  /// data.
+  pub low_speed: Option<Duration>,
+  /// The maximum time a fetch may take overall.
+  pub max_time: Option<Duration>,
+}
+
+impl Timeouts {
+  /// Calculate the deadline for a fetch starting now.
+  pub fn deadline(&self) -> Option<Instant> {
+    self
+      .max_time
+      .and_then(|max_time| Instant::now().checked_add(max_time))
+  }
+}
+
+
+/// An error indicating that a response exceeded the configured maximum
+/// size.
+///
+/// Errors reported while reading response data wrap this type in an
+/// [`std::io::Error`], from which it can be retrieved via
+/// [`std::io::Error::get_ref`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SizeLimitExceeded {
+  /// The configured maximum size, in bytes.
+  pub limit: u64,
+  /// The actual size of the response, if known.
+  pub size: Option<u64>,
+}
+
+impl Display for SizeLimitExceeded {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    if let Some(size) = self.size {
+      write!(
+        f,
+        "response size of {size} bytes exceeds maximum of {} bytes",
+        self.limit
+      )
+    } else {
+      write!(f, "response exceeds maximum size of {} bytes", self.limit)
+    }
+  }
+}
+
+impl Error for SizeLimitExceeded {}
+
+
+/// The deadline of the fetch a request is part of.
+///
+/// Attached to requests as an extension, for `HttpClient`
+/// implementations to give up on their own once it has passed.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(not(feature = "reqwest"), allow(dead_code))]
+pub(crate) struct Deadline(pub Instant);
+
+
+pub(crate) fn timed_out(msg: &str) -> IoError {
+  IoError::new(ErrorKind::TimedOut, msg)
+}
+
+fn max_time_exceeded() -> IoError {
+  timed_out("transfer did not complete within maximum time")
+}
+
+fn stalled(duration: Duration) -> IoError {
+  timed_out(&format!("transfer made no progress for {duration:?}"))
+}
+
+fn worker_terminated(uri: &Uri) -> IoError {
+  IoError::new(
+    ErrorKind::Other,
+    format!("request to `{uri}` terminated unexpectedly"),
+  )
+}
+
+
+/// Wait for a message on `receiver`, giving up once `until` has
+/// passed.
+fn recv_until<T>(receiver: &Receiver<T>, until: Option<Instant>) -> Result<T, RecvTimeoutError> {
+  match until {
+    Some(until) => receiver.recv_timeout(until.saturating_duration_since(Instant::now())),
+    None => receiver
+      .recv()
+      .map_err(|_err| RecvTimeoutError::Disconnected),
+  }
+}
+
+
+/// The body of a response that is read on a worker thread.
+///
+/// In contrast to reading the body directly, reads are abandoned once
+/// the transfer stalls, its deadline passes, or it gets cancelled,
+/// even if the `HttpClient` implementation blocks indefinitely.
+#[derive(Debug)]
+struct WorkerBody {
+  /// The URI of the request, for error reporting.
+  uri: Uri,
+  /// The channel for asking the worker to fill the provided buffer.
+  requests: SyncSender<Vec<u8>>,
+  /// The channel over which the worker sends back the buffer,
+  /// truncated to the data read.
+  data: Receiver<IoResult<Vec<u8>>>,
+  /// Whether a read is outstanding.
+  pending: bool,
+  /// Data received from the worker. The buffer is reused for
+  /// subsequent reads.
+  buffer: Vec<u8>,
+  /// The number of bytes of `buffer` consumed already.
+  consumed: usize,
+  /// The maximum time a single read may take.
+  low_speed: Option<Duration>,
+  /// The point in time by which the transfer has to be complete.
+  deadline: Option<Instant>,
+  /// The token used for cancelling the transfer.
+  cancel: Option<CancellationToken>,
+  /// The registration with `cancel` waking us up on cancellation.
+  _registration: Option<Registration>,
+}
+
+impl Read for WorkerBody {
+  fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+    if let Some(cancel) = &self.cancel {
+      let () = cancel
+        .check()
+        .map_err(|err| IoError::new(ErrorKind::Other, err))?;
+    }
+
+    if self.consumed == self.buffer.len() {
+      if buf.is_empty() {
+        return Ok(0)
+      }
+
+      // A read abandoned earlier may still be outstanding, in which
+      // case we continue waiting for its data.
+      if !self.pending {
+        let mut chunk = take(&mut self.buffer);
+        let () = chunk.resize(buf.len().min(MAX_WORKER_CHUNK), 0);
+        self.consumed = 0;
+        let () = self
+          .requests
+          .send(chunk)
+          .map_err(|_err| worker_terminated(&self.uri))?;
+        self.pending = true;
+      }
+
+      let start = Instant::now();
+      let stall = self
+        .low_speed
+        .and_then(|low_speed| start.checked_add(low_speed));
+      let until = match (stall, self.deadline) {
+        (Some(stall), Some(deadline)) => Some(stall.min(deadline)),
+        (stall, deadline) => stall.or(deadline),
+      };
+
+      match recv_until(&self.data, until) {
+        Ok(result) => {
+          self.pending = false;
+          self.buffer = result?;
+        },
+        Err(RecvTimeoutError::Timeout) => {
+          if self
+            .deadline
+            .map_or(false, |deadline| Instant::now() >= deadline)
+          {
+            return Err(max_time_exceeded())
+          } else {
+            return Err(stalled(start.elapsed()))
+          }
+        },
+        Err(RecvTimeoutError::Disconnected) => return Err(worker_terminated(&self.uri)),
+      }
+    }
+
+    let data = &self.buffer[self.consumed..];
+    let count = buf.len().min(data.len());
+    let () = buf[..count].copy_from_slice(&data[..count]);
+    self.consumed += count;
+    Ok(count)
+  }
+}
+
+
+/// Serve requests for filling buffers with data from `body`, until
+/// the other side hangs up.
+fn serve_reads(
+  mut body: Box<dyn Readable>,
+  requests: Receiver<Vec<u8>>,
+  data: Arc<SyncSender<IoResult<Vec<u8>>>>,
+) {
+  while let Ok(mut buf) = requests.recv() {
+    let result = body.read(&mut buf).map(|count| {
+      let () = buf.truncate(count);
+      buf
+    });
+    if data.send(result).is_err() {
+      break
+    }
+  }
+}
+
+
+/// Send `request` using `client`, failing if no response headers
+/// arrived within `timeout` or if `cancel` got cancelled in the
+/// meantime.
+///
+/// If a timeout or `deadline` is set, the request is issued and the
+/// response body read on a worker thread, meaning that no support from
+/// the `HttpClient` implementation is required. Reads of the body fail
+/// once they take longer than `low_speed`, `deadline` passed, or
+/// `cancel` got cancelled. The worker is abandoned in these cases and
+/// exits as soon as the `HttpClient` returns control to it. To that
+/// end, `deadline` is provided to the client as a [`Deadline`] request
+/// extension.
+///
+/// Cancellation alone does not warrant a worker, as abandoned ones
+/// could pile up without a timeout bounding their lifetime. Instead,
+/// `cancel` is provided to the client as a request extension and
+/// checked between reads by [`Transfer`].
+pub(crate) fn send_with_timeout(
+  client: &Arc<dyn HttpClient + Send + Sync>,
+  mut request: Request<()>,
+  timeout: Option<Duration>,
+  low_speed: Option<Duration>,
+  deadline: Option<Instant>,
+  cancel: Option<&CancellationToken>,
+) -> Result<Response<Box<dyn Readable>>, HttpClientError> {
+  if let Some(deadline) = deadline {
+    let _prev = request.extensions_mut().insert(Deadline(deadline));
+  }
+  if let Some(cancel) = cancel {
+    let _prev = request.extensions_mut().insert(cancel.clone());
+  }
+
+  if timeout.is_none() && low_speed.is_none() && deadline.is_none() {
+    return client.send(request)
+  }
+
+  // Besides the worker, the cancellation callback may send a message,
+  // which is why channels have room for two. The callback only holds
+  // weak references to the senders, so that we notice the worker being
+  // gone.
+  let (response_sender, response_receiver) = sync_channel(2);
+  let (request_sender, request_receiver) = sync_channel(1);
+  let (data_sender, data_receiver) = sync_channel(2);
+  let response_sender = Arc::new(response_sender);
+  let data_sender = Arc::new(data_sender);
+  let registration = cancel.map(|cancel| {
+    let response_sender = Arc::downgrade(&response_sender);
+    let data_sender = Arc::downgrade(&data_sender);
+    cancel.on_cancel(move || {
+      if let Some(sender) = response_sender.upgrade() {
+        let _result = sender.try_send(Err(HttpClientError::Other(Box::new(Cancelled))));
+      }
+      if let Some(sender) = data_sender.upgrade() {
+        let _result = sender.try_send(Err(IoError::new(ErrorKind::Other, Cancelled)));
+      }
+    })
+  });
+
+  let client = Arc::clone(client);
+  let uri = request.uri().clone();
+  let _handle = thread::Builder::new()
+    .name("debuginfod-request".to_string())
+    .spawn(move || {
+      let (response, body) = match client.send(request) {
+        Ok(response) => {
+          let (parts, body) = response.into_parts();
+          (Ok(Response::from_parts(parts, ())), Some(body))
+        },
+        Err(err) => (Err(err), None),
+      };
+      // The receiver may be gone if we timed out, in which case the
+      // response is just dropped.
+      if response_sender.send(response).is_ok() {
+        let () = drop(response_sender);
+        if let Some(body) = body {
+          let () = serve_reads(body, request_receiver, data_sender);
+        }
+      }
+    })
+    .map_err(|err| HttpClientError::Other(Box::new(err)))?;
+
+  let until = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+  let response = match recv_until(&response_receiver, until) {
+    Ok(response) => response?,
+    Err(RecvTimeoutError::Timeout) => {
+      // SANITY: We only time out if a timeout was set.
+      let timeout = timeout.unwrap();
+      return Err(HttpClientError::Other(Box::new(timed_out(&format!(
+        "request to `{uri}` timed out after {timeout:?}"
+      )))))
+    },
+    Err(RecvTimeoutError::Disconnected) => {
+      return Err(HttpClientError::Other(Box::new(worker_terminated(&uri))))
+    },
+  };
+
+  let body = WorkerBody {
+    uri,
+    requests: request_sender,
+    data: data_receiver,
+    pending: false,
+    buffer: Vec::new(),
+    consumed: 0,
+    low_speed,
+    deadline,
+    cancel: cancel.cloned(),
+    _registration: registration,
+  };
+  let (parts, ()) = response.into_parts();
+  Ok(Response::from_parts(parts, Box::new(body)))
+}
+
+
+/// State shared between the synchronous and asynchronous flavors of a
+/// transfer.
+#[derive(Debug, Default)]
+struct TransferState {
+  /// The maximum number of bytes that may be transferred.
+  max_size: Option<u64>,
+  /// The number of bytes transferred so far.
+  transferred: u64,
+  /// The reporter informed about the progress of the transfer.
+  progress: Option<ProgressReporter>,
+  /// The token used for cancelling the transfer.
+  cancel: Option<CancellationToken>,
+}
+
+impl TransferState {
+  /// Fail if the transfer got cancelled.
+  fn check_cancelled(&self) -> IoResult<()> {
+    if let Some(cancel) = &self.cancel {
+      // Note that we deliberately do not use `ErrorKind::Interrupted`,
+      // as many consumers just retry the read in that case.
+      let () = cancel
+        .check()
+        .map_err(|err| IoError::new(ErrorKind::Other, err))?;
+    }
+    Ok(())
+  }
+
+  /// Account for `count` bytes having been transferred by a read into
+  /// a non-empty buffer, if `non_empty` is set.
+  fn record(&mut self, count: usize, non_empty: bool) -> IoResult<()> {
+    // SANITY: A single read can't possibly exceed `u64` capacity.
+    self.transferred += u64::try_from(count).unwrap();
+    if let Some(max_size) = self.max_size {
+      if self.transferred > max_size {
+        let err = SizeLimitExceeded {
+          limit: max_size,
+          size: None,
+        };
+        return Err(IoError::new(ErrorKind::Other, err))
+      }
+    }
+
+    if let Some(progress) = &mut self.progress {
+      if count > 0 || non_empty {
+        let () = progress.report(self.transferred, count == 0);
+      }
+    }
+    Ok(())
+  }
+}
+
+
+/// A reader enforcing various constraints on the transfer of data
+/// from an underlying reader.
+///
+/// Timeouts are checked once a read of the underlying reader returned.
+/// Response bodies provided by [`send_with_timeout`] enforce them while
+/// a read is blocked as well.
+#[derive(Debug)]
+pub(crate) struct Transfer<R> {
+  /// The reader from which data is transferred.
+  reader: R,
+  /// The maximum time a single read may take before the transfer is
+  /// considered stalled.
+  low_speed: Option<Duration>,
+  /// The point in time by which the transfer has to be complete.
+  deadline: Option<Instant>,
+  /// Additional state of the transfer.
+  state: TransferState,
+}
+
+impl<R> Transfer<R> {
+  pub fn new(reader: R, timeouts: &Timeouts, deadline: Option<Instant>) -> Self {
+    Self {
+      reader,
+      low_speed: timeouts.low_speed,
+      deadline,
+      state: TransferState::default(),
+    }
+  }
+
+  /// Limit the transfer to at most `max_size` bytes.
+  pub fn max_size(mut self, max_size: Option<u64>) -> Self {
+    self.state.max_size = max_size;
+    self
+  }
+
+  /// Report the progress of the transfer to `progress`.
+  pub fn progress(mut self, progress: Option<ProgressReporter>) -> Self {
+    self.state.progress = progress;
+    self
+  }
+
+  /// Abort the transfer once `cancel` got cancelled.
+  pub fn cancel(mut self, cancel: Option<CancellationToken>) -> Self {
+    self.state.cancel = cancel;
+    self
+  }
+}
+
+impl<R> Read for Transfer<R>
+where
+  R: Read,
+{
+  fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+    let () = self.state.check_cancelled()?;
+
+    let start = Instant::now();
+    if let Some(deadline) = self.deadline {
+      if start >= deadline {
+        return Err(max_time_exceeded())
+      }
+    }
+
+    let count = self.reader.read(buf)?;
+
+    let now = Instant::now();
+    if let Some(deadline) = self.deadline {
+      if now > deadline {
+        return Err(max_time_exceeded())
+      }
+    }
+
+    if let Some(low_speed) = self.low_speed {
+      let elapsed = now.saturating_duration_since(start);
+      if elapsed > low_speed {
+        return Err(stalled(elapsed))
+      }
+    }
+
+    let () = self.state.record(count, !buf.is_empty())?;
+    Ok(count)
+  }
+}
+
+
+/// The asynchronous counterpart to [`Transfer`].
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub(crate) struct AsyncTransfer<R> {
+  /// The reader from which data is transferred.
+  reader: R,
+  /// The maximum time the transfer may make no progress before it is
+  /// considered stalled.
+  low_speed: Option<Duration>,
+  /// The timer firing once the transfer is considered stalled.
+  stall: Option<Pin<Box<Sleep>>>,
+  /// Whether the last read is still pending.
+  pending: bool,
+  /// The timer firing once the transfer has to be complete.
+  deadline: Option<Pin<Box<Sleep>>>,
+  /// Additional state of the transfer.
+  state: TransferState,
+}
+
+#[cfg(feature = "tokio")]
+impl<R> AsyncTransfer<R> {
+  /// Create a new `AsyncTransfer` object.
+  ///
+  /// This function has to be called from within the context of a
+  /// `tokio` runtime with timers enabled, if any timeouts are set.
+  pub fn new(reader: R, timeouts: &Timeouts, deadline: Option<Instant>) -> Self {
+    let low_speed = timeouts.low_speed;
+    Self {
+      reader,
+      low_speed,
+      stall: low_speed.map(|low_speed| Box::pin(sleep(low_speed))),
+      pending: false,
+      deadline: deadline.map(|deadline| Box::pin(sleep_until(TokioInstant::from_std(deadline)))),
+      state: TransferState::default(),
+    }
+  }
+
+  /// Limit the transfer to at most `max_size` bytes.
+  pub fn max_size(mut self, max_size: Option<u64>) -> Self {
+    self.state.max_size = max_size;
+    self
+  }
+
+  /// Report the progress of the transfer to `progress`.
+  pub fn progress(mut self, progress: Option<ProgressReporter>) -> Self {
+    self.state.progress = progress;
+    self
+  }
+
+  /// Abort the transfer once `cancel` got cancelled.
+  pub fn cancel(mut self, cancel: Option<CancellationToken>) -> Self {
+    self.state.cancel = cancel;
+    self
+  }
+}
+
+#[cfg(feature = "tokio")]
+impl<R> AsyncRead for AsyncTransfer<R>
+where
+  R: AsyncRead + Unpin,
+{
+  fn poll_read(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<IoResult<()>> {
+    let slf = &mut *self;
+    let () = slf.state.check_cancelled()?;
+
+    if let Some(deadline) = &mut slf.deadline {
+      if deadline.as_mut().poll(cx).is_ready() {
+        return Poll::Ready(Err(max_time_exceeded()))
+      }
+    }
+
+    if let (Some(stall), Some(low_speed)) = (&mut slf.stall, slf.low_speed) {
+      // Similar to the synchronous case, we only measure the time a
+      // read is pending, not the time between reads.
+      if !slf.pending {
+        let () = stall.as_mut().reset(TokioInstant::now() + low_speed);
+      }
+    }
+
+    let non_empty = buf.remaining() > 0;
+    let filled = buf.filled().len();
+    match Pin::new(&mut slf.reader).poll_read(cx, buf) {
+      Poll::Ready(result) => {
+        slf.pending = false;
+        let () = result?;
+      },
+      Poll::Pending => {
+        slf.pending = true;
+        if let (Some(stall), Some(low_speed)) = (&mut slf.stall, slf.low_speed) {
+          if stall.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(Err(stalled(low_speed)))
+          }
+        }
+        return Poll::Pending
+      },
+    }
+
+    let count = buf.filled().len() - filled;
+    let () = slf.state.record(count, non_empty)?;
+    Poll::Ready(Ok(()))
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::io::copy;
+  use std::io::repeat;
+  use std::io::sink;
+  use std::thread::sleep;
+
+  use http::StatusCode;
+
+  use crate::Cancelled;
+
+
+  /// A reader that is slow to produce data.
+  #[derive(Debug)]
+  struct SlowReader {
+    delay: Duration,
+    remaining: usize,
+  }
+
+  impl Read for SlowReader {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+      if self.remaining == 0 || buf.is_empty() {
+        return Ok(0)
+      }
+      let () = sleep(self.delay);
+      self.remaining -= 1;
+      buf[0] = 42;
+      Ok(1)
+    }
+  }
+
+  #[derive(Debug)]
+  struct HangingHttpClient;
+
+  impl HttpClient for HangingHttpClient {
+    fn get(&self, _url: &str) -> Result<Response<Box<dyn Readable>>, HttpClientError> {
+      let () = sleep(Duration::from_secs(30));
+      Err(HttpClientError::StatusCode(StatusCode::NOT_FOUND))
+    }
+  }
+
+  /// An `HttpClient` responding with a large body.
+  #[derive(Debug)]
+  struct LargeHttpClient;
+
+  impl HttpClient for LargeHttpClient {
+    fn get(&self, _url: &str) -> Result<Response<Box<dyn Readable>>, HttpClientError> {
+      let reader = repeat(42).take(3 * MAX_WORKER_CHUNK as u64 + 1);
+      Ok(Response::new(Box::new(reader)))
+    }
+  }
+
+  /// An `HttpClient` responding right away, but then stalling after
+  /// the first byte of data.
+  #[derive(Debug)]
+  struct StallingHttpClient;
+
+  impl HttpClient for StallingHttpClient {
+    fn get(&self, _url: &str) -> Result<Response<Box<dyn Readable>>, HttpClientError> {
+      let data: &[u8] = b"D";
+      let reader = data.chain(SlowReader {
+        delay: Duration::from_secs(30),
+        remaining: 1,
+      });
+      Ok(Response::new(Box::new(reader)))
+    }
+  }
+
+
+  /// Check that a transfer without timeouts is transparent.
+  #[test]
+  fn transfer_without_timeouts() {
+    let reader = SlowReader {
+      delay: Duration::from_millis(1),
+      remaining: 5,
+    };
+    let mut transfer = Transfer::new(reader, &Timeouts::default(), None);
+    let mut data = Vec::new();
+    let count = transfer.read_to_end(&mut data).unwrap();
+    assert_eq!(count, 5);
+    assert_eq!(data, [42; 5]);
+  }
+
+  /// Make sure that stalled transfers are detected.
+  #[test]
+  fn transfer_low_speed() {
+    let reader = SlowReader {
+      delay: Duration::from_millis(200),
+      remaining: 5,
+    };
+    let timeouts = Timeouts {
+      low_speed: Some(Duration::from_millis(50)),
+      ..Default::default()
+    };
+    let mut transfer = Transfer::new(reader, &timeouts, None);
+    let err = copy(&mut transfer, &mut sink()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+  }
+
+  /// Make sure that transfers exceeding the maximum time are aborted.
+  #[test]
+  fn transfer_max_time() {
+    let reader = SlowReader {
+      delay: Duration::from_millis(20),
+      remaining: usize::MAX,
+    };
+    let timeouts = Timeouts {
+      low_speed: Some(Duration::from_secs(10)),
+      max_time: Some(Duration::from_millis(100)),
+      ..Default::default()
+    };
+    let mut transfer = Transfer::new(reader, &timeouts, timeouts.deadline());
+    let err = copy(&mut transfer, &mut sink()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+  }
+
+  /// Make sure that transfers exceeding the maximum size are aborted.
+  #[test]
+  fn transfer_max_size() {
+    let data = [42u8; 64];
+    let mut transfer = Transfer::new(&data[..], &Timeouts::default(), None).max_size(Some(64));
+    let count = copy(&mut transfer, &mut sink()).unwrap();
+    assert_eq!(count, 64);
+
+    let mut transfer = Transfer::new(&data[..], &Timeouts::default(), None).max_size(Some(63));
+    let err = copy(&mut transfer, &mut sink()).unwrap_err();
+    let err = err
+      .get_ref()
+      .unwrap()
+      .downcast_ref::<SizeLimitExceeded>()
+      .unwrap();
+    assert_eq!(
+      err,
+      &SizeLimitExceeded {
+        limit: 63,
+        size: None
+      }
+    );
+  }
+
+  /// Make sure that stalled transfers are detected while a read is
+  /// blocked.
+  #[test]
+  fn transfer_low_speed_blocked() {
+    let client = Arc::new(StallingHttpClient) as Arc<dyn HttpClient + Send + Sync>;
+    let request = Request::get("https://debug.infod/").body(()).unwrap();
+    let timeouts = Timeouts {
+      low_speed: Some(Duration::from_millis(50)),
+      ..Default::default()
+    };
+    let response =
+      send_with_timeout(&client, request, None, timeouts.low_speed, None, None).unwrap();
+    let mut transfer = Transfer::new(response.into_body(), &timeouts, None);
+
+    let now = Instant::now();
+    let mut data = [0; 4];
+    let () = transfer.read_exact(&mut data[..1]).unwrap();
+    let err = transfer.read_exact(&mut data).unwrap_err();
+    assert!(now.elapsed() < Duration::from_secs(15));
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+  }
+
+  /// Check that data read on a worker thread is transferred in chunks
+  /// of bounded size.
+  #[test]
+  fn worker_chunks() {
+    let client = Arc::new(LargeHttpClient) as Arc<dyn HttpClient + Send + Sync>;
+    let request = Request::get("https://debug.infod/").body(()).unwrap();
+    let low_speed = Some(Duration::from_secs(30));
+    let response = send_with_timeout(&client, request, None, low_speed, None, None).unwrap();
+    let mut body = response.into_body();
+
+    let mut buf = vec![0; 4 * MAX_WORKER_CHUNK];
+    let mut total = 0;
+    loop {
+      let count = body.read(&mut buf).unwrap();
+      assert!(count <= MAX_WORKER_CHUNK, "{count}");
+      assert!(buf[..count].iter().all(|b| *b == 42));
+      if count == 0 {
+        break
+      }
+      total += count;
+    }
+    assert_eq!(total, 3 * MAX_WORKER_CHUNK + 1);
+  }
+
+  /// Make sure that transfers exceeding the maximum time are aborted
+  /// while a read is blocked.
+  #[test]
+  fn transfer_max_time_blocked() {
+    let client = Arc::new(StallingHttpClient) as Arc<dyn HttpClient + Send + Sync>;
+    let request = Request::get("https://debug.infod/").body(()).unwrap();
+    let timeouts = Timeouts {
+      max_time: Some(Duration::from_millis(100)),
+      ..Default::default()
+    };
+    let deadline = timeouts.deadline();
+    let response = send_with_timeout(&client, request, None, None, deadline, None).unwrap();
+    let mut transfer = Transfer::new(response.into_body(), &timeouts, deadline);
+
+    let now = Instant::now();
+    let err = copy(&mut transfer, &mut sink()).unwrap_err();
+    assert!(now.elapsed() < Duration::from_secs(15));
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    assert!(err.to_string().contains("maximum time"), "{err}");
+  }
+
+  /// Check that we time out requests to unresponsive servers.
+  #[test]
+  fn request_timeout() {
+    let client = Arc::new(HangingHttpClient) as Arc<dyn HttpClient + Send + Sync>;
+    let request = Request::get("https://debug.infod/").body(()).unwrap();
+    let now = Instant::now();
+    let timeout = Some(Duration::from_millis(50));
+    let err = send_with_timeout(&client, request, timeout, None, None, None).unwrap_err();
+    assert!(now.elapsed() < Duration::from_secs(15));
+    assert!(err.to_string().contains("timed out"), "{err}");
+  }
+
+  /// Check that we stop waiting for a response once cancelled.
+  #[test]
+  fn request_cancel() {
+    let client = Arc::new(HangingHttpClient) as Arc<dyn HttpClient + Send + Sync>;
+    let request = Request::get("https://debug.infod/").body(()).unwrap();
+    let cancel = CancellationToken::new();
+    let cancel_clone = cancel.clone();
+    let _handle = thread::spawn(move || {
+      let () = sleep(Duration::from_millis(50));
+      let () = cancel_clone.cancel();
+    });
+
+    let now = Instant::now();
+    let timeout = Some(Duration::from_secs(60));
+    let err = send_with_timeout(&client, request, timeout, None, None, Some(&cancel)).unwrap_err();
+    assert!(now.elapsed() < Duration::from_secs(15));
+    assert!(
+      matches!(&err, HttpClientError::Other(err) if err.is::<Cancelled>()),
+      "{err}"
+    );
+  }
+
+  /// Check that requests that can only get cancelled are issued on the
+  /// calling thread, with the token provided as an extension.
+  #[test]
+  fn request_cancel_no_worker() {
+    #[derive(Debug)]
+    struct CheckingHttpClient;
+
+    impl HttpClient for CheckingHttpClient {
+      fn get(&self, _url: &str) -> Result<Response<Box<dyn Readable>>, HttpClientError> {
+        unreachable!()
+      }
+
+      fn send(&self, request: Request<()>) -> Result<Response<Box<dyn Readable>>, HttpClientError> {
+        assert_ne!(thread::current().name(), Some("debuginfod-request"));
+        let cancel = request.extensions().get::<CancellationToken>().unwrap();
+        assert!(cancel.is_cancelled());
+        Err(HttpClientError::Other(Box::new(Cancelled)))
+      }
+    }
+
+    let client = Arc::new(CheckingHttpClient) as Arc<dyn HttpClient + Send + Sync>;
+    let request = Request::get("https://debug.infod/").body(()).unwrap();
+    let cancel = CancellationToken::new();
+    let () = cancel.cancel();
+    let _err = send_with_timeout(&client, request, None, None, None, Some(&cancel)).unwrap_err();
+  }
+
+  /// Make sure that cancelled transfers are aborted.
+  #[test]
+  fn transfer_cancel() {
+    let reader = SlowReader {
+      delay: Duration::from_millis(1),
+      remaining: usize::MAX,
+    };
+    let cancel = CancellationToken::new();
+    let mut transfer =
+      Transfer::new(reader, &Timeouts::default(), None).cancel(Some(cancel.clone()));
+    let mut data = [0; 4];
+    let () = transfer.read_exact(&mut data).unwrap();
+
+    let () = cancel.cancel();
+    let err = copy(&mut transfer, &mut sink()).unwrap_err();
+    assert!(err.get_ref().unwrap().is::<Cancelled>());
+  }
+}
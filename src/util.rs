@@ -1,6 +1,18 @@
 // Copyright (C) 2024-2025 Daniel Mueller <deso@posteo.net>
 // SPDX-License-Identifier: (Apache-2.0 OR MIT)
 
+use std::error::Error as StdError;
+use std::ffi::OsStr;
+use std::str::FromStr;
+
+use http::HeaderMap;
+use http::HeaderName;
+use http::HeaderValue;
+
+use crate::error::Result;
+use crate::log::warn;
+use crate::Error;
+
 
 pub(crate) fn format_build_id(build_id: &[u8]) -> String {
   build_id
@@ -12,6 +24,19 @@ pub(crate) fn format_build_id(build_id: &[u8]) -> String {
 }
 
 
+/// Parse the value of the environment variable `name`.
+pub(crate) fn parse_env_var<T>(name: &str, value: &OsStr) -> Result<T>
+where
+  T: FromStr,
+  T::Err: StdError + Send + Sync + 'static,
+{
+  let value = value
+    .to_str()
+    .ok_or_else(|| Error::invalid_env(name, Some("value does not contain valid Unicode")))?;
+  T::from_str(value.trim()).map_err(|err| Error::invalid_env(name, Some(err)))
+}
+
+
 pub(crate) fn split_env_var_contents(urls_str: &str) -> impl Iterator<Item = &str> {
   urls_str
     .split([',', ' '])
@@ -21,6 +46,32 @@ pub(crate) fn split_env_var_contents(urls_str: &str) -> impl Iterator<Item = &st
 }
 
 
+/// Parse HTTP headers from `contents`, expected to contain one
+/// `Name: value` pair per line.
+///
+/// Lines that cannot be parsed are ignored.
+pub(crate) fn parse_header_lines(contents: &str) -> HeaderMap {
+  contents
+    .lines()
+    .filter(|line| !line.trim().is_empty())
+    .filter_map(|line| {
+      let header = line.split_once(':').and_then(|(name, value)| {
+        let name = HeaderName::from_bytes(name.trim().as_bytes()).ok()?;
+        let value = HeaderValue::from_str(value.trim()).ok()?;
+        Some((name, value))
+      });
+      if header.is_none() {
+        warn!("ignoring malformed header line `{line}`");
+      }
+      header
+    })
+    .fold(HeaderMap::new(), |mut headers, (name, value)| {
+      let _existed = headers.append(name, value);
+      headers
+    })
+}
+
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -65,4 +116,23 @@ mod tests {
     let urls = split_env_var_contents("").collect::<Vec<_>>();
     assert_eq!(urls, Vec::<&str>::new());
   }
+
+  /// Check that we can parse headers from a list of lines.
+  #[test]
+  fn header_line_parsing() {
+    let contents = r#"
+Authorization: Bearer 1234
+X-Custom:value:with:colons
+malformed line
+In valid: header
+X-Custom: another
+"#;
+    let headers = parse_header_lines(contents);
+    assert_eq!(headers.len(), 3);
+    assert_eq!(headers["authorization"], "Bearer 1234");
+    assert_eq!(
+      headers.get_all("x-custom").iter().collect::<Vec<_>>(),
+      ["value:with:colons", "another"]
+    );
+  }
 }